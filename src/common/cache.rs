@@ -0,0 +1,274 @@
+use crate::common::task::md5_str;
+use crate::common::CheckDataStatus::{Success, Unchecked};
+use crate::common::{CheckOptions, M3uObject, OtherStatus};
+use crate::r#const::constant::CHECK_CACHE_FILE;
+use log::error;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, RwLock};
+
+/// 缓存记录最短保留时间（秒），清理时不会删除更新的记录，避免删掉其它有效期更长的任务仍在使用的结果
+const CACHE_MIN_RETENTION: i64 = 7 * 24 * 3600;
+
+/// 一个URL的缓存检查结果
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CacheEntry {
+    pub time: i64, // 检查时间（秒）
+    pub success: bool,
+    pub other_status: OtherStatus,
+}
+
+impl CacheEntry {
+    /// 根据检查后的源生成缓存，未检查的源返回None
+    pub fn from_object(item: &M3uObject, time: i64) -> Option<CacheEntry> {
+        if item.get_status() == Unchecked {
+            return None;
+        }
+        Some(CacheEntry {
+            time,
+            success: item.get_status() == Success,
+            other_status: item.get_other_status().clone(),
+        })
+    }
+}
+
+/// 按URL和检查参数缓存的检查结果，CLI、定时任务和 `/check/url-is-available` 共用
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CheckCache {
+    entries: HashMap<String, CacheEntry>,
+}
+
+/// 缓存有效期（秒），为0时对应结果不使用缓存
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct CacheTtl {
+    pub success: i64,
+    pub failure: i64,
+}
+
+impl CacheTtl {
+    pub fn new(success: i64, failure: i64) -> CacheTtl {
+        CacheTtl { success, failure }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.success > 0 || self.failure > 0
+    }
+}
+
+impl CheckCache {
+    /// 读取缓存文件，文件不存在或无法解析时为空
+    pub fn load(path: &str) -> CheckCache {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<CheckCache>(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// 保存缓存文件，先与文件中已有的缓存合并，避免覆盖其他进程写入的较新结果
+    pub fn save(&mut self, path: &str) -> io::Result<()> {
+        self.merge(CheckCache::load(path));
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)
+    }
+
+    /// 合并另一份缓存，同一key保留较新的结果
+    pub fn merge(&mut self, other: CheckCache) {
+        for (key, entry) in other.entries {
+            match self.entries.get(&key) {
+                Some(e) if e.time >= entry.time => {}
+                _ => {
+                    self.entries.insert(key, entry);
+                }
+            }
+        }
+    }
+
+    pub fn put(&mut self, key: String, entry: CacheEntry) {
+        self.entries.insert(key, entry);
+    }
+
+    /// 获取未过期的缓存结果
+    ///
+    /// # 参数
+    /// * `key` - 由 `cache_key` 生成的缓存key
+    /// * `ttl` - 成功和失败结果的有效期
+    /// * `now` - 当前时间（秒）
+    pub fn get_fresh(&self, key: &str, ttl: CacheTtl, now: i64) -> Option<&CacheEntry> {
+        self.entries.get(key).filter(|e| {
+            let ttl = if e.success { ttl.success } else { ttl.failure };
+            ttl > 0 && now - e.time <= ttl
+        })
+    }
+
+    /// 删除过期的记录，避免缓存文件无限增长
+    ///
+    /// 其它任务的有效期可能更长，只删除超过当前有效期且超过 `CACHE_MIN_RETENTION` 的记录
+    pub fn prune(&mut self, ttl: CacheTtl, now: i64) {
+        let retention = ttl.success.max(ttl.failure).max(CACHE_MIN_RETENTION);
+        self.entries.retain(|_, e| now - e.time <= retention);
+    }
+}
+
+/// 生成缓存key，由URL和影响检查结果的参数的哈希组成，参数不同的检查结果互不复用
+///
+/// # 参数
+/// * `url` - 源地址
+/// * `headers` - 请求头
+/// * `opt` - 检查参数
+pub fn cache_key(url: &str, headers: &BTreeMap<String, String>, opt: &CheckOptions) -> String {
+    let proxy = opt
+        .proxy
+        .as_ref()
+        .map(|p| format!("{}@{}", p.username, p.display_url()))
+        .unwrap_or_default();
+    let params = format!(
+        "deep={} ffmpeg={} liveness={} timing={} throughput={} content={} fingerprint={} family={} not_http_skip={} headers={:?} proxy={}",
+        opt.deep_check,
+        opt.ffmpeg_check,
+        opt.liveness_check,
+        opt.timing_check,
+        opt.throughput_segments,
+        opt.content_check,
+        opt.fingerprint_check,
+        opt.family_check,
+        opt.not_http_skip,
+        headers,
+        proxy
+    );
+    format!("{}#{}", url, md5_str(params))
+}
+
+static CHECK_CACHE: Lazy<RwLock<CheckCache>> =
+    Lazy::new(|| RwLock::new(CheckCache::load(CHECK_CACHE_FILE)));
+
+/// 同一时间只有一个线程读写缓存文件
+static CACHE_FILE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// 获取未过期的缓存结果
+///
+/// # 参数
+/// * `key` - 由 `cache_key` 生成的缓存key
+/// * `ttl` - 成功和失败结果的有效期
+/// * `now` - 当前时间（秒）
+pub fn get_cached(key: &str, ttl: CacheTtl, now: i64) -> Option<CacheEntry> {
+    CHECK_CACHE
+        .read()
+        .unwrap()
+        .get_fresh(key, ttl, now)
+        .cloned()
+}
+
+/// 写入检查结果并保存缓存文件，文件读写在阻塞线程中进行，不持有内存缓存的锁
+///
+/// # 参数
+/// * `entries` - 缓存key和对应的检查结果
+/// * `ttl` - 缓存有效期，用于清理过期记录
+/// * `now` - 当前时间（秒）
+pub async fn save_cached(entries: Vec<(String, CacheEntry)>, ttl: CacheTtl, now: i64) {
+    let mut snapshot = {
+        let mut cache = CHECK_CACHE.write().unwrap();
+        for (key, entry) in entries {
+            cache.put(key, entry);
+        }
+        cache.prune(ttl, now);
+        cache.clone()
+    };
+    let saved = tokio::task::spawn_blocking(move || {
+        let _guard = CACHE_FILE_LOCK.lock().unwrap();
+        snapshot.save(CHECK_CACHE_FILE)
+    })
+    .await;
+    match saved {
+        Ok(Err(e)) => error!("save check cache error: {}", e),
+        Err(e) => error!("save check cache error: {}", e),
+        Ok(Ok(())) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(time: i64, success: bool) -> CacheEntry {
+        CacheEntry {
+            time,
+            success,
+            other_status: OtherStatus::new(),
+        }
+    }
+
+    #[test]
+    fn cache_ttl_and_merge() {
+        let ttl = CacheTtl::new(600, 60);
+        let mut cache = CheckCache::default();
+        cache.put("ok".to_string(), entry(1000, true));
+        cache.put("fail".to_string(), entry(1000, false));
+        assert!(cache.get_fresh("ok", ttl, 1500).is_some());
+        assert!(cache.get_fresh("fail", ttl, 1050).is_some());
+        assert!(cache.get_fresh("fail", ttl, 1100).is_none());
+        assert!(cache.get_fresh("ok", CacheTtl::new(0, 60), 1001).is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.json");
+        let path = path.to_str().unwrap();
+        let mut other = CheckCache::default();
+        other.put("ok".to_string(), entry(2000, false));
+        other.put("new".to_string(), entry(2000, true));
+        other.save(path).unwrap();
+        cache.save(path).unwrap();
+        let loaded = CheckCache::load(path);
+        assert!(!loaded.get_fresh("ok", ttl, 2010).unwrap().success);
+        assert!(loaded.get_fresh("new", ttl, 2010).is_some());
+
+        // 有效期更长的任务仍可以使用，超过最短保留时间后才删除
+        cache.prune(ttl, 1700);
+        assert!(cache
+            .get_fresh("fail", CacheTtl::new(1000, 1000), 1700)
+            .is_some());
+        cache.prune(ttl, 1001 + CACHE_MIN_RETENTION);
+        assert!(cache
+            .get_fresh(
+                "fail",
+                CacheTtl::new(i64::MAX, i64::MAX),
+                1001 + CACHE_MIN_RETENTION
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn cache_key_depends_on_options() {
+        let opt = CheckOptions {
+            request_time: 3000,
+            concurrent: 1,
+            ..Default::default()
+        };
+        let url = "http://a.com/1.m3u8";
+        let headers = BTreeMap::new();
+        let plain = cache_key(url, &headers, &opt);
+        assert!(plain.starts_with(url));
+        // 超时时间和并发数不影响结果
+        let mut other = opt.clone();
+        other.request_time = 5000;
+        other.concurrent = 8;
+        assert_eq!(cache_key(url, &headers, &other), plain);
+
+        let mut ffmpeg = opt.clone();
+        ffmpeg.ffmpeg_check = true;
+        ffmpeg.timing_check = true;
+        assert_ne!(cache_key(url, &headers, &ffmpeg), plain);
+        let mut ua = BTreeMap::new();
+        ua.insert("User-Agent".to_string(), "iptv".to_string());
+        assert_ne!(cache_key(url, &ua, &opt), plain);
+        let mut proxied = opt.clone();
+        proxied.proxy = Some(crate::common::proxy::ProxyConfig::new(
+            "http://10.0.0.1:3128".to_string(),
+        ));
+        assert_ne!(cache_key(url, &headers, &proxied), plain);
+    }
+}
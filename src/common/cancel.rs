@@ -0,0 +1,86 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use tokio::sync::watch;
+
+/// 任务取消信号
+#[derive(Clone)]
+pub struct CancelToken {
+    rx: watch::Receiver<bool>,
+}
+
+impl CancelToken {
+    pub fn is_cancelled(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// 等待任务被取消
+    pub async fn cancelled(&mut self) {
+        // 发送端被删除时任务已经结束，不会再取消
+        if self.rx.wait_for(|c| *c).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// 正在运行的任务的取消信号
+static CANCEL_MAP: Lazy<RwLock<HashMap<String, watch::Sender<bool>>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// 任务开始运行时注册取消信号
+pub fn register_cancel(task_id: &str) -> CancelToken {
+    let (sender, rx) = watch::channel(false);
+    CANCEL_MAP
+        .write()
+        .unwrap()
+        .insert(task_id.to_string(), sender);
+    CancelToken { rx }
+}
+
+/// 任务结束后删除取消信号
+pub fn remove_cancel(task_id: &str) {
+    CANCEL_MAP.write().unwrap().remove(task_id);
+}
+
+/// 获取正在运行的任务的取消信号
+pub fn get_cancel_token(task_id: &str) -> Option<CancelToken> {
+    CANCEL_MAP
+        .read()
+        .unwrap()
+        .get(task_id)
+        .map(|s| CancelToken { rx: s.subscribe() })
+}
+
+/// 取消正在运行的任务
+///
+/// # 返回值
+/// * `bool` - 任务没有在运行时返回false
+pub fn cancel_task(task_id: &str) -> bool {
+    match CANCEL_MAP.read().unwrap().get(task_id) {
+        Some(sender) => {
+            sender.send_replace(true);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cancel_running_task() {
+        assert!(!cancel_task("cancel_running_task"));
+        let token = register_cancel("cancel_running_task");
+        let mut waiting = get_cancel_token("cancel_running_task").unwrap();
+        assert!(!token.is_cancelled());
+        assert!(cancel_task("cancel_running_task"));
+        tokio::time::timeout(std::time::Duration::from_secs(1), waiting.cancelled())
+            .await
+            .unwrap();
+        assert!(token.is_cancelled());
+        remove_cancel("cancel_running_task");
+        assert!(get_cancel_token("cancel_running_task").is_none());
+    }
+}
@@ -1,13 +1,155 @@
 use crate::common;
+use crate::common::fingerprint::Fingerprint;
+use crate::common::hls::{HlsCheckStage, HlsEncryption, LiveStatus};
 use crate::common::m3u::m3u::list_str2obj;
-use crate::common::util::from_video_resolution;
+use crate::common::redirect::RedirectInfo;
+use crate::common::timing::TimingInfo;
 use crate::common::{AudioInfo, CheckOptions, SearchOptions, VideoInfo};
 use crate::config::favourite::get_favourite_list;
 use crate::r#const::constant::{INPUT_SEARCH_FOLDER, OUTPUT_FOLDER};
 use log::info;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fmt::Error;
 
+/// 检查失败原因
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FailureReason {
+    InvalidUrl,        // url格式错误
+    UnsupportedScheme, // 不支持的协议
+    Dns,               // 域名解析失败
+    Connect,           // 连接失败
+    Tls,               // TLS握手失败
+    Timeout,           // 超时
+    Handshake,         // RTSP/RTMP等协议握手失败
+    HttpStatus,        // http状态码非2xx
+    Body,              // 读取响应内容失败
+    NotM3u8,           // 内容不是m3u8
+    NoSegment,         // 播放列表中没有分片
+    InvalidSegment,    // 分片不是有效的TS/fMP4
+    FfprobeFailed,     // ffprobe执行失败
+    FfprobeTimeout,    // ffprobe超时
+    Other,
+}
+
+impl std::str::FromStr for FailureReason {
+    type Err = String;
+
+    /// 按名称解析失败原因，例如 `Timeout`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(s.to_string()))
+            .map_err(|_| format!("unknown failure reason {}", s))
+    }
+}
+
+/// 检查错误，包装在 `std::io::Error` 中返回，调用方可通过 `downcast_ref` 取出
+#[derive(Debug)]
+pub struct CheckError {
+    pub reason: FailureReason,
+    pub http_status: Option<u16>,
+    pub stage: Option<HlsCheckStage>, // 深度检查失败的阶段
+    pub msg: String,
+}
+
+impl CheckError {
+    pub fn new(reason: FailureReason, msg: String) -> CheckError {
+        CheckError {
+            reason,
+            http_status: None,
+            stage: None,
+            msg,
+        }
+    }
+
+    pub fn set_http_status(&mut self, status: u16) {
+        self.http_status = Some(status)
+    }
+
+    pub fn set_stage(&mut self, stage: HlsCheckStage) {
+        self.stage = Some(stage)
+    }
+
+    /// 从 `std::io::Error` 中取出检查错误
+    pub fn from_io_error(e: &std::io::Error) -> Option<&CheckError> {
+        e.get_ref().and_then(|r| r.downcast_ref::<CheckError>())
+    }
+
+    /// 取出 `std::io::Error` 对应的失败原因，没有携带检查错误时按错误类型判断
+    pub fn reason_of(e: &std::io::Error) -> FailureReason {
+        match CheckError::from_io_error(e) {
+            Some(err) => err.reason,
+            None if e.kind() == std::io::ErrorKind::TimedOut => FailureReason::Timeout,
+            None => FailureReason::Other,
+        }
+    }
+
+    /// 根据reqwest错误类型分类失败原因
+    pub fn from_reqwest(e: &reqwest::Error) -> CheckError {
+        let reason = if e.is_timeout() {
+            FailureReason::Timeout
+        } else if e.is_connect() {
+            // 连接错误需要进一步从错误链中区分DNS和TLS
+            let mut chain = vec![e.to_string().to_lowercase()];
+            let mut source = std::error::Error::source(e);
+            while let Some(s) = source {
+                chain.push(s.to_string().to_lowercase());
+                source = s.source();
+            }
+            let chain = chain.join(" ");
+            // 自定义解析器返回的解析失败
+            let resolve_failed =
+                std::iter::successors(std::error::Error::source(e), |s| s.source()).any(|s| {
+                    s.downcast_ref::<CheckError>()
+                        .is_some_and(|c| c.reason == FailureReason::Dns)
+                });
+            if resolve_failed
+                || chain.contains("dns error")
+                || chain.contains("failed to lookup address")
+            {
+                FailureReason::Dns
+            } else if chain.contains("certificate")
+                || chain.contains("ssl")
+                || chain.contains("tls")
+            {
+                FailureReason::Tls
+            } else {
+                FailureReason::Connect
+            }
+        } else if e.is_body() || e.is_decode() {
+            FailureReason::Body
+        } else if e.is_builder() {
+            FailureReason::InvalidUrl
+        } else {
+            FailureReason::Other
+        };
+        let mut err = CheckError::new(reason, e.to_string());
+        if let Some(status) = e.status() {
+            err.set_http_status(status.as_u16());
+        }
+        err
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.reason, self.msg)
+    }
+}
+
+impl std::error::Error for CheckError {}
+
+impl From<CheckError> for std::io::Error {
+    fn from(e: CheckError) -> Self {
+        let kind =
+            if e.reason == FailureReason::Timeout || e.reason == FailureReason::FfprobeTimeout {
+                std::io::ErrorKind::TimedOut
+            } else {
+                std::io::ErrorKind::Other
+            };
+        std::io::Error::new(kind, e)
+    }
+}
+
 /// URL检查响应结构体
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CheckUrlIsAvailableResponse {
@@ -15,6 +157,168 @@ pub struct CheckUrlIsAvailableResponse {
     pub ffmpeg_info: Option<FfmpegInfo>,
     pub video: Option<VideoInfo>, // 视频信息
     pub audio: Option<AudioInfo>, // 音频信息
+    #[serde(default)]
+    pub live_status: Option<LiveStatus>, // 直播状态
+    #[serde(default)]
+    pub throughput: Option<ThroughputInfo>, // 持续下载速度
+    #[serde(default)]
+    pub timing: Option<TimingInfo>, // 各阶段耗时
+    #[serde(default)]
+    pub content_health: Option<ContentHealth>, // 黑屏、静帧、静音检测结果
+    #[serde(default)]
+    pub fingerprint: Option<Fingerprint>, // 内容指纹
+    #[serde(default)]
+    pub encryption: Option<HlsEncryption>, // 分片加密状态
+    #[serde(default)]
+    pub redirect: Option<RedirectInfo>, // 跳转链和地址过期时间
+    #[serde(default)]
+    pub proxy: Option<String>, // 检查使用的代理，不包含认证信息
+}
+
+/// 持续下载速度测量结果
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ThroughputInfo {
+    pub segments: i32,          // 下载的分片数量
+    pub bytes: u64,             // 下载的总字节数
+    pub download_time: i64,     // 下载总耗时（毫秒）
+    pub segment_time: i64,      // 平均每个分片下载耗时（毫秒）
+    pub media_duration: f32,    // 下载分片的播放时长（秒）
+    pub throughput: u64,        // 下载速度（bit/s）
+    pub bandwidth: Option<u64>, // 主播放列表声明的码率（bit/s）
+    pub ratio: Option<f32>,     // 下载速度与所需码率之比，小于1时无法流畅播放
+}
+
+impl ThroughputInfo {
+    pub fn new() -> ThroughputInfo {
+        ThroughputInfo {
+            segments: 0,
+            bytes: 0,
+            download_time: 0,
+            segment_time: 0,
+            media_duration: 0.0,
+            throughput: 0,
+            bandwidth: None,
+            ratio: None,
+        }
+    }
+
+    /// 根据已下载的数据计算下载速度和速度比，
+    /// 没有声明码率时使用分片大小除以播放时长作为所需码率
+    pub fn calc(&mut self) {
+        if self.segments > 0 {
+            self.segment_time = self.download_time / self.segments as i64;
+        }
+        let bits = self.bytes as f64 * 8.0;
+        self.throughput = (bits * 1000.0 / self.download_time.max(1) as f64) as u64;
+        let required = match self.bandwidth {
+            Some(bandwidth) => bandwidth as f64,
+            None if self.media_duration > 0.0 => bits / self.media_duration as f64,
+            None => 0.0,
+        };
+        self.ratio = if required > 0.0 {
+            Some((self.throughput as f64 / required) as f32)
+        } else {
+            None
+        };
+    }
+}
+
+/// 画面和声音的异常类型
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentIssue {
+    Black,  // 黑屏
+    Frozen, // 静帧
+    Silent, // 静音
+}
+
+/// 异常时长占检测时长的比例超过该值时认为源有异常
+const CONTENT_ISSUE_RATIO: f32 = 0.9;
+
+/// 使用ffmpeg的blackdetect、freezedetect、silencedetect检测的结果
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ContentHealth {
+    pub sample_duration: f32,  // 检测时长（秒）
+    pub black_duration: f32,   // 黑屏时长（秒）
+    pub freeze_duration: f32,  // 静帧时长（秒）
+    pub silence_duration: f32, // 静音时长（秒）
+    pub issues: Vec<ContentIssue>,
+}
+
+/// 读取ffmpeg日志中 `key` 后面的数字
+fn parse_log_value(line: &str, key: &str) -> Option<f32> {
+    let value = line[line.find(key)? + key.len()..].trim_start();
+    let end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+/// 累加检测到的时长，只有开始没有结束时算到检测结束
+fn detect_duration(log: &str, start_key: &str, duration_key: &str, sample: f32) -> f32 {
+    let mut total = 0.0;
+    let mut pending = None;
+    for line in log.lines() {
+        if let Some(start) = parse_log_value(line, start_key) {
+            pending = Some(start);
+        }
+        if let Some(duration) = parse_log_value(line, duration_key) {
+            total += duration;
+            pending = None;
+        }
+    }
+    if let Some(start) = pending {
+        total += (sample - start).max(0.0);
+    }
+    total
+}
+
+impl ContentHealth {
+    /// 解析ffmpeg检测滤镜输出的日志
+    ///
+    /// # 参数
+    /// * `log` - ffmpeg的stderr输出
+    /// * `sample_duration` - 检测时长（秒）
+    pub fn parse(log: &str, sample_duration: f32) -> ContentHealth {
+        let mut health = ContentHealth {
+            sample_duration,
+            black_duration: detect_duration(
+                log,
+                "black_start:",
+                "black_duration:",
+                sample_duration,
+            ),
+            freeze_duration: detect_duration(
+                log,
+                "freeze_start:",
+                "freeze_duration:",
+                sample_duration,
+            ),
+            silence_duration: detect_duration(
+                log,
+                "silence_start:",
+                "silence_duration:",
+                sample_duration,
+            ),
+            issues: vec![],
+        };
+        let limit = sample_duration * CONTENT_ISSUE_RATIO;
+        if sample_duration > 0.0 {
+            if health.black_duration >= limit {
+                health.issues.push(ContentIssue::Black);
+            }
+            if health.freeze_duration >= limit {
+                health.issues.push(ContentIssue::Frozen);
+            }
+            if health.silence_duration >= limit {
+                health.issues.push(ContentIssue::Silent);
+            }
+        }
+        health
+    }
+
+    pub fn has_issue(&self, issue: ContentIssue) -> bool {
+        self.issues.contains(&issue)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -47,6 +351,14 @@ impl CheckUrlIsAvailableResponse {
             ffmpeg_info: None,
             video: None,
             audio: None,
+            live_status: None,
+            throughput: None,
+            timing: None,
+            content_health: None,
+            fingerprint: None,
+            encryption: None,
+            redirect: None,
+            proxy: None,
         }
     }
 
@@ -129,30 +441,135 @@ pub struct FfprobeStream {
 
 /// 检查模块
 pub mod check {
-    use crate::common::util::{check_body_is_m3u8_format, get_video_resolution};
-    use crate::common::{AudioInfo, CheckUrlIsAvailableResponse, FfmpegInfo, Ffprobe, VideoInfo};
+    use crate::common::cmd::ffmpeg_headers_args;
+    use crate::common::dash::{is_dash_url, parse_mpd, DashManifest};
+    use crate::common::dns::CachedResolver;
+    use crate::common::fingerprint::{capture_frame_hash, Fingerprint};
+    use crate::common::hls::{
+        detect_segment_format, master_video_info, parse_playlist, resolve_uri, HlsCheckStage,
+        HlsEncryption, HlsKey, HlsMediaPlaylist, HlsPlaylist, HlsVariant, LiveStatus,
+        SegmentFormat,
+    };
+    use crate::common::media::{parse_fmp4_init, parse_ts};
+    use crate::common::probe::{is_probe_scheme, probe_stream};
+    use crate::common::proxy::{apply_proxy, ffmpeg_proxy_args, ProxyConfig};
+    use crate::common::redirect::RedirectInfo;
+    use crate::common::timing::{measure_timing, TimingInfo};
+    use crate::common::tls::{ffmpeg_tls_args, TlsConfig};
+    use crate::common::util::{
+        check_body_is_dash_format, check_body_is_m3u8_format, get_video_resolution,
+    };
+    use crate::common::{
+        AudioInfo, CheckError, CheckOptions, CheckUrlIsAvailableResponse, ContentHealth,
+        FailureReason, FfmpegInfo, Ffprobe, ThroughputInfo, VideoInfo,
+    };
+    use crate::config::base::get_base_config;
     use chrono::Utc;
     use log::debug;
-    use std::io::{Error, ErrorKind, Read};
-    use std::process::{Command, ExitStatus, Stdio};
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+    use std::collections::{BTreeMap, HashMap};
+    use std::io::Error;
+    use std::net::{IpAddr, SocketAddr};
+    use std::process::Stdio;
     use std::sync::{Arc, Mutex};
-    use std::thread;
     use std::time;
-    use std::time::Instant;
+    use tokio::process::Command;
+    use tokio::sync::{OwnedSemaphorePermit, Semaphore};
     use tokio::time::Duration;
     use url::Url;
 
+    /// 使用ffmpeg检测一段时间内的黑屏、静帧和静音
+    ///
+    /// # 参数
+    /// * `_url` - 要检查的URL
+    /// * `sample_secs` - 检测时长（秒）
+    /// * `timeout_mill_secs` - 连接超时时间（毫秒），总超时时间会加上检测时长
+    /// * `headers` - 请求头
+    /// * `proxy` - 拉流使用的代理，ffmpeg不支持的代理返回错误
+    ///
+    /// # 返回值
+    /// * `Result<ContentHealth, Error>` - 检测结果
+    pub async fn check_content_health(
+        _url: &str,
+        sample_secs: u64,
+        timeout_mill_secs: u64,
+        headers: &BTreeMap<String, String>,
+        proxy: Option<&ProxyConfig>,
+    ) -> Result<ContentHealth, Error> {
+        let timeout = Duration::from_millis(timeout_mill_secs + sample_secs * 2000);
+        let mut cmd = Command::new("ffmpeg");
+        cmd.args(["-hide_banner", "-nostats"]);
+        cmd.args(ffmpeg_headers_args(headers));
+        cmd.args(ffmpeg_tls_args(_url));
+        cmd.args(
+            ffmpeg_proxy_args(proxy)
+                .map_err(|e| CheckError::new(FailureReason::FfprobeFailed, e.to_string()))?,
+        );
+        cmd.args([
+            "-t",
+            &sample_secs.to_string(),
+            "-i",
+            _url,
+            "-vf",
+            "blackdetect=d=1:pix_th=0.10,freezedetect=n=-60dB:d=2",
+            "-af",
+            "silencedetect=n=-50dB:d=2",
+            "-f",
+            "null",
+            "-",
+        ]);
+        cmd.stdout(Stdio::null());
+        cmd.stderr(Stdio::piped());
+        cmd.kill_on_drop(true);
+        let child = cmd.spawn().map_err(|e| {
+            CheckError::new(
+                FailureReason::FfprobeFailed,
+                format!("Failed to spawn ffmpeg: {}", e),
+            )
+        })?;
+        let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                return Err(CheckError::new(
+                    FailureReason::FfprobeFailed,
+                    format!("Failed to wait on ffmpeg: {}", e),
+                )
+                .into())
+            }
+            Err(_) => {
+                return Err(CheckError::new(
+                    FailureReason::FfprobeTimeout,
+                    "ffmpeg timed out".to_string(),
+                )
+                .into())
+            }
+        };
+        if !output.status.success() {
+            return Err(CheckError::new(
+                FailureReason::FfprobeFailed,
+                format!("ffmpeg failed with status: {}", output.status),
+            )
+            .into());
+        }
+        let log = String::from_utf8_lossy(&output.stderr);
+        Ok(ContentHealth::parse(&log, sample_secs as f32))
+    }
+
     /// 使用超时运行命令并获取结果
     ///
     /// # 参数
     /// * `_url` - 要检查的URL
     /// * `timeout_mill_secs` - 超时时间（毫秒）
+    /// * `headers` - 请求头
+    /// * `proxy` - ffprobe使用的代理，ffmpeg不支持的代理返回错误
     ///
     /// # 返回值
     /// * `Result<CheckUrlIsAvailableResponse, Error>` - 检查结果
     pub async fn run_command_with_timeout_new(
         _url: String,
         timeout_mill_secs: u64,
+        headers: &BTreeMap<String, String>,
+        proxy: Option<&ProxyConfig>,
     ) -> Result<CheckUrlIsAvailableResponse, Error> {
         let timeout = Duration::from_millis(timeout_mill_secs);
         let mut second = timeout_mill_secs / 1000;
@@ -160,8 +577,14 @@ pub mod check {
             second = 1
         }
 
-        // 1. 配置FFprobe命令
+        // 1. 配置FFprobe命令，超时或任务取消时丢弃future会结束子进程
         let mut cmd = Command::new("ffprobe");
+        cmd.args(ffmpeg_headers_args(headers));
+        cmd.args(ffmpeg_tls_args(&_url));
+        cmd.args(
+            ffmpeg_proxy_args(proxy)
+                .map_err(|e| CheckError::new(FailureReason::FfprobeFailed, e.to_string()))?,
+        );
         cmd.args(vec![
             "-v",
             "quiet",
@@ -175,147 +598,55 @@ pub mod check {
         ]);
         cmd.stdout(Stdio::piped());
         cmd.stderr(Stdio::piped());
+        cmd.kill_on_drop(true);
 
-        // 启动子进程
-        let mut child = cmd
-            .spawn()
-            .map_err(|e| format!("Failed to spawn command: {}", e))
-            .unwrap();
-
-        // 2. 获取标准输出和错误输出的管道句柄
-        let stdout_handle = child
-            .stdout
-            .take()
-            .ok_or("Failed to open stdout pipe".to_string())
-            .unwrap();
-        let stderr_handle = child
-            .stderr
-            .take()
-            .ok_or("Failed to open stderr pipe".to_string())
-            .unwrap();
-
-        // 3. 创建共享缓冲区用于存储输出
-        let stdout_buf = Arc::new(Mutex::new(Vec::new()));
-        let stderr_buf = Arc::new(Mutex::new(Vec::new()));
-
-        // 克隆Arc以便在线程间共享
-        let stdout_buf_clone = Arc::clone(&stdout_buf);
-        let stderr_buf_clone = Arc::clone(&stderr_buf);
-
-        // 4. 启动标准输出读取线程
-        let stdout_thread = thread::spawn(move || {
-            let mut buffer = [0; 1024];
-            let mut handle = stdout_handle;
-            loop {
-                match handle.read(&mut buffer) {
-                    Ok(0) => break, // 文件结束
-                    Ok(n) => {
-                        let mut locked_buf = stdout_buf_clone.lock().unwrap();
-                        locked_buf.extend_from_slice(&buffer[..n]);
-                    }
-                    Err(ref e) if e.kind() == ErrorKind::BrokenPipe => break,
-                    Err(e) => {
-                        eprintln!("Error reading stdout: {}", e);
-                        break;
-                    }
-                }
-            }
-        });
+        // 2. 启动子进程
+        let child = cmd.spawn().map_err(|e| {
+            CheckError::new(
+                FailureReason::FfprobeFailed,
+                format!("Failed to spawn command: {}", e),
+            )
+        })?;
 
-        // 5. 启动标准错误读取线程
-        let stderr_thread = thread::spawn(move || {
-            let mut buffer = [0; 1024];
-            let mut handle = stderr_handle;
-            loop {
-                match handle.read(&mut buffer) {
-                    Ok(0) => break,
-                    Ok(n) => {
-                        let mut locked_buf = stderr_buf_clone.lock().unwrap();
-                        locked_buf.extend_from_slice(&buffer[..n]);
-                    }
-                    Err(ref e) if e.kind() == ErrorKind::BrokenPipe => break,
-                    Err(e) => {
-                        eprintln!("Error reading stderr: {}", e);
-                        break;
-                    }
-                }
+        // 3. 等待子进程结束并收集输出
+        let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                return Err(CheckError::new(
+                    FailureReason::FfprobeFailed,
+                    format!("Failed to wait on child process: {}", e),
+                )
+                .into())
             }
-        });
-
-        // 6. 主线程执行超时检查和进程状态监控
-        let start = Instant::now();
-        let final_status: ExitStatus;
-        let mut timed_out = false;
-
-        loop {
-            match child.try_wait() {
-                Ok(Some(status)) => {
-                    final_status = status;
-                    break;
-                }
-                Ok(None) => {
-                    if start.elapsed() >= timeout {
-                        match child.kill() {
-                            Ok(_) => debug!("Process killed due to timeout."),
-                            Err(e) => {
-                                debug!("Warning: Failed to kill process after timeout: {}", e)
-                            }
-                        }
-                        timed_out = true;
-                        thread::sleep(Duration::from_millis(50));
-                        final_status = child
-                            .try_wait()
-                            .map_err(|e| format!("Error checking status after kill: {}", e))
-                            .unwrap()
-                            .unwrap_or_else(|| {
-                                debug!(
-                                    "Warning: Process did not exit immediately after kill signal."
-                                );
-                                ExitStatus::default()
-                            });
-                        break;
-                    }
-                    thread::sleep(Duration::from_millis(100));
-                }
-                Err(e) => {
-                    stdout_thread.join().expect("Stdout thread panicked");
-                    stderr_thread.join().expect("Stderr thread panicked");
-                    return Err(Error::new(
-                        ErrorKind::Other,
-                        format!("Failed to wait on child process: {}", e),
-                    ));
-                }
+            Err(_) => {
+                debug!("Process killed due to timeout.");
+                return Err(CheckError::new(
+                    FailureReason::FfprobeTimeout,
+                    "Command timed out".to_string(),
+                )
+                .into());
             }
-        }
-
-        // 7. 等待读取线程完成
-        stdout_thread.join().expect("Stdout thread panicked");
-        stderr_thread.join().expect("Stderr thread panicked");
+        };
 
-        // 8. 处理超时情况
-        if timed_out {
-            return Err(Error::new(ErrorKind::TimedOut, "Command timed out"));
-        }
-
-        // 9. 检查进程退出状态
-        if !final_status.success() {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("Command failed with status: {}", final_status),
-            ));
+        // 4. 检查进程退出状态
+        if !output.status.success() {
+            return Err(CheckError::new(
+                FailureReason::FfprobeFailed,
+                format!("Command failed with status: {}", output.status),
+            )
+            .into());
         }
 
-        // 10. 解析FFprobe输出
-        let stdout_data = stdout_buf.lock().unwrap();
-        let output = String::from_utf8_lossy(&stdout_data);
+        // 5. 解析FFprobe输出
+        let output = String::from_utf8_lossy(&output.stdout);
         let ffprobe: Ffprobe = serde_json::from_str(&output).map_err(|e| {
-            Error::new(
-                ErrorKind::InvalidData,
+            CheckError::new(
+                FailureReason::FfprobeFailed,
                 format!("Failed to parse ffprobe output: {}", e),
             )
         })?;
 
-        // 11. 处理流信息
+        // 6. 处理流信息
         let mut response = CheckUrlIsAvailableResponse::new();
         let mut audio = None;
         let mut video_list = vec![];
@@ -355,156 +686,1175 @@ pub mod check {
         Ok(response)
     }
 
-    /// 检查链接是否有效
+    fn http_status_error(status: reqwest::StatusCode) -> CheckError {
+        let mut err = CheckError::new(
+            FailureReason::HttpStatus,
+            format!("status is not 200, got {}", status),
+        );
+        err.set_http_status(status.as_u16());
+        err
+    }
+
+    /// 深度检查时最多下载的分片字节数
+    const DEEP_CHECK_SEGMENT_LIMIT: usize = 1024 * 1024;
+
+    /// 获取播放列表或清单时最多读取的字节数，超过时认为是直接的媒体流
+    const PLAYLIST_FETCH_LIMIT: usize = 2 * 1024 * 1024;
+
+    /// 获取加密密钥时最多下载的字节数
+    const KEY_FETCH_LIMIT: usize = 4096;
+
+    /// AES-128 密钥长度
+    const AES_128_KEY_LEN: usize = 16;
+
+    /// 最多跟随的http跳转次数
+    const MAX_REDIRECTS: usize = 10;
+
+    /// 主播放列表最多跟随的层数
+    const DEEP_CHECK_MAX_DEPTH: usize = 3;
+
+    /// 测速时单个分片最多下载的字节数
+    const THROUGHPUT_SEGMENT_LIMIT: usize = 32 * 1024 * 1024;
+
+    /// 直播检测两次获取播放列表的最长间隔（秒）
+    const LIVENESS_MAX_WAIT_SECS: f32 = 15.0;
+
+    /// 播放列表没有 `EXT-X-TARGETDURATION` 时的间隔（秒）
+    const LIVENESS_DEFAULT_WAIT_SECS: f32 = 6.0;
+
+    fn hls_error(stage: HlsCheckStage, mut err: CheckError) -> Error {
+        err.set_stage(stage);
+        err.into()
+    }
+
+    /// 获取地址内容，返回（最终地址，响应头耗时毫秒，内容）
     ///
     /// # 参数
-    /// * `_url` - 要检查的URL
-    /// * `timeout` - 超时时间（毫秒）
-    /// * `need_video_info` - 是否需要视频信息
-    /// * `ffmpeg_check` - 是否使用FFmpeg检查
-    /// * `not_http_skip` - 是否跳过非HTTP链接
+    /// * `client` - http客户端
+    /// * `_url` - 要获取的地址
+    /// * `headers` - 请求头
+    /// * `limit` - 最多读取的字节数
+    async fn fetch_limited(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+        limit: usize,
+    ) -> Result<(String, i64, Vec<u8>), CheckError> {
+        let (_, final_url, delay, body) =
+            fetch_with_redirects(client, _url, headers, limit).await?;
+        Ok((final_url, delay, body))
+    }
+
+    /// 获取地址内容并记录跳转链，返回（依次跳转到的地址，最终地址，响应头耗时毫秒，内容）
     ///
-    /// # 返回值
-    /// * `Result<CheckUrlIsAvailableResponse, Error>` - 检查结果
-    pub async fn check_link_is_valid(
-        _url: String,
-        timeout: u64,
-        ffmpeg_check: bool,
-        not_http_skip: bool,
-    ) -> Result<CheckUrlIsAvailableResponse, Error> {
-        if ffmpeg_check {
-            let res = run_command_with_timeout_new(_url.to_owned(), timeout).await;
-            return match res {
-                Ok(res) => Ok(res),
-                Err(e) => Err(Error::new(
-                    ErrorKind::Other,
-                    format!("status is not 200 {}", e),
-                )),
-            };
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 要获取的地址
+    /// * `headers` - 请求头
+    /// * `limit` - 最多读取的字节数
+    async fn fetch_with_redirects(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+        limit: usize,
+    ) -> Result<(Vec<String>, String, i64, Vec<u8>), CheckError> {
+        let curr_timestamp = Utc::now().timestamp_millis();
+        let (mut res, redirects) = send_following_redirects(client, _url, headers).await?;
+        let delay = Utc::now().timestamp_millis() - curr_timestamp;
+        if !res.status().is_success() {
+            return Err(http_status_error(res.status()));
         }
-        let parsed_info = Url::parse(&_url);
-        match parsed_info {
-            Ok(parsed_url) => {
-                if parsed_url.scheme() != "https" && parsed_url.scheme() != "http" {
-                    return if not_http_skip {
-                        Ok(CheckUrlIsAvailableResponse::new())
-                    } else {
-                        Err(Error::new(
-                            ErrorKind::Other,
-                            "scheme not http, temporary not support",
-                        ))
-                    };
-                }
-            }
-            Err(e) => {
-                return Err(Error::new(ErrorKind::Other, format!("error {}", e)));
-            }
-        }
-        let client_resp = reqwest::Client::builder()
-            .timeout(time::Duration::from_millis(timeout))
-            .danger_accept_invalid_certs(true)
-            .build();
-        match client_resp {
-            Ok(client) => {
-                let curr_timestamp = Utc::now().timestamp_millis();
-                let http_res = client.get(_url.to_owned()).send().await;
-                match http_res {
-                    Ok(res) => {
-                        if res.status().is_success() {
-                            let delay = Utc::now().timestamp_millis() - curr_timestamp;
-                            let _body = res.text().await;
-                            match _body {
-                                Ok(body) => {
-                                    if check_body_is_m3u8_format(body.clone()) {
-                                        let mut body: CheckUrlIsAvailableResponse =
-                                            CheckUrlIsAvailableResponse::new();
-                                        body.set_delay(delay as i32);
-                                        Ok(body)
-                                    } else {
-                                        Err(Error::new(ErrorKind::Other, "not a m3u8 file"))
-                                    }
-                                }
-                                Err(e) => Err(Error::new(ErrorKind::Other, format!("{:?}", e))),
-                            }
-                        } else {
-                            Err(Error::new(ErrorKind::Other, "status is not 200"))
-                        }
-                    }
-                    Err(e) => {
-                        return Err(Error::new(ErrorKind::Other, format!("error {}", e)));
-                    }
-                }
-            }
-            Err(e) => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    format!("http client build error {}", e),
-                ));
+        let final_url = res.url().to_string();
+        let mut body = vec![];
+        while body.len() < limit {
+            match res.chunk().await {
+                Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+                Ok(None) => break,
+                Err(e) => return Err(CheckError::from_reqwest(&e)),
             }
         }
+        Ok((redirects, final_url, delay, body))
     }
 
-    /// 测试模块
-    mod tests {
-        // use crate::common::check::check::run_command_with_timeout_new;
-        // use std::sync::mpsc;
-        // use std::thread;
-        // #[tokio::test]
-        // async fn test_timeout() {
-        //     let (tx, rx) = mpsc::channel();
-        //
-        //     // 模拟从channel里收到一条命令
-        //     thread::spawn(move || {
-        //         tx.send(("https://cd-live-stream.news.cctvplus.com/live/smil:CHANNEL2.smil/playlist.m3u8", 5000)).unwrap();
-        //         // 比如要执行sleep 5秒
-        //     });
-        //
-        //     if let Ok((_url, timeout)) = rx.recv() {
-        //         println!("Running command: {} {:?}", _url, timeout);
-        //         match run_command_with_timeout_new(_url.to_string(), (timeout as u64)).await {
-        //             Ok(ed) => {
-        //                 let v = ed.video.clone().unwrap();
-        //                 println!("Command finished successfully.{} {}", v.width, v.height)
-        //             }
-        //             Err(e) => println!("Command failed: {}", e),
-        //         }
-        //     }
-        // }
+    /// 内容开头是否为媒体数据（MPEG-TS、fMP4或FLV），即地址是直接的媒体流而不是播放列表
+    fn is_media_stream(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"FLV") || detect_segment_format(bytes).is_some()
     }
-}
 
-pub async fn get_favourite_channel(channel_type: String) -> Result<String, Error> {
-    // 获取今日日期对应目录
-    let today = chrono::Local::now().format("%Y%m%d").to_string();
-    let search_path = format!("{}/{}", INPUT_SEARCH_FOLDER, today);
+    /// 获取播放列表或清单并记录跳转链，返回值与 `fetch_with_redirects` 相同
+    ///
+    /// 直接的 `.ts`/`.flv` 直播流不会结束，内容开头是媒体数据或超过 `PLAYLIST_FETCH_LIMIT`
+    /// 时立即停止读取，按内容不是播放列表返回，避免一直下载到超时
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 播放列表或清单地址
+    /// * `headers` - 请求头
+    async fn fetch_playlist(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<(Vec<String>, String, i64, Vec<u8>), CheckError> {
+        let curr_timestamp = Utc::now().timestamp_millis();
+        let (mut res, redirects) = send_following_redirects(client, _url, headers).await?;
+        let delay = Utc::now().timestamp_millis() - curr_timestamp;
+        if !res.status().is_success() {
+            return Err(http_status_error(res.status()));
+        }
+        let final_url = res.url().to_string();
+        let mut body = vec![];
+        while let Some(chunk) = res
+            .chunk()
+            .await
+            .map_err(|e| CheckError::from_reqwest(&e))?
+        {
+            body.extend_from_slice(&chunk);
+            if is_media_stream(&body) || body.len() > PLAYLIST_FETCH_LIMIT {
+                return Err(CheckError::new(
+                    FailureReason::NotM3u8,
+                    format!("{} is a media stream, not a playlist", final_url),
+                ));
+            }
+        }
+        Ok((redirects, final_url, delay, body))
+    }
 
-    let mut all_files = Vec::new();
-    let dir_entries = match std::fs::read_dir(&search_path) {
-        Ok(entries) => entries,
-        Err(e) => {
-            return Ok("".to_string());
+    /// 发送GET请求并跟随跳转，返回最终响应和依次跳转到的地址
+    ///
+    /// 检查使用的客户端不会自动跳转，这样才能记录跳转链
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 请求地址
+    /// * `headers` - 请求头，每次跳转都会带上
+    pub async fn send_following_redirects(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<(reqwest::Response, Vec<String>), CheckError> {
+        let mut redirects: Vec<String> = vec![];
+        let mut current = _url.to_string();
+        loop {
+            let res = request_with_headers(client, &current, headers)
+                .send()
+                .await
+                .map_err(|e| CheckError::from_reqwest(&e))?;
+            if !res.status().is_redirection() {
+                return Ok((res, redirects));
+            }
+            let location = match res
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|l| l.to_str().ok())
+            {
+                Some(location) => location,
+                None => return Ok((res, redirects)),
+            };
+            if redirects.len() >= MAX_REDIRECTS {
+                return Err(CheckError::new(
+                    FailureReason::HttpStatus,
+                    format!("too many redirects from {}", _url),
+                ));
+            }
+            current = resolve_uri(res.url().as_str(), location);
+            redirects.push(current.clone());
         }
-    };
+    }
 
-    for entry in dir_entries {
-        match entry {
-            Ok(entry) => {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Some(file_str) = path.to_str() {
-                        all_files.push(file_str.to_string());
+    /// 跟随主播放列表后得到的媒体播放列表
+    pub struct FetchedPlaylist {
+        pub url: String,                 // 媒体播放列表地址（跳转后）
+        pub delay: i64,                  // 首次请求耗时（毫秒）
+        pub stage: HlsCheckStage,        // 媒体播放列表所处的阶段
+        pub variant: Option<HlsVariant>, // 选中的码率，直接是媒体播放列表时为空
+        pub master: Vec<VideoInfo>,      // 主播放列表中声明的所有码率
+        pub redirects: Vec<String>,      // 首次请求依次跳转到的地址
+        pub media: HlsMediaPlaylist,
+    }
+
+    /// 获取媒体播放列表，主播放列表会跟随第一个码率
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 播放列表地址
+    /// * `headers` - 请求头
+    ///
+    /// # 返回值
+    /// * `Result<FetchedPlaylist, Error>` - 媒体播放列表
+    pub async fn fetch_media_playlist(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<FetchedPlaylist, Error> {
+        let mut stage = HlsCheckStage::Playlist;
+        let mut playlist_url = _url.to_string();
+        let mut delay = None;
+        let mut variant = None;
+        let mut master = vec![];
+        let mut redirects = None;
+        let mut depth = 0;
+        let media = loop {
+            let (req_redirects, final_url, req_delay, body) =
+                fetch_playlist(client, &playlist_url, headers)
+                    .await
+                    .map_err(|e| hls_error(stage, e))?;
+            delay.get_or_insert(req_delay);
+            redirects.get_or_insert(req_redirects);
+            playlist_url = final_url;
+            match parse_playlist(&String::from_utf8_lossy(&body)) {
+                None => {
+                    return Err(hls_error(
+                        stage,
+                        CheckError::new(FailureReason::NotM3u8, "not a m3u8 file".to_string()),
+                    ))
+                }
+                Some(HlsPlaylist::Master(variants)) => {
+                    depth += 1;
+                    if depth > DEEP_CHECK_MAX_DEPTH {
+                        return Err(hls_error(
+                            stage,
+                            CheckError::new(
+                                FailureReason::NotM3u8,
+                                "too many nested playlists".to_string(),
+                            ),
+                        ));
+                    }
+                    if master.is_empty() {
+                        master = master_video_info(&playlist_url, &variants);
                     }
+                    playlist_url = resolve_uri(&playlist_url, &variants[0].uri);
+                    variant = Some(variants[0].clone());
+                    stage = HlsCheckStage::VariantPlaylist;
                 }
+                Some(HlsPlaylist::Media(media)) => break media,
             }
-            Err(e) => {
-                // 遇到单个文件错误也继续处理其他文件
-                continue;
+        };
+        Ok(FetchedPlaylist {
+            url: playlist_url,
+            delay: delay.unwrap_or(0),
+            stage,
+            variant,
+            master,
+            redirects: redirects.unwrap_or_default(),
+            media,
+        })
+    }
+
+    /// 合并清单中声明的码率和从分片中解析到的音视频信息
+    ///
+    /// 清单中声明了所有码率，分片中只能解析到当前码率，清单缺少分辨率时才使用分片中的视频信息
+    ///
+    /// # 参数
+    /// * `declared` - 主播放列表或MPD清单中的音视频信息
+    /// * `parsed` - 从分片中解析到的音视频信息
+    fn merge_declared_info(
+        mut declared: FfmpegInfo,
+        parsed: Option<FfmpegInfo>,
+    ) -> Option<FfmpegInfo> {
+        if let Some(parsed) = parsed {
+            if declared.video.iter().all(|v| v.height == 0) && !parsed.video.is_empty() {
+                declared.set_video(parsed.video);
+            }
+            if let Some(audio) = parsed.audio {
+                declared.set_audio(audio);
+            }
+        }
+        if declared.video.is_empty() && declared.audio.is_none() {
+            return None;
+        }
+        Some(declared)
+    }
+
+    /// 深度检查HLS源
+    ///
+    /// 解析播放列表，主播放列表会跟随到子播放列表，然后下载一个媒体分片，
+    /// 校验其为真实的 MPEG-TS 或 fMP4 数据，并从中解析分辨率和音视频编码；
+    /// 分片加密时记录加密方式并探测密钥能否获取，AES-128 整段加密的分片不校验格式
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 要检查的URL
+    /// * `headers` - 请求头
+    ///
+    /// # 返回值
+    /// * `Result<CheckUrlIsAvailableResponse, Error>` - 失败时错误中携带 `CheckError`
+    pub async fn deep_check_hls(
+        client: &CheckClient,
+        _url: String,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<CheckUrlIsAvailableResponse, Error> {
+        let FetchedPlaylist {
+            url: playlist_url,
+            delay,
+            stage,
+            master,
+            redirects,
+            media,
+            ..
+        } = fetch_media_playlist(client, &_url, headers).await?;
+        // 直播列表中最后一个分片最新，最不容易已过期
+        let segment = match media.segments.last() {
+            Some(s) => s,
+            None => {
+                return Err(hls_error(
+                    stage,
+                    CheckError::new(
+                        FailureReason::NoSegment,
+                        "playlist has no segment".to_string(),
+                    ),
+                ))
+            }
+        };
+        let segment_url = resolve_uri(&playlist_url, &segment.uri);
+        let segment_start = time::Instant::now();
+        let (_, _, bytes) = fetch_limited(client, &segment_url, headers, DEEP_CHECK_SEGMENT_LIMIT)
+            .await
+            .map_err(|e| hls_error(HlsCheckStage::Segment, e))?;
+        let mut timing = TimingInfo::new();
+        timing.set_first_segment(Some(segment_start.elapsed().as_millis() as i64));
+        // AES-128 加密整个分片，无法校验格式和解析音视频信息
+        let whole_encrypted = media.key.as_ref().is_some_and(|k| k.method == "AES-128");
+        let format = match detect_segment_format(&bytes) {
+            Some(format) => Some(format),
+            None if whole_encrypted && !bytes.is_empty() => None,
+            None => {
+                return Err(hls_error(
+                    HlsCheckStage::SegmentFormat,
+                    CheckError::new(
+                        FailureReason::InvalidSegment,
+                        format!("segment {} is not mpeg-ts or fmp4", segment_url),
+                    ),
+                ))
+            }
+        };
+        let mut response = CheckUrlIsAvailableResponse::new();
+        response.set_delay(delay as i32);
+        response.timing = Some(timing);
+        response.fingerprint = Some(Fingerprint::from_segment(&bytes));
+        response.redirect = Some(RedirectInfo::new(&_url, redirects));
+        if let Some(key) = &media.key {
+            response.encryption = Some(check_hls_key(client, key, &playlist_url, headers).await);
+        }
+        // 直接从分片中解析音视频信息，fMP4的编码信息在初始化分片中
+        let ffmpeg_info = match format {
+            None => None,
+            Some(SegmentFormat::MpegTs) => parse_ts(&bytes),
+            Some(SegmentFormat::Fmp4) => match &media.map_uri {
+                Some(map_uri) => {
+                    let init_url = resolve_uri(&playlist_url, map_uri);
+                    fetch_limited(client, &init_url, headers, DEEP_CHECK_SEGMENT_LIMIT)
+                        .await
+                        .ok()
+                        .and_then(|(_, _, init)| parse_fmp4_init(&init))
+                }
+                None => parse_fmp4_init(&bytes),
+            },
+        };
+        let mut declared = FfmpegInfo::new();
+        declared.set_video(master);
+        if let Some(ffmpeg_info) = merge_declared_info(declared, ffmpeg_info) {
+            response.set_ffmpeg_info(ffmpeg_info);
+        }
+        Ok(response)
+    }
+
+    /// 记录加密方式并探测密钥能否获取
+    ///
+    /// 只探测 http/https 地址，`data:` 地址中直接带有密钥，DRM的 `skd://` 等地址无法直接获取不做探测
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `key` - 播放列表中的加密方式
+    /// * `playlist_url` - 媒体播放列表地址（跳转后）
+    /// * `headers` - 请求头
+    ///
+    /// # 返回值
+    /// * `HlsEncryption` - 加密状态
+    pub async fn check_hls_key(
+        client: &CheckClient,
+        key: &HlsKey,
+        playlist_url: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> HlsEncryption {
+        let mut encryption = HlsEncryption::new(key, playlist_url);
+        let key_uri = match &encryption.key_uri {
+            Some(uri) if uri.starts_with("http") => uri.clone(),
+            Some(uri) if uri.starts_with("data:") => {
+                encryption.set_key_reachable(true, None);
+                return encryption;
+            }
+            _ => return encryption,
+        };
+        match fetch_limited(client, &key_uri, headers, KEY_FETCH_LIMIT).await {
+            // 明文AES-128密钥固定16字节，返回网页等其它内容说明密钥已失效
+            Ok((_, _, body))
+                if encryption.method == "AES-128"
+                    && !encryption.is_drm()
+                    && body.len() != AES_128_KEY_LEN =>
+            {
+                encryption.set_key_reachable(false, Some(FailureReason::Body))
+            }
+            Ok(_) => encryption.set_key_reachable(true, None),
+            Err(e) => encryption.set_key_reachable(false, Some(e.reason)),
+        }
+        encryption
+    }
+
+    /// 内容不是m3u8播放列表导致的失败，此时可以再按DASH清单检查
+    fn is_not_playlist(e: &Error) -> bool {
+        CheckError::from_io_error(e)
+            .map(|err| {
+                err.reason == FailureReason::NotM3u8 && err.stage == Some(HlsCheckStage::Playlist)
+            })
+            .unwrap_or(false)
+    }
+
+    /// 分片不是能识别的 MPEG-TS/fMP4，可能是ffprobe能解析的其它容器
+    fn is_unknown_container(e: &Error) -> bool {
+        CheckError::from_io_error(e).is_some_and(|err| err.reason == FailureReason::InvalidSegment)
+    }
+
+    /// 获取并解析DASH清单，返回（依次跳转到的地址，最终地址，响应头耗时毫秒，清单）
+    async fn fetch_dash_manifest(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<(Vec<String>, String, i64, DashManifest), Error> {
+        let (redirects, final_url, delay, body) = fetch_playlist(client, _url, headers)
+            .await
+            .map_err(|e| hls_error(HlsCheckStage::Playlist, e))?;
+        match parse_mpd(&String::from_utf8_lossy(&body)) {
+            Some(manifest) => Ok((redirects, final_url, delay, manifest)),
+            None => Err(hls_error(
+                HlsCheckStage::Playlist,
+                CheckError::new(FailureReason::NotM3u8, "not a mpd file".to_string()),
+            )),
+        }
+    }
+
+    /// 深度检查DASH源
+    ///
+    /// 解析MPD清单，下载第一个视频码率的初始化分片和一个媒体分片（直播取最新的分片），
+    /// 校验其为真实的 fMP4 或 MPEG-TS 数据；清单中所有视频码率的分辨率、码率和编码写入视频信息，
+    /// 并根据清单类型记录直播或点播
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 清单地址
+    /// * `headers` - 请求头
+    ///
+    /// # 返回值
+    /// * `Result<CheckUrlIsAvailableResponse, Error>` - 失败时错误中携带 `CheckError`
+    pub async fn deep_check_dash(
+        client: &CheckClient,
+        _url: String,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<CheckUrlIsAvailableResponse, Error> {
+        let (redirects, manifest_url, delay, manifest) =
+            fetch_dash_manifest(client, &_url, headers).await?;
+        let no_segment = |msg: &str| {
+            hls_error(
+                HlsCheckStage::Playlist,
+                CheckError::new(FailureReason::NoSegment, msg.to_string()),
+            )
+        };
+        let rep = manifest
+            .main_representation()
+            .ok_or_else(|| no_segment("mpd has no representation"))?;
+        let (init_url, segment_url) = manifest
+            .segment_urls(rep, &manifest_url, Utc::now().timestamp_millis())
+            .ok_or_else(|| no_segment("mpd has no segment"))?;
+        let init = match init_url {
+            Some(init_url) => {
+                let (_, _, init) =
+                    fetch_limited(client, &init_url, headers, DEEP_CHECK_SEGMENT_LIMIT)
+                        .await
+                        .map_err(|e| hls_error(HlsCheckStage::Segment, e))?;
+                if detect_segment_format(&init).is_none() {
+                    return Err(hls_error(
+                        HlsCheckStage::SegmentFormat,
+                        CheckError::new(
+                            FailureReason::InvalidSegment,
+                            format!("init segment {} is not fmp4", init_url),
+                        ),
+                    ));
+                }
+                Some(init)
+            }
+            None => None,
+        };
+        let segment_start = time::Instant::now();
+        let (_, _, bytes) = fetch_limited(client, &segment_url, headers, DEEP_CHECK_SEGMENT_LIMIT)
+            .await
+            .map_err(|e| hls_error(HlsCheckStage::Segment, e))?;
+        let mut timing = TimingInfo::new();
+        timing.set_first_segment(Some(segment_start.elapsed().as_millis() as i64));
+        let format = match detect_segment_format(&bytes) {
+            Some(format) => format,
+            None => {
+                return Err(hls_error(
+                    HlsCheckStage::SegmentFormat,
+                    CheckError::new(
+                        FailureReason::InvalidSegment,
+                        format!("segment {} is not mpeg-ts or fmp4", segment_url),
+                    ),
+                ))
+            }
+        };
+        let mut response = CheckUrlIsAvailableResponse::new();
+        response.set_delay(delay as i32);
+        response.timing = Some(timing);
+        response.fingerprint = Some(Fingerprint::from_segment(&bytes));
+        response.redirect = Some(RedirectInfo::new(&_url, redirects));
+        response.live_status = Some(if manifest.live {
+            LiveStatus::Live
+        } else {
+            LiveStatus::Vod
+        });
+        let parsed = match format {
+            SegmentFormat::MpegTs => parse_ts(&bytes),
+            SegmentFormat::Fmp4 => init
+                .as_deref()
+                .and_then(parse_fmp4_init)
+                .or_else(|| parse_fmp4_init(&bytes)),
+        };
+        if let Some(ffmpeg_info) = merge_declared_info(manifest.ffmpeg_info(), parsed) {
+            response.set_ffmpeg_info(ffmpeg_info);
+        }
+        Ok(response)
+    }
+
+    /// 深度检查HLS或DASH源，地址不以 `.mpd` 结尾但内容是MPD清单时也按DASH检查
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 要检查的URL
+    /// * `headers` - 请求头
+    ///
+    /// # 返回值
+    /// * `Result<CheckUrlIsAvailableResponse, Error>` - 失败时错误中携带 `CheckError`
+    pub async fn deep_check_stream(
+        client: &CheckClient,
+        _url: String,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<CheckUrlIsAvailableResponse, Error> {
+        if is_dash_url(&_url) {
+            return deep_check_dash(client, _url, headers).await;
+        }
+        match deep_check_hls(client, _url.clone(), headers).await {
+            Err(e) if is_not_playlist(&e) => {
+                deep_check_dash(client, _url, headers)
+                    .await
+                    .map_err(|dash_err| {
+                        if is_not_playlist(&dash_err) {
+                            e
+                        } else {
+                            dash_err
+                        }
+                    })
+            }
+            res => res,
+        }
+    }
+
+    /// 检查DASH清单是直播（type="dynamic"）还是点播
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 清单地址
+    /// * `headers` - 请求头
+    ///
+    /// # 返回值
+    /// * `Result<LiveStatus, Error>` - 直播状态
+    pub async fn check_dash_liveness(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<LiveStatus, Error> {
+        let (_, _, _, manifest) = fetch_dash_manifest(client, _url, headers).await?;
+        Ok(if manifest.live {
+            LiveStatus::Live
+        } else {
+            LiveStatus::Vod
+        })
+    }
+
+    /// 检查HLS直播是否在更新
+    ///
+    /// 间隔一个分片时长两次获取媒体播放列表，`EXT-X-MEDIA-SEQUENCE` 增加或出现新分片为直播，
+    /// 带有 `EXT-X-ENDLIST` 为点播，否则认为画面已冻结
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 播放列表地址
+    /// * `headers` - 请求头
+    ///
+    /// # 返回值
+    /// * `Result<LiveStatus, Error>` - 直播状态
+    pub async fn check_hls_liveness(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<LiveStatus, Error> {
+        let FetchedPlaylist {
+            url: media_url,
+            stage,
+            media: first,
+            ..
+        } = fetch_media_playlist(client, _url, headers).await?;
+        if first.end_list {
+            return Ok(LiveStatus::Vod);
+        }
+        let wait = if first.target_duration > 0.0 {
+            first.target_duration.clamp(1.0, LIVENESS_MAX_WAIT_SECS)
+        } else {
+            LIVENESS_DEFAULT_WAIT_SECS
+        };
+        tokio::time::sleep(Duration::from_secs_f32(wait)).await;
+        let (_, _, _, body) = fetch_playlist(client, &media_url, headers)
+            .await
+            .map_err(|e| hls_error(stage, e))?;
+        let second = match parse_playlist(&String::from_utf8_lossy(&body)) {
+            Some(HlsPlaylist::Media(media)) => media,
+            _ => {
+                return Err(hls_error(
+                    stage,
+                    CheckError::new(FailureReason::NotM3u8, "not a m3u8 file".to_string()),
+                ))
+            }
+        };
+        if second.end_list {
+            return Ok(LiveStatus::Vod);
+        }
+        let last_uri = |m: &HlsMediaPlaylist| m.segments.last().map(|s| s.uri.clone());
+        if second.media_sequence > first.media_sequence || last_uri(&second) != last_uri(&first) {
+            Ok(LiveStatus::Live)
+        } else {
+            Ok(LiveStatus::Frozen)
+        }
+    }
+
+    /// 测量HLS源的持续下载速度
+    ///
+    /// 从直播列表末尾连续下载 `segments` 个分片，统计下载速度、平均分片下载耗时，
+    /// 并与主播放列表中声明的 `BANDWIDTH`（没有时使用分片实际码率）比较
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 播放列表地址
+    /// * `headers` - 请求头
+    /// * `segments` - 下载的分片数量
+    ///
+    /// # 返回值
+    /// * `Result<ThroughputInfo, Error>` - 下载速度信息
+    pub async fn measure_throughput(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+        segments: usize,
+    ) -> Result<ThroughputInfo, Error> {
+        let playlist = fetch_media_playlist(client, _url, headers).await?;
+        let list = &playlist.media.segments;
+        if list.is_empty() {
+            return Err(hls_error(
+                playlist.stage,
+                CheckError::new(
+                    FailureReason::NoSegment,
+                    "playlist has no segment".to_string(),
+                ),
+            ));
+        }
+        let mut info = ThroughputInfo::new();
+        info.bandwidth = playlist.variant.map(|v| v.bandwidth).filter(|b| *b > 0);
+        let start = list.len().saturating_sub(segments.max(1));
+        for segment in &list[start..] {
+            let segment_url = resolve_uri(&playlist.url, &segment.uri);
+            let begin = time::Instant::now();
+            let (_, _, bytes) =
+                fetch_limited(client, &segment_url, headers, THROUGHPUT_SEGMENT_LIMIT)
+                    .await
+                    .map_err(|e| hls_error(HlsCheckStage::Segment, e))?;
+            info.download_time += begin.elapsed().as_millis() as i64;
+            info.bytes += bytes.len() as u64;
+            info.media_duration += segment.duration;
+            info.segments += 1;
+        }
+        info.calc();
+        Ok(info)
+    }
+
+    /// 记录各阶段耗时，深度检查时已经记录了第一个分片的下载耗时，否则单独下载一个分片
+    ///
+    /// DNS、连接、TLS、首字节耗时是直接连接目标测量的，使用代理时不测量，只记录经过代理下载分片的耗时
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 要检查的URL
+    /// * `headers` - 请求头
+    /// * `opt` - 检查参数
+    /// * `checked` - 检查过程中已记录的耗时
+    ///
+    /// # 返回值
+    /// * `Option<TimingInfo>` - 各阶段耗时
+    async fn check_timing(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+        opt: &CheckOptions,
+        checked: Option<TimingInfo>,
+    ) -> Option<TimingInfo> {
+        let parsed_url = Url::parse(_url).ok()?;
+        let mut timing = if opt.proxy.is_some() {
+            TimingInfo::new()
+        } else {
+            match measure_timing(&parsed_url, headers, opt.request_time as u64).await {
+                Ok(timing) => timing,
+                Err(e) => {
+                    debug!("measure timing {} failed: {}", _url, e);
+                    TimingInfo::new()
+                }
+            }
+        };
+        let first_segment = match checked.and_then(|t| t.first_segment) {
+            Some(first_segment) => Some(first_segment),
+            None => measure_throughput(client, _url, headers, 1)
+                .await
+                .ok()
+                .map(|t| t.download_time),
+        };
+        timing.set_first_segment(first_segment);
+        Some(timing)
+    }
+
+    /// 检查使用的http客户端
+    ///
+    /// 严格校验证书并且配置了例外域名时，例外域名使用另外一个不校验证书的客户端
+    #[derive(Clone)]
+    pub struct CheckClient {
+        client: reqwest::Client,
+        insecure: Option<reqwest::Client>,
+        tls: TlsConfig,
+    }
+
+    impl CheckClient {
+        /// 获取请求指定地址使用的客户端
+        pub fn for_url(&self, _url: &str) -> &reqwest::Client {
+            match &self.insecure {
+                Some(insecure) if !self.tls.verify_url(_url) => insecure,
+                _ => &self.client,
+            }
+        }
+    }
+
+    /// 创建检查使用的http客户端，同一批检查共享连接池
+    ///
+    /// # 参数
+    /// * `timeout` - 超时时间（毫秒）
+    /// * `proxy` - 检查使用的代理
+    pub fn new_check_client(
+        timeout: u64,
+        proxy: Option<&ProxyConfig>,
+    ) -> Result<CheckClient, Error> {
+        build_check_client(timeout, None, proxy)
+    }
+
+    /// # 参数
+    /// * `timeout` - 超时时间（毫秒）
+    /// * `resolve` - 将域名固定解析到指定地址
+    /// * `proxy` - 检查使用的代理
+    fn build_check_client(
+        timeout: u64,
+        resolve: Option<(&str, SocketAddr)>,
+        proxy: Option<&ProxyConfig>,
+    ) -> Result<CheckClient, Error> {
+        let base = get_base_config();
+        let (tls, dns) = (base.tls, base.dns);
+        let build = |verify: bool| -> Result<reqwest::Client, Error> {
+            let mut builder = reqwest::Client::builder()
+                .timeout(time::Duration::from_millis(timeout))
+                .pool_idle_timeout(time::Duration::from_secs(30))
+                .redirect(reqwest::redirect::Policy::none());
+            if let Some((host, addr)) = resolve {
+                builder = builder.resolve(host, addr);
+            }
+            builder = builder.dns_resolver(Arc::new(CachedResolver::new(dns.clone())));
+            builder = apply_proxy(builder, proxy)?;
+            tls.apply(builder, verify)?
+                .build()
+                .map_err(|e| Error::other(format!("http client build error {}", e)))
+        };
+        let client = build(tls.strict)?;
+        let insecure = if tls.strict && !tls.insecure_hosts.is_empty() {
+            Some(build(false)?)
+        } else {
+            None
+        };
+        Ok(CheckClient {
+            client,
+            insecure,
+            tls,
+        })
+    }
+
+    /// 指定连接的IP检查链接，用于分别验证每个地址族或每个IP是否可用
+    ///
+    /// 只做基本的可用性检查，不再执行ffprobe、直播状态、耗时记录和测速。
+    /// 使用代理时连接由代理发起，指定的IP不会生效，调用方需要跳过
+    ///
+    /// # 参数
+    /// * `_url` - 要检查的URL
+    /// * `headers` - 请求头
+    /// * `opt` - 检查参数
+    /// * `ip` - 连接的IP，URL中的域名会解析到该IP
+    ///
+    /// # 返回值
+    /// * `Result<CheckUrlIsAvailableResponse, Error>` - 检查结果
+    pub async fn check_link_by_address(
+        _url: String,
+        headers: &BTreeMap<String, String>,
+        opt: &CheckOptions,
+        ip: IpAddr,
+    ) -> Result<CheckUrlIsAvailableResponse, Error> {
+        let parsed_url = Url::parse(&_url)
+            .map_err(|e| CheckError::new(FailureReason::InvalidUrl, e.to_string()))?;
+        // 端口为0时使用URL中的端口
+        let resolve = parsed_url
+            .host_str()
+            .filter(|host| host.parse::<IpAddr>().is_err())
+            .map(|host| (host, SocketAddr::new(ip, 0)));
+        let client = build_check_client(opt.request_time as u64, resolve, opt.proxy.as_ref())?;
+        let mut basic = opt.clone();
+        basic.ffmpeg_check = false;
+        basic.liveness_check = false;
+        basic.timing_check = false;
+        basic.throughput_segments = 0;
+        basic.content_check = 0;
+        basic.fingerprint_check = false;
+        check_link(&client, _url, headers, &basic).await
+    }
+
+    /// 按域名限制并发数
+    pub struct HostLimiter {
+        per_host: usize,
+        hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+    }
+
+    impl HostLimiter {
+        /// # 参数
+        /// * `per_host` - 同一域名最大并发数，0表示不限制
+        pub fn new(per_host: usize) -> HostLimiter {
+            HostLimiter {
+                per_host,
+                hosts: Mutex::new(HashMap::new()),
+            }
+        }
+
+        /// 获取某个域名的许可，返回值被丢弃时释放
+        pub async fn acquire(&self, host: &str) -> Option<OwnedSemaphorePermit> {
+            if self.per_host == 0 {
+                return None;
+            }
+            let semaphore = {
+                let mut hosts = self.hosts.lock().unwrap();
+                hosts
+                    .entry(host.to_string())
+                    .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)))
+                    .clone()
+            };
+            semaphore.acquire_owned().await.ok()
+        }
+    }
+
+    /// 创建附带请求头的GET请求，无效的请求头会被忽略
+    pub fn request_with_headers(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> reqwest::RequestBuilder {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                (Ok(name), Ok(value)) => {
+                    header_map.insert(name, value);
+                }
+                _ => debug!("invalid header {}: {}", name, value),
+            }
+        }
+        client.for_url(_url).get(_url).headers(header_map)
+    }
+
+    /// 检查链接是否有效
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 要检查的URL
+    /// * `headers` - 请求头
+    /// * `opt` - 检查参数，使用其中的超时时间、是否使用FFmpeg检查、是否跳过不支持探测的非HTTP链接、
+    ///   是否深度检查HLS、是否检测直播状态、是否记录各阶段耗时以及测速分片数
+    ///
+    /// # 返回值
+    /// * `Result<CheckUrlIsAvailableResponse, Error>` - 检查结果
+    pub async fn check_link_is_valid(
+        client: &CheckClient,
+        _url: String,
+        headers: &BTreeMap<String, String>,
+        opt: &CheckOptions,
+    ) -> Result<CheckUrlIsAvailableResponse, Error> {
+        let mut res = check_link(client, _url.clone(), headers, opt).await?;
+        // DASH深度检查时已经根据清单类型记录了直播状态
+        if opt.liveness_check && _url.starts_with("http") && res.live_status.is_none() {
+            res.live_status = if is_dash_url(&_url) {
+                check_dash_liveness(client, &_url, headers).await.ok()
+            } else {
+                match check_hls_liveness(client, &_url, headers).await {
+                    Err(e) if is_not_playlist(&e) => {
+                        check_dash_liveness(client, &_url, headers).await.ok()
+                    }
+                    status => status.ok(),
+                }
+            };
+        }
+        if opt.timing_check && _url.starts_with("http") {
+            res.timing = check_timing(client, &_url, headers, opt, res.timing.take()).await;
+        }
+        if opt.throughput_segments > 0 && _url.starts_with("http") {
+            res.throughput =
+                measure_throughput(client, &_url, headers, opt.throughput_segments as usize)
+                    .await
+                    .ok();
+        }
+        if opt.fingerprint_check {
+            res.fingerprint =
+                Some(check_fingerprint(client, &_url, headers, opt, res.fingerprint.take()).await);
+        }
+        if opt.content_check > 0 {
+            res.content_health = check_content_health(
+                &_url,
+                opt.content_check as u64,
+                opt.request_time as u64,
+                headers,
+                opt.proxy.as_ref(),
+            )
+            .await
+            .ok();
+        }
+        if _url.starts_with("http") {
+            res.proxy = opt.proxy.as_ref().map(|p| p.display_url());
+        }
+        Ok(res)
+    }
+
+    /// 生成内容指纹，HLS源使用最新分片的哈希，并截取一帧计算画面哈希
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `_url` - 源地址
+    /// * `headers` - 请求头
+    /// * `opt` - 检查参数
+    /// * `fingerprint` - 深度检查时已经生成的指纹
+    async fn check_fingerprint(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+        opt: &CheckOptions,
+        fingerprint: Option<Fingerprint>,
+    ) -> Fingerprint {
+        let mut fingerprint = match fingerprint {
+            Some(fingerprint) => fingerprint,
+            None if _url.starts_with("http") => fetch_segment_fingerprint(client, _url, headers)
+                .await
+                .unwrap_or_default(),
+            None => Fingerprint::new(),
+        };
+        fingerprint.set_frame_hash(
+            capture_frame_hash(
+                _url,
+                headers,
+                opt.request_time.max(1000) as u64,
+                opt.proxy.as_ref(),
+            )
+            .await,
+        );
+        fingerprint
+    }
+
+    /// 下载HLS最新的分片并生成指纹
+    async fn fetch_segment_fingerprint(
+        client: &CheckClient,
+        _url: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<Fingerprint, Error> {
+        let playlist = fetch_media_playlist(client, _url, headers).await?;
+        let segment = playlist.media.segments.last().ok_or_else(|| {
+            Error::from(CheckError::new(
+                FailureReason::NoSegment,
+                "playlist has no segment".to_string(),
+            ))
+        })?;
+        let segment_url = resolve_uri(&playlist.url, &segment.uri);
+        let (_, _, bytes) =
+            fetch_limited(client, &segment_url, headers, DEEP_CHECK_SEGMENT_LIMIT).await?;
+        Ok(Fingerprint::from_segment(&bytes))
+    }
+
+    async fn check_link(
+        client: &CheckClient,
+        _url: String,
+        headers: &BTreeMap<String, String>,
+        opt: &CheckOptions,
+    ) -> Result<CheckUrlIsAvailableResponse, Error> {
+        let timeout = opt.request_time as u64;
+        let proxy = opt.proxy.as_ref();
+        let (ffmpeg_check, not_http_skip, deep_check) =
+            (opt.ffmpeg_check, opt.not_http_skip, opt.deep_check);
+        if ffmpeg_check && !deep_check {
+            // 优先直接解析HLS/DASH分片获取音视频信息，解析不到时再使用ffprobe
+            if _url.starts_with("http") {
+                match deep_check_stream(client, _url.to_owned(), headers).await {
+                    Ok(res) if res.ffmpeg_info.is_some() => return Ok(res),
+                    Ok(_) => {}
+                    // 直接的媒体流或无法识别的分片格式交给ffprobe，网络错误等直接返回
+                    Err(e) if is_not_playlist(&e) || is_unknown_container(&e) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            return run_command_with_timeout_new(_url.to_owned(), timeout, headers, proxy).await;
+        }
+        let parsed_info = Url::parse(&_url);
+        match parsed_info {
+            Ok(parsed_url) => {
+                if is_probe_scheme(parsed_url.scheme()) {
+                    // rtsp/rtmp/udp/rtp 使用内置的协议探测
+                    let delay = probe_stream(&parsed_url, timeout).await?;
+                    let mut res = CheckUrlIsAvailableResponse::new();
+                    res.set_delay(delay as i32);
+                    return Ok(res);
+                }
+                if parsed_url.scheme() != "https" && parsed_url.scheme() != "http" {
+                    return if not_http_skip {
+                        Ok(CheckUrlIsAvailableResponse::new())
+                    } else {
+                        Err(CheckError::new(
+                            FailureReason::UnsupportedScheme,
+                            "scheme not http, temporary not support".to_string(),
+                        )
+                        .into())
+                    };
+                }
+            }
+            Err(e) => {
+                return Err(
+                    CheckError::new(FailureReason::InvalidUrl, format!("error {}", e)).into(),
+                );
+            }
+        }
+        if deep_check {
+            let mut res = deep_check_stream(client, _url.to_owned(), headers).await?;
+            if ffmpeg_check && res.ffmpeg_info.is_none() {
+                // 分片中解析不到音视频信息时，再用ffprobe补充
+                if let Ok(ff) =
+                    run_command_with_timeout_new(_url.to_owned(), timeout, headers, proxy).await
+                {
+                    res.ffmpeg_info = ff.ffmpeg_info;
+                }
+            }
+            return Ok(res);
+        }
+        let (redirects, final_url, delay, body) = fetch_playlist(client, &_url, headers).await?;
+        let body = String::from_utf8_lossy(&body).to_string();
+        // 主播放列表和MPD清单中已经声明了各码率的分辨率，不需要ffmpeg也能得到清晰度
+        let declared = if check_body_is_dash_format(&body) {
+            parse_mpd(&body).map(|manifest| manifest.ffmpeg_info())
+        } else {
+            match parse_playlist(&body) {
+                Some(HlsPlaylist::Master(variants)) => {
+                    let mut info = FfmpegInfo::new();
+                    info.set_video(master_video_info(&final_url, &variants));
+                    Some(info)
+                }
+                _ => None,
+            }
+        };
+        if !check_body_is_dash_format(&body) && !check_body_is_m3u8_format(body) {
+            return Err(CheckError::new(
+                FailureReason::NotM3u8,
+                "not a m3u8 or mpd file".to_string(),
+            )
+            .into());
+        }
+        let mut body = CheckUrlIsAvailableResponse::new();
+        body.set_delay(delay as i32);
+        body.redirect = Some(RedirectInfo::new(&_url, redirects));
+        if let Some(info) = declared.and_then(|info| merge_declared_info(info, None)) {
+            body.set_ffmpeg_info(info);
+        }
+        Ok(body)
+    }
+
+    /// 测试模块
+    mod tests {
+        // use crate::common::check::check::run_command_with_timeout_new;
+        // use std::sync::mpsc;
+        // use std::thread;
+        // #[tokio::test]
+        // async fn test_timeout() {
+        //     let (tx, rx) = mpsc::channel();
+        //
+        //     // 模拟从channel里收到一条命令
+        //     thread::spawn(move || {
+        //         tx.send(("https://cd-live-stream.news.cctvplus.com/live/smil:CHANNEL2.smil/playlist.m3u8", 5000)).unwrap();
+        //         // 比如要执行sleep 5秒
+        //     });
+        //
+        //     if let Ok((_url, timeout)) = rx.recv() {
+        //         println!("Running command: {} {:?}", _url, timeout);
+        //         match run_command_with_timeout_new(_url.to_string(), (timeout as u64)).await {
+        //             Ok(ed) => {
+        //                 let v = ed.video.clone().unwrap();
+        //                 println!("Command finished successfully.{} {}", v.width, v.height)
+        //             }
+        //             Err(e) => println!("Command failed: {}", e),
+        //         }
+        //     }
+        // }
+    }
+}
+
+pub async fn get_favourite_channel(channel_type: String) -> Result<String, Error> {
+    // 获取今日日期对应目录
+    let today = chrono::Local::now().format("%Y%m%d").to_string();
+    let search_path = format!("{}/{}", INPUT_SEARCH_FOLDER, today);
+
+    let mut all_files = Vec::new();
+    let dir_entries = match std::fs::read_dir(&search_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return Ok("".to_string());
+        }
+    };
+
+    for entry in dir_entries {
+        match entry {
+            Ok(entry) => {
+                let path = entry.path();
+                if path.is_file() {
+                    if let Some(file_str) = path.to_str() {
+                        all_files.push(file_str.to_string());
+                    }
+                }
+            }
+            Err(e) => {
+                // 遇到单个文件错误也继续处理其他文件
+                continue;
             }
         }
     }
 
     // 将文件转换为数组
-    let list = common::m3u::m3u::from_arr(all_files.to_owned(), 0).await;
+    let list = common::m3u::m3u::from_arr(all_files.to_owned(), 0, None).await;
     // 将数组转换为对象
     let mut data = list_str2obj(list, false);
     // 将频道名繁体转简体
@@ -530,74 +1880,50 @@ pub async fn get_favourite_channel(channel_type: String) -> Result<String, Error
     })
     .await;
     let rename_channel_type = 0;
-    return Ok(data.get_m3u_content_str(rename_channel_type,false));
+    return Ok(data.get_m3u_content_str(rename_channel_type, false));
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn do_check(
     input_files: Vec<String>,
     output_id: String,
     timeout: i32,
     print_result: bool,
-    request_timeout: i32,
-    concurrent: i32,
-    keyword_like: Vec<String>,
-    keyword_dislike: Vec<String>,
-    sort: bool,
-    no_check: bool,
     rename: bool,
-    ffmpeg_check: bool,
-    same_save_num: i32,
-    not_http_skip: bool,
-    video_quality: Vec<String>,
-    export_file: bool,
-    rename_channel_type: i8,
+    search: SearchOptions,
+    opt: CheckOptions,
+    export: Option<i8>,
 ) -> Result<bool, Error> {
     // 将文件转换为数组
-    let list = common::m3u::m3u::from_arr(input_files.to_owned(), timeout as u64).await;
+    let list =
+        common::m3u::m3u::from_arr(input_files.to_owned(), timeout as u64, opt.proxy.as_ref())
+            .await;
     // 将数组转换为对象
     let mut data = list_str2obj(list, false);
-    // 获取域名对应的ip类型
-    data.to_ip_address();
+    // 并发解析域名，获取对应的ip类型
+    data.to_ip_address().await;
     // 将频道名繁体转简体
     data.t2s();
     if rename {
         // 去除name中无效的字符
         data.remove_useless_char();
     }
-    // 搜索关键字
-    data.search(SearchOptions {
-        keyword_full_match: vec![],
-        keyword_like,
-        keyword_dislike,
-        ipv4: false,
-        ipv6: false,
-        exclude_url: vec![],
-        exclude_host: vec![],
-        quality: vec![],
-    })
-    .await;
+    // 清晰度要检查后才知道，先只按关键字搜索
+    let quality = search.quality.clone();
+    data.search(search).await;
     // 检查数据
-    data.check_data_new(CheckOptions {
-        request_time: request_timeout,
-        concurrent,
-        sort,
-        no_check,
-        ffmpeg_check,
-        same_save_num,
-        not_http_skip,
-    })
-    .await;
-    println!("entry video quality {:?}", video_quality.clone());
-    if ffmpeg_check {
-        data.search_video_quality(from_video_resolution(video_quality));
-    }
+    let no_check = opt.no_check;
+    data.check_data_new(opt).await;
+    println!("entry video quality {:?}", quality);
+    // 普通检查时主播放列表和MPD清单也会记录清晰度，所以总是按清晰度过滤
+    data.search_video_quality(quality);
     let output_file = format!("{}{}.json", OUTPUT_FOLDER, output_id);
     if print_result {
         info!("输出文件: {}", output_file);
     }
     data.save_raw_data(output_file);
     // 导出数据
-    if export_file {
+    if let Some(rename_channel_type) = export {
         data.output_file(
             format!("{}{}.m3u", OUTPUT_FOLDER, output_id),
             true,
@@ -610,7 +1936,7 @@ pub async fn do_check(
             let status_string = data.print_result();
             info!("\n{}", status_string);
         }
-        info!("解析完成----")
+        info!("解析完成----");
     }
     Ok(true)
 }
@@ -619,8 +1945,97 @@ pub async fn do_check(
 #[cfg(test)]
 mod tests {
     use crate::common::check::check::run_command_with_timeout_new;
+    use crate::common::hls::HlsCheckStage;
+    use crate::common::{CheckDataStatus, CheckError, FailureReason};
+    use std::collections::BTreeMap;
+    use std::net::SocketAddr;
     use std::sync::mpsc;
+    use std::sync::Arc;
     use std::thread;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// 只有一个分片的媒体播放列表
+    const MEDIA_PLAYLIST: &str = "#EXTM3U\n#EXTINF:5,\n1.ts\n";
+
+    /// 本地http测试服务的响应
+    struct TestResponse {
+        status: String, // 状态行，可以追加响应头，如 "302 Found\r\nlocation: /a"
+        body: Vec<u8>,
+        delay: u64,    // 返回前等待的毫秒数
+        endless: bool, // 不带长度一直重复发送响应体，模拟直接的直播流
+    }
+
+    impl TestResponse {
+        fn new(status: &str, body: impl Into<Vec<u8>>) -> TestResponse {
+            TestResponse {
+                status: status.to_string(),
+                body: body.into(),
+                delay: 0,
+                endless: false,
+            }
+        }
+
+        fn ok(body: impl Into<Vec<u8>>) -> TestResponse {
+            TestResponse::new("200 OK", body)
+        }
+
+        fn delay(mut self, delay: u64) -> TestResponse {
+            self.delay = delay;
+            self
+        }
+
+        fn endless(mut self) -> TestResponse {
+            self.endless = true;
+            self
+        }
+    }
+
+    /// 启动本地http测试服务，每个连接只处理一个请求
+    ///
+    /// # 参数
+    /// * `route` - 根据请求路径（代理请求时为完整地址）和请求报文返回响应
+    ///
+    /// # 返回值
+    /// * `SocketAddr` - 监听地址
+    async fn serve_http<F>(route: F) -> SocketAddr
+    where
+        F: Fn(&str, &str) -> TestResponse + Send + Sync + 'static,
+    {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let route = Arc::new(route);
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let route = route.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let req = String::from_utf8_lossy(&buf[..n]).to_string();
+                    let path = req.split_whitespace().nth(1).unwrap_or("");
+                    let resp = route(path, &req);
+                    if resp.delay > 0 {
+                        tokio::time::sleep(std::time::Duration::from_millis(resp.delay)).await;
+                    }
+                    if resp.endless {
+                        let head = format!("HTTP/1.1 {}\r\n\r\n", resp.status);
+                        let _ = socket.write_all(head.as_bytes()).await;
+                        while socket.write_all(&resp.body).await.is_ok() {}
+                        return;
+                    }
+                    let head = format!(
+                        "HTTP/1.1 {}\r\ncontent-length: {}\r\n\r\n",
+                        resp.status,
+                        resp.body.len()
+                    );
+                    let _ = socket.write_all(head.as_bytes()).await;
+                    let _ = socket.write_all(&resp.body).await;
+                });
+            }
+        });
+        addr
+    }
+
     #[tokio::test]
     async fn test_timeout() {
         let (tx, rx) = mpsc::channel();
@@ -636,7 +2051,14 @@ mod tests {
 
         if let Ok((_url, timeout)) = rx.recv() {
             println!("Running command: {} {:?}", _url, timeout);
-            match run_command_with_timeout_new(_url.to_string(), (timeout as u64)).await {
+            match run_command_with_timeout_new(
+                _url.to_string(),
+                (timeout as u64),
+                &BTreeMap::new(),
+                None,
+            )
+            .await
+            {
                 Ok(ed) => {
                     for v in ed.ffmpeg_info.unwrap().video.clone() {
                         println!("Command finished successfully.{} {}", v.width, v.height)
@@ -646,4 +2068,635 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn check_error_in_io_error() {
+        let mut err = CheckError::new(FailureReason::HttpStatus, "status is 404".to_string());
+        err.set_http_status(404);
+        err.set_stage(HlsCheckStage::Segment);
+        let io_err: std::io::Error = err.into();
+        let err = CheckError::from_io_error(&io_err).unwrap();
+        assert_eq!(err.reason, FailureReason::HttpStatus);
+        assert_eq!(err.http_status, Some(404));
+        assert_eq!(err.stage, Some(HlsCheckStage::Segment));
+
+        let io_err: std::io::Error =
+            CheckError::new(FailureReason::Timeout, "timeout".to_string()).into();
+        assert_eq!(io_err.kind(), std::io::ErrorKind::TimedOut);
+        assert!(CheckError::from_io_error(&std::io::Error::other("x")).is_none());
+    }
+
+    #[tokio::test]
+    async fn classify_connect_error() {
+        // 本地未监听的端口，连接应被拒绝
+        let client = reqwest::Client::new();
+        let e = client.get("http://127.0.0.1:1/").send().await.unwrap_err();
+        assert_eq!(CheckError::from_reqwest(&e).reason, FailureReason::Connect);
+        let e = client
+            .get("http://nonexistent.invalid/")
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(CheckError::from_reqwest(&e).reason, FailureReason::Dns);
+    }
+
+    #[tokio::test]
+    async fn host_limiter_caps_concurrency() {
+        use crate::common::check::check::HostLimiter;
+        use futures::future::join_all;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let limiter = HostLimiter::new(2);
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        join_all((0..8).map(|_| async {
+            let _permit = limiter.acquire("a.com").await;
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+        }))
+        .await;
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn check_list_keeps_original_order() {
+        use crate::common::m3u::m3u::list_str2obj;
+        use crate::common::CheckOptions;
+
+        // 本地http服务，/slow 延迟返回，/404 返回错误，/ua 需要指定User-Agent
+        let addr = serve_http(|path, req| {
+            let need_ua =
+                path.starts_with("/ua") && !req.to_lowercase().contains("user-agent: iptv-test");
+            if path.starts_with("/404") || need_ua {
+                TestResponse::new("404 Not Found", vec![])
+            } else if path.starts_with("/slow") {
+                TestResponse::ok(MEDIA_PLAYLIST).delay(200)
+            } else {
+                TestResponse::ok(MEDIA_PLAYLIST)
+            }
+        })
+        .await;
+
+        let body = ["slow", "fast", "404", "ua"]
+            .iter()
+            .map(|p| format!("{},http://{}/{}/index.m3u8", p, addr, p))
+            .collect::<Vec<String>>()
+            .join("\n")
+            + "|User-Agent=iptv-test";
+        let data = list_str2obj(vec![body], false);
+        let opt = CheckOptions {
+            request_time: 3000,
+            concurrent: 4,
+            per_host_concurrent: 4,
+            ..Default::default()
+        };
+        let mut finished = vec![];
+        let list = data
+            .check_list(&opt, None, |item| finished.push(item.get_url()))
+            .await;
+        let urls = list.iter().map(|i| i.get_url()).collect::<Vec<String>>();
+        assert!(urls[0].contains("/slow/"));
+        assert!(urls[1].contains("/fast/"));
+        assert!(urls[2].contains("/404/"));
+        // 慢的源最后完成
+        assert!(finished[3].contains("/slow/"));
+        let failed = list
+            .iter()
+            .filter(|i| i.get_status() == CheckDataStatus::Failed)
+            .count();
+        assert_eq!(failed, 1);
+    }
+
+    #[tokio::test]
+    async fn deep_check_stops_on_raw_stream() {
+        use crate::common::check::check::{deep_check_stream, new_check_client};
+
+        // /live.ts 是不会结束的TS直播流，/live.flv 是FLV直播流，/huge 是不会结束的文本
+        let addr = serve_http(|path, _| match path {
+            "/live.ts" => TestResponse::ok(vec![0x47u8; 188 * 16]).endless(),
+            "/live.flv" => TestResponse::ok(b"FLV\x01\x05\0\0\0\x09".repeat(100)).endless(),
+            _ => TestResponse::ok(vec![b'#'; 64 * 1024]).endless(),
+        })
+        .await;
+
+        let client = new_check_client(10000, None).unwrap();
+        for path in ["live.ts", "live.flv", "huge"] {
+            let start = std::time::Instant::now();
+            let err = deep_check_stream(
+                &client,
+                format!("http://{}/{}", addr, path),
+                &BTreeMap::new(),
+            )
+            .await
+            .unwrap_err();
+            assert!(start.elapsed() < std::time::Duration::from_secs(5));
+            let err = CheckError::from_io_error(&err).unwrap();
+            assert_eq!(err.reason, FailureReason::NotM3u8);
+            assert_eq!(err.stage, Some(HlsCheckStage::Playlist));
+        }
+    }
+
+    #[tokio::test]
+    async fn ffmpeg_check_returns_network_errors() {
+        use crate::common::check::check::{check_link_is_valid, new_check_client};
+        use crate::common::CheckOptions;
+
+        let addr = serve_http(|_, _| TestResponse::new("404 Not Found", vec![])).await;
+        let opt = CheckOptions {
+            request_time: 3000,
+            concurrent: 1,
+            ffmpeg_check: true,
+            ..Default::default()
+        };
+        let client = new_check_client(3000, None).unwrap();
+        // 源已失效时不再调用ffprobe
+        let err = check_link_is_valid(
+            &client,
+            format!("http://{}/live.m3u8", addr),
+            &BTreeMap::new(),
+            &opt,
+        )
+        .await
+        .unwrap_err();
+        let err = CheckError::from_io_error(&err).unwrap();
+        assert_eq!(err.reason, FailureReason::HttpStatus);
+        assert_eq!(err.http_status, Some(404));
+    }
+
+    #[tokio::test]
+    async fn hls_liveness_status() {
+        use crate::common::check::check::{check_hls_liveness, new_check_client};
+        use crate::common::hls::LiveStatus;
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        // /live 每次请求媒体序号加一，/frozen 保持不变，/vod 带有结束标记
+        let counter = AtomicU64::new(0);
+        let addr = serve_http(move |path, _| {
+            let (seq, end) = if path.starts_with("/live") {
+                (counter.fetch_add(1, Ordering::SeqCst), "")
+            } else if path.starts_with("/vod") {
+                (0, "#EXT-X-ENDLIST\n")
+            } else {
+                (0, "")
+            };
+            TestResponse::ok(format!(
+                "#EXTM3U\n#EXT-X-TARGETDURATION:1\n#EXT-X-MEDIA-SEQUENCE:{}\n#EXTINF:1,\n{}.ts\n{}",
+                seq, seq, end
+            ))
+        })
+        .await;
+
+        let client = new_check_client(3000, None).unwrap();
+        let headers = BTreeMap::new();
+        let live_url = format!("http://{}/live.m3u8", addr);
+        let frozen_url = format!("http://{}/frozen.m3u8", addr);
+        let vod_url = format!("http://{}/vod.m3u8", addr);
+        let (live, frozen, vod) = tokio::join!(
+            check_hls_liveness(&client, &live_url, &headers),
+            check_hls_liveness(&client, &frozen_url, &headers),
+            check_hls_liveness(&client, &vod_url, &headers),
+        );
+        assert_eq!(live.unwrap(), LiveStatus::Live);
+        assert_eq!(frozen.unwrap(), LiveStatus::Frozen);
+        assert_eq!(vod.unwrap(), LiveStatus::Vod);
+    }
+
+    #[tokio::test]
+    async fn deep_check_dash_manifest() {
+        use crate::common::check::check::{deep_check_stream, new_check_client};
+        use crate::common::hls::LiveStatus;
+
+        // /channel 没有 .mpd 后缀但内容是直播MPD，/broken.mpd 的分片不是媒体数据
+        let addr = serve_http(|path, _| {
+            if path == "/channel" || path == "/broken.mpd" {
+                let media = if path == "/channel" { "seg" } else { "html" };
+                TestResponse::ok(format!(
+                    r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="dynamic">
+  <Period><AdaptationSet mimeType="video/mp4" codecs="avc1.640028">
+    <SegmentTemplate timescale="1000" initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/{}-$Number$.m4s" startNumber="7">
+      <SegmentTimeline><S t="0" d="2000" r="2"/></SegmentTimeline>
+    </SegmentTemplate>
+    <Representation id="sd" bandwidth="1000000" width="854" height="480"/>
+    <Representation id="hd" bandwidth="4000000" width="1920" height="1080"/>
+  </AdaptationSet></Period>
+</MPD>"#,
+                    media
+                ))
+            } else if path.ends_with("init.mp4") {
+                TestResponse::ok(b"\0\0\0\x10ftypiso6\0\0\0\0".to_vec())
+            } else if path == "/sd/seg-9.m4s" {
+                TestResponse::ok(b"\0\0\0\x10stypmsdh\0\0\0\0".to_vec())
+            } else if path.starts_with("/sd/html") {
+                TestResponse::ok(b"<html>not found</html>".to_vec())
+            } else {
+                TestResponse::new("404 Not Found", vec![])
+            }
+        })
+        .await;
+
+        let client = new_check_client(3000, None).unwrap();
+        let headers = BTreeMap::new();
+        let res = deep_check_stream(&client, format!("http://{}/channel", addr), &headers)
+            .await
+            .unwrap();
+        assert_eq!(res.live_status, Some(LiveStatus::Live));
+        let info = res.ffmpeg_info.unwrap();
+        assert_eq!(info.video.len(), 2);
+        assert_eq!(info.video[0].height, 1080);
+        assert_eq!(info.video[0].codec, "h264");
+        assert_eq!(info.video[0].bandwidth, Some(4000000));
+
+        let err = deep_check_stream(&client, format!("http://{}/broken.mpd", addr), &headers)
+            .await
+            .unwrap_err();
+        let err = CheckError::from_io_error(&err).unwrap();
+        assert_eq!(err.reason, FailureReason::InvalidSegment);
+        assert_eq!(err.stage, Some(HlsCheckStage::SegmentFormat));
+    }
+
+    #[tokio::test]
+    async fn deep_check_encrypted_hls() {
+        use crate::common::check::check::{deep_check_hls, new_check_client};
+
+        // 分片是AES-128加密后的数据，/good.key 返回16字节密钥，/expired.key 返回网页
+        let addr = serve_http(|path, _| match path {
+            "/good.m3u8" | "/expired.m3u8" => TestResponse::ok(format!(
+                "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-KEY:METHOD=AES-128,URI=\"{}.key\"\n#EXTINF:6,\n1.ts\n",
+                path.trim_end_matches(".m3u8")
+            )),
+            "/good.key" => TestResponse::ok(vec![7u8; 16]),
+            "/expired.key" => TestResponse::ok(b"<html>token expired</html>".to_vec()),
+            _ => TestResponse::ok(vec![0x5au8; 1024]),
+        })
+        .await;
+
+        let client = new_check_client(3000, None).unwrap();
+        let headers = BTreeMap::new();
+        let good = deep_check_hls(&client, format!("http://{}/good.m3u8", addr), &headers)
+            .await
+            .unwrap();
+        let encryption = good.encryption.unwrap();
+        assert_eq!(encryption.method, "AES-128");
+        assert_eq!(
+            encryption.key_uri,
+            Some(format!("http://{}/good.key", addr))
+        );
+        assert_eq!(encryption.key_reachable, Some(true));
+        assert!(!encryption.is_drm());
+
+        let expired = deep_check_hls(&client, format!("http://{}/expired.m3u8", addr), &headers)
+            .await
+            .unwrap();
+        let encryption = expired.encryption.unwrap();
+        assert!(encryption.is_key_unreachable());
+        assert_eq!(encryption.key_failure, Some(FailureReason::Body));
+    }
+
+    #[tokio::test]
+    async fn check_through_http_proxy() {
+        use crate::common::check::check::{check_link_is_valid, new_check_client};
+        use crate::common::proxy::ProxyConfig;
+        use crate::common::CheckOptions;
+
+        // 本地http代理，只响应带认证信息的绝对地址请求，源地址的域名无法直接解析
+        let addr = serve_http(|target, req| {
+            // dXNlcjpwYXNz 是 user:pass
+            let authorized = req
+                .to_lowercase()
+                .contains("proxy-authorization: basic dxnlcjpwyxnz");
+            match target {
+                _ if !authorized => TestResponse::new("407 Proxy Authentication Required", vec![]),
+                "http://stream.invalid/live.m3u8" => TestResponse::ok(
+                    b"#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6,\n1.ts\n".to_vec(),
+                ),
+                "http://stream.invalid/1.ts" => TestResponse::ok(vec![0x47u8; 188 * 4]),
+                _ => TestResponse::new("404 Not Found", vec![]),
+            }
+        })
+        .await;
+
+        let mut proxy = ProxyConfig {
+            url: format!("http://{}", addr),
+            username: "user".to_string(),
+            password: "pass".to_string(),
+        };
+        let opt = CheckOptions {
+            request_time: 3000,
+            concurrent: 1,
+            deep_check: true,
+            proxy: Some(proxy.clone()),
+            ..Default::default()
+        };
+        let headers = BTreeMap::new();
+        let client = new_check_client(3000, Some(&proxy)).unwrap();
+        let res = check_link_is_valid(
+            &client,
+            "http://stream.invalid/live.m3u8".to_string(),
+            &headers,
+            &opt,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.proxy, Some(format!("http://{}", addr)));
+
+        // 认证信息错误时代理拒绝请求
+        proxy.password = "wrong".to_string();
+        let client = new_check_client(3000, Some(&proxy)).unwrap();
+        let err = check_link_is_valid(
+            &client,
+            "http://stream.invalid/live.m3u8".to_string(),
+            &headers,
+            &opt,
+        )
+        .await
+        .unwrap_err();
+        let err = CheckError::from_io_error(&err).unwrap();
+        assert_eq!(err.http_status, Some(407));
+    }
+
+    #[tokio::test]
+    async fn deep_check_records_redirect_chain() {
+        use crate::common::check::check::{deep_check_hls, new_check_client};
+
+        // /live.m3u8 先跳转到调度地址，再跳转到带 txTime 的CDN地址
+        let addr = serve_http(|path, _| match path {
+            "/live.m3u8" => TestResponse::new("302 Found\r\nlocation: /gslb?id=1", vec![]),
+            "/gslb?id=1" => TestResponse::new(
+                "302 Found\r\nlocation: /cdn/live.m3u8?wsSecret=x&txTime=6710B0C0",
+                vec![],
+            ),
+            "/cdn/live.m3u8?wsSecret=x&txTime=6710B0C0" => {
+                TestResponse::ok(b"#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6,\n1.ts\n".to_vec())
+            }
+            _ => TestResponse::ok(vec![0x47u8; 188 * 4]),
+        })
+        .await;
+
+        let client = new_check_client(3000, None).unwrap();
+        let res = deep_check_hls(
+            &client,
+            format!("http://{}/live.m3u8", addr),
+            &BTreeMap::new(),
+        )
+        .await
+        .unwrap();
+        let redirect = res.redirect.unwrap();
+        assert_eq!(
+            redirect.chain,
+            vec![
+                format!("http://{}/gslb?id=1", addr),
+                format!("http://{}/cdn/live.m3u8?wsSecret=x&txTime=6710B0C0", addr),
+            ]
+        );
+        assert_eq!(redirect.final_url, redirect.chain[1]);
+        assert_eq!(redirect.expiry_param, Some("txTime".to_string()));
+        assert_eq!(redirect.expires_at, Some(0x6710B0C0));
+    }
+
+    #[tokio::test]
+    async fn measure_throughput_with_declared_bandwidth() {
+        use crate::common::check::check::{measure_throughput, new_check_client};
+
+        // 主播放列表声明 800kbit/s，媒体播放列表有3个1秒的分片，每个分片 100KB
+        let addr = serve_http(|path, _| match path {
+            "/master.m3u8" => {
+                TestResponse::ok(b"#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=800000\nmedia.m3u8\n".to_vec())
+            }
+            "/media.m3u8" => TestResponse::ok(
+                b"#EXTM3U\n#EXT-X-TARGETDURATION:1\n#EXTINF:1,\n0.ts\n#EXTINF:1,\n1.ts\n#EXTINF:1,\n2.ts\n".to_vec(),
+            ),
+            _ => TestResponse::ok(vec![0x47u8; 100 * 1024]),
+        })
+        .await;
+
+        let client = new_check_client(3000, None).unwrap();
+        let url = format!("http://{}/master.m3u8", addr);
+        let info = measure_throughput(&client, &url, &BTreeMap::new(), 2)
+            .await
+            .unwrap();
+        assert_eq!(info.segments, 2);
+        assert_eq!(info.bytes, 2 * 100 * 1024);
+        assert_eq!(info.media_duration, 2.0);
+        assert_eq!(info.bandwidth, Some(800000));
+        assert!(info.throughput > 0);
+        let ratio = info.ratio.unwrap();
+        assert!((ratio - info.throughput as f32 / 800000.0).abs() < 0.01);
+    }
+
+    #[tokio::test]
+    async fn family_check_records_each_address() {
+        use crate::common::m3u::m3u::list_str2obj;
+        use crate::common::{CheckOptions, ExportFilter, OtherStatus};
+        use std::collections::HashMap;
+
+        // 只监听IPv4，IPv6地址无法连接
+        let port = serve_http(|_, _| TestResponse::ok(MEDIA_PLAYLIST))
+            .await
+            .port();
+
+        let body = format!("cctv,http://iptv-test.local:{}/index.m3u8", port);
+        let mut data = list_str2obj(vec![body], false);
+        let mut list = data.clone().get_list();
+        let mut status = OtherStatus::new();
+        status.set_ip_address(vec!["127.0.0.1".to_string(), "::1".to_string()]);
+        list[0].set_other_status(status);
+        let opt = CheckOptions {
+            request_time: 3000,
+            concurrent: 1,
+            family_check: 1,
+            ..Default::default()
+        };
+        let client = crate::common::check::check::new_check_client(3000, None).unwrap();
+        list[0].check(&client, &opt).await;
+        let other_status = list[0].get_other_status();
+        assert!(other_status.is_address_checked());
+        assert!(other_status.is_family_available(false));
+        assert!(!other_status.is_family_available(true));
+
+        data.set_list(list);
+        let export = |ip_type| {
+            let mut filter = ExportFilter::new();
+            filter.set_ip_type(ip_type);
+            data.clone()
+                .export(String::new(), HashMap::new(), false, 0, filter)
+        };
+        assert!(export(1).contains("iptv-test.local"));
+        assert!(!export(2).contains("iptv-test.local"));
+    }
+
+    #[tokio::test]
+    async fn retry_marks_flaky_sources() {
+        use crate::common::m3u::m3u::list_str2obj;
+        use crate::common::{CheckOptions, ExportFilter, RetryPolicy};
+        use std::collections::HashMap;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        // /flaky 第一次请求超时，之后正常返回；/404 始终返回404
+        let first = AtomicBool::new(true);
+        let addr = serve_http(move |path, _| {
+            if path.starts_with("/404") {
+                TestResponse::new("404 Not Found", vec![])
+            } else if path.starts_with("/flaky") && first.swap(false, Ordering::SeqCst) {
+                TestResponse::ok(MEDIA_PLAYLIST).delay(1000)
+            } else {
+                TestResponse::ok(MEDIA_PLAYLIST)
+            }
+        })
+        .await;
+
+        let body = ["flaky", "stable", "404"]
+            .iter()
+            .map(|p| format!("cctv,http://{}/{}/index.m3u8", addr, p))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let mut data = list_str2obj(vec![body], false);
+        let mut retry = RetryPolicy::new();
+        retry.set_attempts(2);
+        retry.set_backoff(10);
+        let opt = CheckOptions {
+            request_time: 300,
+            concurrent: 3,
+            retry,
+            ..Default::default()
+        };
+        let list = data.check_list(&opt, None, |_| {}).await;
+        let flaky = list[0].get_other_status();
+        assert_eq!(list[0].get_status(), CheckDataStatus::Success);
+        assert_eq!(flaky.get_attempts(), 2);
+        assert!(flaky.is_flaky());
+        assert!(!list[1].get_other_status().is_flaky());
+        assert_eq!(list[1].get_other_status().get_attempts(), 1);
+        // 404 不在可重试的失败原因中
+        assert_eq!(list[2].get_status(), CheckDataStatus::Failed);
+        assert_eq!(list[2].get_other_status().get_attempts(), 1);
+
+        data.set_list(list.clone());
+        let mut filter = ExportFilter::new();
+        filter.set_exclude_flaky(true);
+        let content = data
+            .clone()
+            .export(String::new(), HashMap::new(), true, 0, filter);
+        assert!(!content.contains("/flaky/"));
+        assert!(content.contains("/stable/"));
+
+        // 相同名称只保存一个时优先保留稳定的源
+        data.set_list(list);
+        data.do_same_save(1, &HashMap::new());
+        let saved = data.get_list();
+        assert_eq!(saved.len(), 1);
+        assert!(saved[0].get_url().contains("/stable/"));
+    }
+
+    #[test]
+    fn parse_content_health() {
+        use crate::common::{ContentHealth, ContentIssue};
+        let log = "[blackdetect @ 0x1] black_start:0 black_end:9.8 black_duration:9.8
+[freezedetect @ 0x2] lavfi.freezedetect.freeze_start: 2.5
+[freezedetect @ 0x2] lavfi.freezedetect.freeze_duration: 3
+[freezedetect @ 0x2] lavfi.freezedetect.freeze_end: 5.5
+[silencedetect @ 0x3] silence_start: 0.5
+";
+        let health = ContentHealth::parse(log, 10.0);
+        assert_eq!(health.black_duration, 9.8);
+        assert_eq!(health.freeze_duration, 3.0);
+        // 没有结束时间时算到检测结束
+        assert_eq!(health.silence_duration, 9.5);
+        assert_eq!(
+            health.issues,
+            vec![ContentIssue::Black, ContentIssue::Silent]
+        );
+        assert!(!health.has_issue(ContentIssue::Frozen));
+    }
+
+    #[tokio::test]
+    async fn cancel_keeps_unchecked_sources() {
+        use crate::common::cancel::{cancel_task, register_cancel, remove_cancel};
+        use crate::common::m3u::m3u::list_str2obj;
+        use crate::common::CheckOptions;
+
+        // /hang 一直不返回
+        let addr = serve_http(|path, _| {
+            if path.starts_with("/hang") {
+                TestResponse::ok(MEDIA_PLAYLIST).delay(30000)
+            } else {
+                TestResponse::ok(MEDIA_PLAYLIST)
+            }
+        })
+        .await;
+
+        let body = format!(
+            "fast,http://{}/fast/index.m3u8\nhang,http://{}/hang/index.m3u8",
+            addr, addr
+        );
+        let data = list_str2obj(vec![body], false);
+        let opt = CheckOptions {
+            request_time: 20000,
+            concurrent: 2,
+            per_host_concurrent: 2,
+            ..Default::default()
+        };
+        let token = register_cancel("cancel_keeps_unchecked_sources");
+        tokio::spawn(async {
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            cancel_task("cancel_keeps_unchecked_sources");
+        });
+        let start = std::time::Instant::now();
+        let list = data.check_list(&opt, Some(token), |_| {}).await;
+        remove_cancel("cancel_keeps_unchecked_sources");
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].get_status(), CheckDataStatus::Success);
+        assert_eq!(list[1].get_status(), CheckDataStatus::Unchecked);
+    }
+
+    #[tokio::test]
+    async fn video_quality_keeps_unknown_and_failed_sources() {
+        use crate::common::m3u::m3u::list_str2obj;
+        use crate::common::{CheckOptions, QualityType};
+
+        let master = |height: i32| {
+            format!(
+                "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=1280x{}\nmedia.m3u8\n",
+                height
+            )
+        };
+        let addr = serve_http(move |path, _| match path {
+            "/hd/index.m3u8" => TestResponse::ok(master(1080)),
+            "/sd/index.m3u8" => TestResponse::ok(master(720)),
+            "/media/index.m3u8" => TestResponse::ok(MEDIA_PLAYLIST),
+            _ => TestResponse::new("404 Not Found", vec![]),
+        })
+        .await;
+
+        let body = ["hd", "sd", "media", "404"]
+            .iter()
+            .map(|p| format!("{},http://{}/{}/index.m3u8", p, addr, p))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let mut data = list_str2obj(vec![body], false);
+        let opt = CheckOptions {
+            request_time: 3000,
+            concurrent: 4,
+            ..Default::default()
+        };
+        let list = data.check_list(&opt, None, |_| {}).await;
+        data.set_list(list);
+        data.search_video_quality(vec![QualityType::Quality1080P]);
+        // 只删除清晰度已知且不匹配的主播放列表，媒体播放列表和失败的源保留
+        let urls = data
+            .get_list()
+            .iter()
+            .map(|i| i.get_url())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            urls,
+            ["hd", "media", "404"]
+                .iter()
+                .map(|p| format!("http://{}/{}/index.m3u8", addr, p))
+                .collect::<Vec<String>>()
+        );
+    }
 }
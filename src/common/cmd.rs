@@ -1,17 +1,56 @@
+use crate::common::proxy::{ffmpeg_proxy_args, ProxyConfig};
+use crate::common::tls::ffmpeg_tls_args;
+use std::collections::BTreeMap;
 use std::process::{Command, ExitStatus};
 
+/// 将请求头转换为ffmpeg/ffprobe的 `-headers` 参数
+///
+/// # 参数
+/// * `headers` - 请求头
+///
+/// # 返回值
+/// * `Vec<String>` - 没有请求头时为空
+pub fn ffmpeg_headers_args(headers: &BTreeMap<String, String>) -> Vec<String> {
+    if headers.is_empty() {
+        return vec![];
+    }
+    let value = headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}\r\n", name, value))
+        .collect::<String>();
+    vec!["-headers".to_string(), value]
+}
+
 /// 从M3U8流中捕获首帧图片
 ///
 /// # 参数
 /// * `m3u8_url` - M3U8流的URL地址
 /// * `output_image` - 输出图片的路径
 /// * `timeout_seconds` - 超时时间（秒）
+/// * `headers` - 请求头
+/// * `proxy` - 拉流使用的代理，ffmpeg不支持的代理直接返回失败
 ///
 /// # 返回值
 /// * `bool` - 成功返回true，失败返回false
-pub fn capture_stream_pic(m3u8_url: String, output_image: String, timeout_seconds: u64) -> bool {
-    // 使用ffmpeg截取首帧
+pub fn capture_stream_pic(
+    m3u8_url: String,
+    output_image: String,
+    timeout_seconds: u64,
+    headers: &BTreeMap<String, String>,
+    proxy: Option<&ProxyConfig>,
+) -> bool {
+    let proxy_args = match ffmpeg_proxy_args(proxy) {
+        Ok(args) => args,
+        Err(e) => {
+            println!("Failed to capture the first frame: {}", e);
+            return false;
+        }
+    };
+    // 使用ffmpeg截取首帧，请求头需要放在输入地址之前
     let status = Command::new("ffmpeg")
+        .args(ffmpeg_headers_args(headers))
+        .args(ffmpeg_tls_args(&m3u8_url))
+        .args(proxy_args)
         .args(&[
             "-i",
             &m3u8_url, // 输入M3U8地址
@@ -0,0 +1,629 @@
+use crate::common::hls::resolve_uri;
+use crate::common::util::{get_codec_names, get_video_resolution};
+use crate::common::{AudioInfo, FfmpegInfo, VideoInfo};
+use chrono::{DateTime, NaiveDateTime};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use url::Url;
+
+/// 按时长计算的直播分片向前回退的个数，避免请求到尚未生成的分片
+const LIVE_SEGMENT_BACKOFF: u64 = 2;
+
+/// `SegmentTimeline` 中的一个 `S` 元素
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DashTimelineEntry {
+    pub t: Option<u64>, // 开始时间，没有时紧接上一个分片
+    pub d: u64,         // 分片时长
+    pub r: i64,         // 重复次数，-1表示重复到下一个元素
+}
+
+/// 分片信息，`SegmentTemplate` 和 `SegmentList` 共用，下层没有的字段从上层继承
+#[derive(Debug, Clone, Default)]
+pub struct DashSegmentInfo {
+    pub initialization: Option<String>, // 初始化分片地址或模板
+    pub media: Option<String>,          // 媒体分片模板
+    pub start_number: Option<u64>,
+    pub timescale: Option<u64>,
+    pub duration: Option<u64>,
+    pub timeline: Vec<DashTimelineEntry>,
+    pub segment_urls: Vec<String>, // SegmentList 中的分片地址
+}
+
+impl DashSegmentInfo {
+    /// 使用上层（AdaptationSet/Period）的分片信息补全缺少的字段
+    pub fn inherit(mut self, parent: &DashSegmentInfo) -> DashSegmentInfo {
+        self.initialization = self.initialization.or(parent.initialization.clone());
+        self.media = self.media.or(parent.media.clone());
+        self.start_number = self.start_number.or(parent.start_number);
+        self.timescale = self.timescale.or(parent.timescale);
+        self.duration = self.duration.or(parent.duration);
+        if self.timeline.is_empty() {
+            self.timeline = parent.timeline.clone();
+        }
+        if self.segment_urls.is_empty() {
+            self.segment_urls = parent.segment_urls.clone();
+        }
+        self
+    }
+}
+
+/// 清单中的一个码率（Representation），AdaptationSet上的属性会被继承
+#[derive(Debug, Clone, Default)]
+pub struct DashRepresentation {
+    pub id: String,
+    pub bandwidth: u64,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub codecs: String,
+    pub mime_type: String,
+    pub frame_rate: Option<f32>,
+    pub base_urls: Vec<String>, // 由外到内的 BaseURL
+    pub segment: DashSegmentInfo,
+}
+
+impl DashRepresentation {
+    pub fn is_video(&self) -> bool {
+        self.mime_type.starts_with("video") || self.width.is_some() || self.height.is_some()
+    }
+
+    pub fn is_audio(&self) -> bool {
+        self.mime_type.starts_with("audio")
+    }
+
+    /// 转换为视频信息
+    pub fn video_info(&self) -> VideoInfo {
+        let mut info = VideoInfo::new();
+        let height = self.height.unwrap_or(0);
+        info.set_width(self.width.unwrap_or(0));
+        info.set_height(height);
+        info.set_codec(get_codec_names(&self.codecs).0.unwrap_or_default());
+        info.set_bandwidth(Some(self.bandwidth).filter(|b| *b > 0));
+        info.set_frame_rate(self.frame_rate);
+        info.quality_type = get_video_resolution(height.max(0) as u32);
+        info
+    }
+
+    /// 根据清单地址和各层 BaseURL 解析出绝对地址
+    fn resolve(&self, manifest_url: &str, uri: &str) -> String {
+        let base = self
+            .base_urls
+            .iter()
+            .fold(manifest_url.to_string(), |base, b| resolve_uri(&base, b));
+        if uri.is_empty() {
+            return base;
+        }
+        resolve_uri(&base, uri)
+    }
+
+    /// 替换模板中的 `$RepresentationID$`、`$Number$`、`$Time$`、`$Bandwidth$`
+    fn fill_template(&self, template: &str, number: u64, time: u64) -> String {
+        let mut result = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('$') {
+            result.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let end = match after.find('$') {
+                Some(end) => end,
+                None => {
+                    result.push_str(&rest[start..]);
+                    return result;
+                }
+            };
+            let token = &after[..end];
+            let (name, format) = match token.find('%') {
+                Some(p) => (&token[..p], Some(&token[p..])),
+                None => (token, None),
+            };
+            let value = match name {
+                "" => "$".to_string(),
+                "RepresentationID" => self.id.clone(),
+                "Number" => pad_number(number, format),
+                "Time" => pad_number(time, format),
+                "Bandwidth" => pad_number(self.bandwidth, format),
+                _ => format!("${}$", token),
+            };
+            result.push_str(&value);
+            rest = &after[end + 1..];
+        }
+        result.push_str(rest);
+        result
+    }
+}
+
+/// 按 `%05d` 这样的格式补零
+fn pad_number(value: u64, format: Option<&str>) -> String {
+    let width = format
+        .map(|f| f.trim_start_matches('%').trim_end_matches('d'))
+        .and_then(|w| w.parse::<usize>().ok())
+        .unwrap_or(0);
+    format!("{:0width$}", value, width = width)
+}
+
+/// MPEG-DASH 清单
+#[derive(Debug, Clone, Default)]
+pub struct DashManifest {
+    pub live: bool,                               // type="dynamic" 为直播
+    pub availability_start_time: Option<i64>,     // 直播开始时间（毫秒时间戳）
+    pub representations: Vec<DashRepresentation>, // 最后一个Period中的所有码率
+}
+
+impl DashManifest {
+    /// 视频码率，按码率从高到低排列
+    pub fn video_representations(&self) -> Vec<&DashRepresentation> {
+        let mut list: Vec<&DashRepresentation> = self
+            .representations
+            .iter()
+            .filter(|r| r.is_video())
+            .collect();
+        list.sort_by_key(|r| std::cmp::Reverse(r.bandwidth));
+        list
+    }
+
+    /// 用于检查的码率，优先第一个视频码率
+    pub fn main_representation(&self) -> Option<&DashRepresentation> {
+        self.representations
+            .iter()
+            .find(|r| r.is_video())
+            .or(self.representations.first())
+    }
+
+    /// 从清单中得到的音视频信息，视频按码率从高到低排列
+    pub fn ffmpeg_info(&self) -> FfmpegInfo {
+        let mut info = FfmpegInfo::new();
+        info.set_video(
+            self.video_representations()
+                .iter()
+                .map(|r| r.video_info())
+                .collect(),
+        );
+        if let Some(audio) = self.representations.iter().find(|r| r.is_audio()) {
+            let mut audio_info = AudioInfo::new();
+            let (video, audio) = get_codec_names(&audio.codecs);
+            audio_info.set_codec(audio.or(video).unwrap_or_default());
+            info.set_audio(audio_info);
+        }
+        info
+    }
+
+    /// 计算 `SegmentTimeline` 中第 `index` 个元素实际代表的分片个数
+    ///
+    /// `r` 为负数时重复到下一个元素的开始时间；最后一个元素没有下一个开始时间，
+    /// 直播时按当前时间推算已经生成的分片，并和按时长计算一样向前回退
+    ///
+    /// # 参数
+    /// * `segment` - 分片信息
+    /// * `index` - 元素下标
+    /// * `time` - 该元素第一个分片的开始时间
+    /// * `now` - 当前毫秒时间戳
+    ///
+    /// # 返回值
+    /// * `u64` - 分片个数，至少为1
+    fn timeline_repeat(&self, segment: &DashSegmentInfo, index: usize, time: u64, now: i64) -> u64 {
+        let s = &segment.timeline[index];
+        if s.r >= 0 {
+            return s.r as u64 + 1;
+        }
+        if s.d == 0 {
+            return 1;
+        }
+        let count = match segment.timeline.get(index + 1) {
+            Some(next) => next.t.map(|t| t.saturating_sub(time).div_ceil(s.d)),
+            None if self.live => self.availability_start_time.map(|start| {
+                let timescale = segment.timescale.unwrap_or(1).max(1);
+                let elapsed = (now - start).max(0) as u64 * timescale / 1000;
+                (elapsed.saturating_sub(time) / s.d).saturating_sub(LIVE_SEGMENT_BACKOFF)
+            }),
+            None => None,
+        };
+        count.unwrap_or(1).max(1)
+    }
+
+    /// 计算某个码率的初始化分片和一个媒体分片的地址
+    ///
+    /// 直播取最新的分片，点播取第一个分片；没有分片信息时整个 BaseURL 就是媒体文件
+    ///
+    /// # 参数
+    /// * `rep` - 码率
+    /// * `manifest_url` - 清单地址（跳转后）
+    /// * `now` - 当前毫秒时间戳，用于计算按时长生成的直播分片序号
+    ///
+    /// # 返回值
+    /// * `Option<(Option<String>, String)>` - （初始化分片地址，媒体分片地址）
+    pub fn segment_urls(
+        &self,
+        rep: &DashRepresentation,
+        manifest_url: &str,
+        now: i64,
+    ) -> Option<(Option<String>, String)> {
+        let segment = &rep.segment;
+        let start_number = segment.start_number.unwrap_or(1);
+        let init = segment
+            .initialization
+            .as_ref()
+            .map(|i| rep.resolve(manifest_url, &rep.fill_template(i, start_number, 0)));
+        if !segment.segment_urls.is_empty() {
+            let uri = if self.live {
+                segment.segment_urls.last()
+            } else {
+                segment.segment_urls.first()
+            }?;
+            return Some((init, rep.resolve(manifest_url, uri)));
+        }
+        let media = match &segment.media {
+            Some(media) => media,
+            None => return Some((init, rep.resolve(manifest_url, ""))),
+        };
+        let (number, time) = if !segment.timeline.is_empty() {
+            let mut time = 0;
+            let mut count = 0;
+            let mut last = (start_number, 0);
+            for (i, s) in segment.timeline.iter().enumerate() {
+                if let Some(t) = s.t {
+                    time = t;
+                }
+                if i == 0 {
+                    last = (start_number, time);
+                    if !self.live {
+                        break;
+                    }
+                }
+                let repeat = self.timeline_repeat(segment, i, time, now);
+                last = (start_number + count + repeat - 1, time + s.d * (repeat - 1));
+                count += repeat;
+                time += s.d * repeat;
+            }
+            last
+        } else {
+            let duration = segment.duration.filter(|d| *d > 0)?;
+            let timescale = segment.timescale.unwrap_or(1).max(1);
+            let mut index = 0;
+            if self.live {
+                if let Some(start) = self.availability_start_time {
+                    let elapsed = (now - start).max(0) as u64 * timescale / 1000;
+                    index = (elapsed / duration).saturating_sub(LIVE_SEGMENT_BACKOFF);
+                }
+            }
+            (start_number + index, index * duration)
+        };
+        Some((
+            init,
+            rep.resolve(manifest_url, &rep.fill_template(media, number, time)),
+        ))
+    }
+}
+
+/// 地址路径是否以 `.mpd` 结尾
+pub fn is_dash_url(_url: &str) -> bool {
+    match Url::parse(_url) {
+        Ok(u) => u.path().to_lowercase().ends_with(".mpd"),
+        Err(_) => false,
+    }
+}
+
+/// 解析 `2024-01-01T00:00:00Z` 格式的时间，没有时区时按UTC处理
+fn parse_datetime(_str: &str) -> Option<i64> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(_str) {
+        return Some(t.timestamp_millis());
+    }
+    NaiveDateTime::parse_from_str(_str, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .map(|t| t.and_utc().timestamp_millis())
+}
+
+/// 解析 `25` 或 `30000/1001` 格式的帧率
+fn parse_frame_rate(_str: &str) -> Option<f32> {
+    match _str.split_once('/') {
+        Some((n, d)) => {
+            let d = d.parse::<f32>().ok().filter(|d| *d > 0.0)?;
+            Some(n.parse::<f32>().ok()? / d)
+        }
+        None => _str.parse::<f32>().ok(),
+    }
+}
+
+/// 清单的层级：MPD、Period、AdaptationSet、Representation
+const LEVEL_COUNT: usize = 4;
+
+#[derive(Default)]
+struct MpdParser {
+    manifest: Option<DashManifest>,
+    level: usize,
+    bases: [Option<String>; LEVEL_COUNT],
+    segments: [DashSegmentInfo; LEVEL_COUNT],
+    set_attrs: DashRepresentation, // AdaptationSet上可被继承的属性
+    current: Option<DashRepresentation>,
+    in_base_url: bool,
+}
+
+impl MpdParser {
+    fn enter(&mut self, level: usize) {
+        self.level = level;
+        for l in level..LEVEL_COUNT {
+            self.bases[l] = None;
+            self.segments[l] = DashSegmentInfo::default();
+        }
+    }
+
+    fn apply_attrs(rep: &mut DashRepresentation, attrs: &HashMap<String, String>) {
+        if let Some(id) = attrs.get("id") {
+            rep.id = id.clone();
+        }
+        if let Some(bandwidth) = attrs.get("bandwidth").and_then(|b| b.parse().ok()) {
+            rep.bandwidth = bandwidth;
+        }
+        if let Some(width) = attrs.get("width").and_then(|w| w.parse().ok()) {
+            rep.width = Some(width);
+        }
+        if let Some(height) = attrs.get("height").and_then(|h| h.parse().ok()) {
+            rep.height = Some(height);
+        }
+        if let Some(codecs) = attrs.get("codecs") {
+            rep.codecs = codecs.clone();
+        }
+        match (attrs.get("mimeType"), attrs.get("contentType")) {
+            (Some(mime_type), _) => rep.mime_type = mime_type.clone(),
+            (None, Some(content_type)) if rep.mime_type.is_empty() => {
+                rep.mime_type = content_type.clone()
+            }
+            _ => {}
+        }
+        if let Some(frame_rate) = attrs.get("frameRate").and_then(|f| parse_frame_rate(f)) {
+            rep.frame_rate = Some(frame_rate);
+        }
+    }
+
+    fn start(&mut self, e: &BytesStart) {
+        let attrs: HashMap<String, String> = e
+            .attributes()
+            .flatten()
+            .map(|a| {
+                (
+                    String::from_utf8_lossy(a.key.local_name().as_ref()).into_owned(),
+                    a.unescape_value()
+                        .map(|v| v.into_owned())
+                        .unwrap_or_default(),
+                )
+            })
+            .collect();
+        let get_u64 = |name: &str| attrs.get(name).and_then(|v| v.parse::<u64>().ok());
+        let level = self.level;
+        match e.local_name().as_ref() {
+            b"MPD" => {
+                self.enter(0);
+                self.manifest = Some(DashManifest {
+                    live: attrs.get("type").map(|t| t == "dynamic").unwrap_or(false),
+                    availability_start_time: attrs
+                        .get("availabilityStartTime")
+                        .and_then(|t| parse_datetime(t)),
+                    representations: vec![],
+                });
+            }
+            b"Period" => {
+                // 只保留最后一个Period，直播时它是最新的内容
+                self.enter(1);
+                if let Some(manifest) = self.manifest.as_mut() {
+                    manifest.representations.clear();
+                }
+            }
+            b"AdaptationSet" => {
+                self.enter(2);
+                self.set_attrs = DashRepresentation::default();
+                Self::apply_attrs(&mut self.set_attrs, &attrs);
+            }
+            b"Representation" => {
+                self.enter(3);
+                let mut rep = self.set_attrs.clone();
+                Self::apply_attrs(&mut rep, &attrs);
+                self.current = Some(rep);
+            }
+            b"BaseURL" => self.in_base_url = true,
+            b"SegmentTemplate" | b"SegmentList" => {
+                let segment = &mut self.segments[level];
+                if let Some(media) = attrs.get("media") {
+                    segment.media = Some(media.clone());
+                }
+                if let Some(initialization) = attrs.get("initialization") {
+                    segment.initialization = Some(initialization.clone());
+                }
+                segment.start_number = get_u64("startNumber").or(segment.start_number);
+                segment.timescale = get_u64("timescale").or(segment.timescale);
+                segment.duration = get_u64("duration").or(segment.duration);
+            }
+            b"Initialization" => {
+                if let Some(source) = attrs.get("sourceURL") {
+                    self.segments[level].initialization = Some(source.clone());
+                }
+            }
+            b"SegmentURL" => {
+                if let Some(media) = attrs.get("media") {
+                    self.segments[level].segment_urls.push(media.clone());
+                }
+            }
+            b"S" => self.segments[level].timeline.push(DashTimelineEntry {
+                t: get_u64("t"),
+                d: get_u64("d").unwrap_or(0),
+                r: attrs.get("r").and_then(|r| r.parse().ok()).unwrap_or(0),
+            }),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, name: &[u8]) {
+        match name {
+            b"BaseURL" => self.in_base_url = false,
+            b"Representation" => {
+                if let Some(mut rep) = self.current.take() {
+                    rep.base_urls = self.bases.iter().flatten().cloned().collect();
+                    rep.segment = self.segments[3]
+                        .clone()
+                        .inherit(&self.segments[2])
+                        .inherit(&self.segments[1]);
+                    if let Some(manifest) = self.manifest.as_mut() {
+                        manifest.representations.push(rep);
+                    }
+                }
+                self.level = 2;
+            }
+            b"AdaptationSet" => self.level = 1,
+            b"Period" => self.level = 0,
+            _ => {}
+        }
+    }
+}
+
+/// 解析 MPEG-DASH 清单
+///
+/// # 参数
+/// * `_body` - 清单内容
+///
+/// # 返回值
+/// * `Option<DashManifest>` - 不是MPD或XML格式错误时返回None
+pub fn parse_mpd(_body: &str) -> Option<DashManifest> {
+    let mut reader = Reader::from_str(_body.trim_start_matches('\u{feff}'));
+    reader.config_mut().trim_text(true);
+    let mut parser = MpdParser::default();
+    loop {
+        match reader.read_event().ok()? {
+            Event::Start(e) | Event::Empty(e)
+                if parser.manifest.is_none() && e.local_name().as_ref() != b"MPD" =>
+            {
+                // 根元素不是MPD
+                return None;
+            }
+            Event::Start(e) => parser.start(&e),
+            Event::Empty(e) => {
+                parser.start(&e);
+                parser.end(e.local_name().as_ref());
+            }
+            Event::End(e) => parser.end(e.local_name().as_ref()),
+            Event::Text(t) if parser.in_base_url => {
+                let text = t.unescape().ok()?.trim().to_string();
+                parser.bases[parser.level] = Some(text);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    parser.manifest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_static_mpd() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT30S">
+  <BaseURL>media/</BaseURL>
+  <Period>
+    <AdaptationSet mimeType="video/mp4" codecs="avc1.4d401f" frameRate="30000/1001">
+      <SegmentTemplate timescale="1000" initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/seg-$Number%05d$.m4s" startNumber="3">
+        <SegmentTimeline><S t="0" d="4000" r="2"/><S d="2000"/></SegmentTimeline>
+      </SegmentTemplate>
+      <Representation id="v720" bandwidth="2000000" width="1280" height="720"/>
+      <Representation id="v1080" bandwidth="5000000" width="1920" height="1080" codecs="hvc1.1.6.L120.90"/>
+    </AdaptationSet>
+    <AdaptationSet mimeType="audio/mp4" codecs="mp4a.40.2">
+      <Representation id="a" bandwidth="128000"><BaseURL>audio.mp4</BaseURL></Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let manifest = parse_mpd(body).unwrap();
+        assert!(!manifest.live);
+        assert_eq!(manifest.representations.len(), 3);
+        let video = manifest.video_representations();
+        assert_eq!(video[0].id, "v1080");
+        assert_eq!(video[1].frame_rate.map(|f| f.round()), Some(30.0));
+
+        let info = manifest.ffmpeg_info();
+        assert_eq!(info.video[0].codec, "hevc");
+        assert_eq!(info.video[0].height, 1080);
+        assert_eq!(info.video[0].bandwidth, Some(5000000));
+        assert_eq!(info.video[1].codec, "h264");
+        assert_eq!(info.audio.unwrap().codec, "aac");
+
+        let rep = manifest.main_representation().unwrap();
+        let (init, media) = manifest
+            .segment_urls(rep, "http://a.com/live/stream.mpd", 0)
+            .unwrap();
+        assert_eq!(init.unwrap(), "http://a.com/live/media/v720/init.mp4");
+        assert_eq!(media, "http://a.com/live/media/v720/seg-00003.m4s");
+
+        // 没有分片信息时 BaseURL 就是媒体文件
+        let audio = &manifest.representations[2];
+        let (init, media) = manifest
+            .segment_urls(audio, "http://a.com/live/stream.mpd", 0)
+            .unwrap();
+        assert!(init.is_none());
+        assert_eq!(media, "http://a.com/live/media/audio.mp4");
+
+        assert!(parse_mpd("#EXTM3U\n").is_none());
+        assert!(parse_mpd("<html><body>404</body></html>").is_none());
+        assert!(is_dash_url("http://a.com/live/Stream.MPD?token=1"));
+        assert!(!is_dash_url("http://a.com/live/index.m3u8"));
+    }
+
+    #[test]
+    fn live_mpd_latest_segment() {
+        let timeline = r#"<MPD type="dynamic" availabilityStartTime="2024-01-01T00:00:00Z">
+  <Period><AdaptationSet contentType="video">
+    <Representation id="1" bandwidth="800000" width="640" height="360">
+      <SegmentTemplate timescale="90000" media="t-$Time$.m4s" initialization="init-$Bandwidth$.mp4">
+        <SegmentTimeline><S t="900000" d="180000" r="-1"/><S d="180000" r="1"/></SegmentTimeline>
+      </SegmentTemplate>
+    </Representation>
+  </AdaptationSet></Period>
+</MPD>"#;
+        let manifest = parse_mpd(timeline).unwrap();
+        assert!(manifest.live);
+        let rep = manifest.main_representation().unwrap();
+        let (init, media) = manifest.segment_urls(rep, "http://a.com/x.mpd", 0).unwrap();
+        assert_eq!(init.unwrap(), "http://a.com/init-800000.mp4");
+        assert_eq!(media, "http://a.com/t-1260000.m4s");
+
+        // 按时长生成分片时，根据开始时间计算最新序号并回退两个分片
+        let duration = r#"<MPD type="dynamic" availabilityStartTime="2024-01-01T00:00:00">
+  <Period><AdaptationSet mimeType="video/mp4">
+    <SegmentTemplate timescale="1" duration="4" startNumber="10" media="$RepresentationID$-$Number$.m4s"/>
+    <Representation id="hd" bandwidth="3000000" height="720"/>
+  </AdaptationSet></Period>
+</MPD>"#;
+        let manifest = parse_mpd(duration).unwrap();
+        let start = manifest.availability_start_time.unwrap();
+        let rep = manifest.main_representation().unwrap();
+        let (_, media) = manifest
+            .segment_urls(rep, "http://a.com/x.mpd", start + 40_000)
+            .unwrap();
+        assert_eq!(media, "http://a.com/hd-18.m4s");
+
+        // 只有一个 r="-1" 的元素时，按当前时间推算最新分片
+        let open = r#"<MPD type="dynamic" availabilityStartTime="2024-01-01T00:00:00Z">
+  <Period><AdaptationSet contentType="video">
+    <Representation id="1" bandwidth="800000">
+      <SegmentTemplate timescale="1000" startNumber="1" media="n-$Number$-$Time$.m4s">
+        <SegmentTimeline><S t="0" d="2000" r="-1"/></SegmentTimeline>
+      </SegmentTemplate>
+    </Representation>
+  </AdaptationSet></Period>
+</MPD>"#;
+        let manifest = parse_mpd(open).unwrap();
+        let start = manifest.availability_start_time.unwrap();
+        let rep = manifest.main_representation().unwrap();
+        let (_, media) = manifest
+            .segment_urls(rep, "http://a.com/x.mpd", start + 21_000)
+            .unwrap();
+        assert_eq!(media, "http://a.com/n-8-14000.m4s");
+
+        // r="-1" 重复到下一个元素的开始时间
+        let until_next = open.replace(
+            r#"<S t="0" d="2000" r="-1"/>"#,
+            r#"<S t="0" d="2000" r="-1"/><S t="10000" d="3000"/>"#,
+        );
+        let manifest = parse_mpd(&until_next).unwrap();
+        let rep = manifest.main_representation().unwrap();
+        let (_, media) = manifest.segment_urls(rep, "http://a.com/x.mpd", 0).unwrap();
+        assert_eq!(media, "http://a.com/n-6-10000.m4s");
+    }
+}
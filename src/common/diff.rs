@@ -0,0 +1,269 @@
+use crate::common::CheckDataStatus::{Failed, Success};
+use crate::common::{M3uObject, M3uObjectList, QualityType};
+use crate::r#const::constant::OUTPUT_FOLDER;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+/// 延迟变化超过该值（毫秒）才记录
+const LATENCY_CHANGE_MIN: i32 = 500;
+
+/// 延迟变化超过原延迟的该比例才记录
+const LATENCY_CHANGE_RATIO: f32 = 0.5;
+
+/// 对比结果中的单个源
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DiffItem {
+    pub name: String,
+    pub url: String,
+}
+
+impl DiffItem {
+    fn from_object(item: &M3uObject) -> DiffItem {
+        DiffItem {
+            name: item.get_name(),
+            url: item.get_url(),
+        }
+    }
+}
+
+/// 清晰度变化
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QualityChange {
+    pub name: String,
+    pub url: String,
+    pub before: Option<QualityType>,
+    pub after: Option<QualityType>,
+}
+
+/// 延迟变化（毫秒）
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LatencyChange {
+    pub name: String,
+    pub url: String,
+    pub before: i32,
+    pub after: i32,
+}
+
+/// 两次任务运行结果的对比
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RunDiff {
+    pub time: i64,                  // 本次运行时间（秒）
+    pub previous_time: Option<i64>, // 上次运行时间（秒），首次运行时为空
+    pub died: Vec<DiffItem>,        // 上次有效，本次失效
+    pub revived: Vec<DiffItem>,     // 上次失效，本次恢复
+    pub added: Vec<DiffItem>,       // 新增的源
+    pub removed: Vec<DiffItem>,     // 删除的源
+    pub quality_changed: Vec<QualityChange>,
+    pub latency_changed: Vec<LatencyChange>,
+}
+
+/// 按url建立索引，重复的url优先使用检查成功的源
+fn index_by_url(list: &[M3uObject]) -> HashMap<String, &M3uObject> {
+    let mut map: HashMap<String, &M3uObject> = HashMap::new();
+    for item in list {
+        match map.get(&item.get_url()) {
+            Some(exist) if exist.get_status() == Success => {}
+            _ => {
+                map.insert(item.get_url(), item);
+            }
+        }
+    }
+    map
+}
+
+fn is_latency_changed(before: i32, after: i32) -> bool {
+    let change = (after - before).abs();
+    change >= LATENCY_CHANGE_MIN && change as f32 >= before as f32 * LATENCY_CHANGE_RATIO
+}
+
+impl RunDiff {
+    /// 对比两次检查的结果
+    ///
+    /// # 参数
+    /// * `previous` - 上次检查的源，首次运行时为空
+    /// * `current` - 本次检查的源
+    /// * `previous_time` - 上次运行时间（秒）
+    /// * `time` - 本次运行时间（秒）
+    pub fn compare(
+        previous: &[M3uObject],
+        current: &[M3uObject],
+        previous_time: Option<i64>,
+        time: i64,
+    ) -> RunDiff {
+        let mut diff = RunDiff {
+            time,
+            previous_time,
+            ..RunDiff::default()
+        };
+        let before = index_by_url(previous);
+        let after = index_by_url(current);
+        for item in current {
+            let url = item.get_url();
+            // 同一url只处理一次
+            if !std::ptr::eq(after[&url], item) {
+                continue;
+            }
+            let old = match before.get(&url) {
+                Some(old) => old,
+                None => {
+                    diff.added.push(DiffItem::from_object(item));
+                    continue;
+                }
+            };
+            match (old.get_status(), item.get_status()) {
+                (Success, Failed) => diff.died.push(DiffItem::from_object(item)),
+                (Failed, Success) => diff.revived.push(DiffItem::from_object(item)),
+                (Success, Success) => {
+                    let old_status = old.get_other_status();
+                    let new_status = item.get_other_status();
+                    if old_status.get_quality() != new_status.get_quality() {
+                        diff.quality_changed.push(QualityChange {
+                            name: item.get_name(),
+                            url: url.clone(),
+                            before: old_status.get_quality(),
+                            after: new_status.get_quality(),
+                        });
+                    }
+                    if is_latency_changed(old_status.get_delay(), new_status.get_delay()) {
+                        diff.latency_changed.push(LatencyChange {
+                            name: item.get_name(),
+                            url: url.clone(),
+                            before: old_status.get_delay(),
+                            after: new_status.get_delay(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        for item in previous {
+            let url = item.get_url();
+            if std::ptr::eq(before[&url], item) && !after.contains_key(&url) {
+                diff.removed.push(DiffItem::from_object(item));
+            }
+        }
+        diff
+    }
+
+    /// 是否没有任何变化
+    pub fn is_empty(&self) -> bool {
+        self.died.is_empty()
+            && self.revived.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.quality_changed.is_empty()
+            && self.latency_changed.is_empty()
+    }
+
+    /// 生成可读的对比结果
+    pub fn print_result(&self) -> String {
+        let mut result = format!(
+            "失效: {}, 恢复: {}, 新增: {}, 删除: {}, 清晰度变化: {}, 延迟变化: {}",
+            self.died.len(),
+            self.revived.len(),
+            self.added.len(),
+            self.removed.len(),
+            self.quality_changed.len(),
+            self.latency_changed.len()
+        );
+        let groups = [
+            ("失效", &self.died),
+            ("恢复", &self.revived),
+            ("新增", &self.added),
+            ("删除", &self.removed),
+        ];
+        for (title, list) in groups {
+            for item in list {
+                result.push_str(&format!("\n[{}] {} {}", title, item.name, item.url));
+            }
+        }
+        for item in &self.quality_changed {
+            result.push_str(&format!(
+                "\n[清晰度] {} {} {:?} -> {:?}",
+                item.name, item.url, item.before, item.after
+            ));
+        }
+        for item in &self.latency_changed {
+            result.push_str(&format!(
+                "\n[延迟] {} {} {}ms -> {}ms",
+                item.name, item.url, item.before, item.after
+            ));
+        }
+        result
+    }
+}
+
+/// 任务结果JSON文件路径
+pub fn result_file_name(output_id: &str) -> String {
+    format!("{}{}.json", OUTPUT_FOLDER, output_id)
+}
+
+/// 对比结果文件路径，和结果JSON放在一起
+pub fn diff_file_name(output_id: &str) -> String {
+    format!("{}{}.diff.json", OUTPUT_FOLDER, output_id)
+}
+
+/// 读取任务结果JSON
+pub fn load_result(output_id: &str) -> Option<M3uObjectList> {
+    let content = fs::read_to_string(result_file_name(output_id)).ok()?;
+    serde_json::from_str::<M3uObjectList>(&content).ok()
+}
+
+/// 保存对比结果
+pub fn save_diff(output_id: &str, diff: &RunDiff) -> io::Result<()> {
+    fs::write(diff_file_name(output_id), serde_json::to_string(diff)?)
+}
+
+/// 读取对比结果
+pub fn load_diff(output_id: &str) -> io::Result<RunDiff> {
+    let content = fs::read_to_string(diff_file_name(output_id))?;
+    Ok(serde_json::from_str::<RunDiff>(&content)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{CheckDataStatus, OtherStatus};
+
+    fn object(url: &str, status: CheckDataStatus, delay: i32) -> M3uObject {
+        let mut item = M3uObject::new();
+        item.set_url(url.to_string());
+        item.set_name(format!("name-{}", url));
+        item.set_status(status);
+        let mut other = OtherStatus::new();
+        other.set_delay(delay);
+        item.set_other_status(other);
+        item
+    }
+
+    #[test]
+    fn compare_runs() {
+        let previous = vec![
+            object("a", Success, 100),
+            object("b", Failed, 0),
+            object("c", Success, 100),
+            object("d", Success, 100),
+            object("e", Success, 100),
+        ];
+        let current = vec![
+            object("a", Failed, 0),
+            object("b", Success, 200),
+            object("c", Success, 900),
+            object("e", Failed, 0),
+            object("e", Success, 120),
+            object("f", Success, 100),
+        ];
+        let diff = RunDiff::compare(&previous, &current, Some(1), 2);
+        let urls = |list: &Vec<DiffItem>| list.iter().map(|i| i.url.clone()).collect::<Vec<_>>();
+        assert_eq!(urls(&diff.died), vec!["a"]);
+        assert_eq!(urls(&diff.revived), vec!["b"]);
+        assert_eq!(urls(&diff.added), vec!["f"]);
+        assert_eq!(urls(&diff.removed), vec!["d"]);
+        assert_eq!(diff.latency_changed.len(), 1);
+        assert_eq!(diff.latency_changed[0].url, "c");
+        assert!(diff.quality_changed.is_empty());
+        assert!(!diff.is_empty());
+    }
+}
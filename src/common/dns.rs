@@ -0,0 +1,520 @@
+use crate::common::{CheckError, FailureReason};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+/// 自定义DNS服务器单次查询的超时时间
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+/// 解析失败结果的缓存时间（秒），不超过 `cache_ttl`
+const FAILURE_CACHE_TTL: i64 = 30;
+
+/// 域名解析配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsConfig {
+    #[serde(default)]
+    pub servers: Vec<String>, // 自定义DNS服务器，按顺序尝试，`8.8.8.8`、`8.8.8.8:53` 使用UDP查询，http(s)地址使用DNS-over-HTTPS，为空时使用系统解析
+    #[serde(default = "DnsConfig::default_cache_ttl")]
+    pub cache_ttl: i64, // 解析结果缓存时间（秒），0表示不缓存
+    #[serde(default = "DnsConfig::default_concurrent")]
+    pub concurrent: usize, // 检查前同时解析的域名数量
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        DnsConfig::new()
+    }
+}
+
+impl DnsConfig {
+    pub fn new() -> DnsConfig {
+        DnsConfig {
+            servers: vec![],
+            cache_ttl: DnsConfig::default_cache_ttl(),
+            concurrent: DnsConfig::default_concurrent(),
+        }
+    }
+
+    fn default_cache_ttl() -> i64 {
+        300
+    }
+
+    fn default_concurrent() -> usize {
+        32
+    }
+}
+
+/// 域名解析缓存，key为小写域名，value为过期时间和缓存的内容
+type HostCache<T> = Lazy<Mutex<HashMap<String, (i64, T)>>>;
+
+/// 解析成功的结果缓存，缓存地址列表
+static DNS_CACHE: HostCache<Vec<IpAddr>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 解析失败的结果缓存，避免同一个域名的每个源都重新查询，缓存错误信息
+static DNS_FAILURE_CACHE: HostCache<String> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn dns_error(host: &str, msg: String) -> CheckError {
+    CheckError::new(
+        FailureReason::Dns,
+        format!("resolve {} error {}", host, msg),
+    )
+}
+
+/// 解析域名，优先使用缓存，IP地址直接返回，解析失败的结果短时间缓存
+///
+/// # 参数
+/// * `host` - 域名或IP地址，IPv6地址可以带中括号
+/// * `config` - 域名解析配置
+///
+/// # 返回值
+/// * `Result<Vec<IpAddr>, CheckError>` - 解析失败时失败原因为 `FailureReason::Dns`
+pub async fn resolve_host(host: &str, config: &DnsConfig) -> Result<Vec<IpAddr>, CheckError> {
+    let host = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.')
+        .to_lowercase();
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![ip]);
+    }
+    let now = chrono::Utc::now().timestamp();
+    if config.cache_ttl > 0 {
+        if let Some((expires_at, ips)) = DNS_CACHE.lock().unwrap().get(&host) {
+            if *expires_at > now {
+                return Ok(ips.clone());
+            }
+        }
+        if let Some((expires_at, msg)) = DNS_FAILURE_CACHE.lock().unwrap().get(&host) {
+            if *expires_at > now {
+                return Err(CheckError::new(FailureReason::Dns, msg.clone()));
+            }
+        }
+    }
+    let ips = match lookup_host(&host, config).await {
+        Ok(ips) => ips,
+        Err(e) => {
+            if config.cache_ttl > 0 {
+                DNS_FAILURE_CACHE.lock().unwrap().insert(
+                    host,
+                    (now + config.cache_ttl.min(FAILURE_CACHE_TTL), e.msg.clone()),
+                );
+            }
+            return Err(e);
+        }
+    };
+    if config.cache_ttl > 0 {
+        DNS_CACHE
+            .lock()
+            .unwrap()
+            .insert(host, (now + config.cache_ttl, ips.clone()));
+    }
+    Ok(ips)
+}
+
+/// 不使用缓存解析域名，配置了多个DNS服务器时返回第一个解析成功的结果
+///
+/// # 参数
+/// * `host` - 域名
+/// * `config` - 域名解析配置
+pub async fn lookup_host(host: &str, config: &DnsConfig) -> Result<Vec<IpAddr>, CheckError> {
+    if config.servers.is_empty() {
+        let mut ips = vec![];
+        for addr in tokio::net::lookup_host((host, 0))
+            .await
+            .map_err(|e| dns_error(host, e.to_string()))?
+        {
+            if !ips.contains(&addr.ip()) {
+                ips.push(addr.ip());
+            }
+        }
+        if ips.is_empty() {
+            return Err(dns_error(host, "no address".to_string()));
+        }
+        return Ok(ips);
+    }
+    let mut last_error = String::default();
+    for server in &config.servers {
+        let server = server.trim();
+        let result = if server.starts_with("https://") || server.starts_with("http://") {
+            query_doh(server, host).await
+        } else {
+            query_udp(server, host).await
+        };
+        match result {
+            Ok(ips) if !ips.is_empty() => return Ok(ips),
+            Ok(_) => last_error = format!("{} no address", server),
+            Err(e) => last_error = format!("{} {}", server, e),
+        }
+    }
+    Err(dns_error(host, last_error))
+}
+
+/// 构造DNS查询报文
+///
+/// # 参数
+/// * `id` - 报文id
+/// * `host` - 查询的域名
+/// * `qtype` - 查询类型，A或AAAA
+fn build_query(id: u16, host: &str, qtype: u16) -> Result<Vec<u8>, String> {
+    let mut buf = Vec::with_capacity(18 + host.len());
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(&[0x01, 0x00]); // 期望递归查询
+    buf.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // 1个问题
+    for label in host.split('.').filter(|l| !l.is_empty()) {
+        if label.len() > 63 {
+            return Err(format!("invalid domain {}", host));
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+    buf.extend_from_slice(&qtype.to_be_bytes());
+    buf.extend_from_slice(&[0, 1]); // IN
+    Ok(buf)
+}
+
+/// 跳过报文中的域名，返回域名之后的位置
+fn skip_name(buf: &[u8], mut pos: usize) -> Result<usize, String> {
+    loop {
+        let len = *buf.get(pos).ok_or("dns response truncated")? as usize;
+        if len == 0 {
+            return Ok(pos + 1);
+        }
+        if len & 0xc0 == 0xc0 {
+            // 压缩指针
+            return Ok(pos + 2);
+        }
+        pos += len + 1;
+    }
+}
+
+/// 解析DNS响应报文中的A和AAAA记录，CNAME等其他记录会被忽略
+///
+/// # 参数
+/// * `id` - 查询报文的id
+/// * `buf` - 响应报文
+fn parse_response(id: u16, buf: &[u8]) -> Result<Vec<IpAddr>, String> {
+    if buf.len() < 12 {
+        return Err("dns response truncated".to_string());
+    }
+    if u16::from_be_bytes([buf[0], buf[1]]) != id {
+        return Err("dns response id mismatch".to_string());
+    }
+    match buf[3] & 0x0f {
+        0 => {}
+        3 => return Err("NXDOMAIN".to_string()),
+        rcode => return Err(format!("dns server error rcode {}", rcode)),
+    }
+    let qdcount = u16::from_be_bytes([buf[4], buf[5]]);
+    let ancount = u16::from_be_bytes([buf[6], buf[7]]);
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        pos = skip_name(buf, pos)? + 4;
+    }
+    let mut ips = vec![];
+    for _ in 0..ancount {
+        pos = skip_name(buf, pos)?;
+        if pos + 10 > buf.len() {
+            return Err("dns response truncated".to_string());
+        }
+        let rtype = u16::from_be_bytes([buf[pos], buf[pos + 1]]);
+        let rdlen = u16::from_be_bytes([buf[pos + 8], buf[pos + 9]]) as usize;
+        pos += 10;
+        let data = buf.get(pos..pos + rdlen).ok_or("dns response truncated")?;
+        match (rtype, data.len()) {
+            (TYPE_A, 4) => ips.push(IpAddr::V4(Ipv4Addr::new(
+                data[0], data[1], data[2], data[3],
+            ))),
+            (TYPE_AAAA, 16) => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(data);
+                ips.push(IpAddr::V6(Ipv6Addr::from(octets)))
+            }
+            _ => {}
+        }
+        pos += rdlen;
+    }
+    Ok(ips)
+}
+
+/// 合并A和AAAA的查询结果，都失败时返回第一个错误
+fn merge_results(results: Vec<Result<Vec<IpAddr>, String>>) -> Result<Vec<IpAddr>, String> {
+    let mut ips = vec![];
+    let mut first_error = None;
+    let mut ok = false;
+    for result in results {
+        match result {
+            Ok(list) => {
+                ok = true;
+                for ip in list {
+                    if !ips.contains(&ip) {
+                        ips.push(ip);
+                    }
+                }
+            }
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match (ok, first_error) {
+        (false, Some(e)) => Err(e),
+        _ => Ok(ips),
+    }
+}
+
+/// 通过UDP向DNS服务器查询A和AAAA记录
+///
+/// # 参数
+/// * `server` - DNS服务器地址，不带端口时使用53端口
+/// * `host` - 查询的域名
+async fn query_udp(server: &str, host: &str) -> Result<Vec<IpAddr>, String> {
+    let addr = match server.parse::<SocketAddr>() {
+        Ok(addr) => addr,
+        Err(_) => SocketAddr::new(
+            server
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+                .map_err(|_| format!("invalid dns server {}", server))?,
+            53,
+        ),
+    };
+    let bind = if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(bind).await.map_err(|e| e.to_string())?;
+    socket.connect(addr).await.map_err(|e| e.to_string())?;
+    let mut results = vec![];
+    for qtype in [TYPE_A, TYPE_AAAA] {
+        let id = rand::random::<u16>();
+        let query = build_query(id, host, qtype)?;
+        socket.send(&query).await.map_err(|e| e.to_string())?;
+        let exchange = async {
+            let mut buf = [0u8; 1500];
+            loop {
+                let n = socket.recv(&mut buf).await.map_err(|e| e.to_string())?;
+                match parse_response(id, &buf[..n]) {
+                    // 之前超时的查询晚到的响应，继续等待
+                    Err(e) if e == "dns response id mismatch" => continue,
+                    result => return result,
+                }
+            }
+        };
+        let result = tokio::time::timeout(QUERY_TIMEOUT, exchange)
+            .await
+            .unwrap_or_else(|_| Err("dns query timeout".to_string()));
+        if matches!(&result, Err(e) if e == "NXDOMAIN") {
+            return result;
+        }
+        results.push(result);
+    }
+    merge_results(results)
+}
+
+/// 通过DNS-over-HTTPS（RFC 8484，POST方式）查询A和AAAA记录
+///
+/// # 参数
+/// * `server` - DoH地址，如 https://1.1.1.1/dns-query
+/// * `host` - 查询的域名
+async fn query_doh(server: &str, host: &str) -> Result<Vec<IpAddr>, String> {
+    let client = crate::common::tls::client_builder(server)
+        .map_err(|e| e.to_string())?
+        .timeout(QUERY_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let exchange = |qtype: u16| {
+        let client = client.clone();
+        async move {
+            // RFC 8484 建议id为0，便于缓存
+            let query = build_query(0, host, qtype)?;
+            let resp = client
+                .post(server)
+                .header("content-type", "application/dns-message")
+                .header("accept", "application/dns-message")
+                .body(query)
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            if !resp.status().is_success() {
+                return Err(format!("doh status {}", resp.status().as_u16()));
+            }
+            let body = resp.bytes().await.map_err(|e| e.to_string())?;
+            parse_response(0, &body)
+        }
+    };
+    let (a, aaaa) = tokio::join!(exchange(TYPE_A), exchange(TYPE_AAAA));
+    if matches!(&a, Err(e) if e == "NXDOMAIN") {
+        return a;
+    }
+    merge_results(vec![a, aaaa])
+}
+
+/// 使用Base配置中的DNS服务器和解析缓存为reqwest客户端解析域名
+pub struct CachedResolver {
+    config: DnsConfig,
+}
+
+impl CachedResolver {
+    pub fn new(config: DnsConfig) -> CachedResolver {
+        CachedResolver { config }
+    }
+}
+
+impl reqwest::dns::Resolve for CachedResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let config = self.config.clone();
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let ips = resolve_host(&host, &config).await?;
+            let addrs: reqwest::dns::Addrs =
+                Box::new(ips.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// 构造只包含一条A或AAAA记录的响应，找不到域名时返回NXDOMAIN
+    fn answer(query: &[u8], known: &str) -> Vec<u8> {
+        let end = skip_name(query, 12).unwrap();
+        let qname = &query[12..end];
+        let qtype = u16::from_be_bytes([query[end], query[end + 1]]);
+        let mut name = vec![];
+        let mut pos = 0;
+        while qname[pos] != 0 {
+            let len = qname[pos] as usize;
+            name.push(String::from_utf8_lossy(&qname[pos + 1..pos + 1 + len]).to_string());
+            pos += len + 1;
+        }
+        let mut resp = query[..2].to_vec();
+        if name.join(".") != known {
+            resp.extend_from_slice(&[0x81, 0x83, 0, 1, 0, 0, 0, 0, 0, 0]);
+            resp.extend_from_slice(&query[12..end + 4]);
+            return resp;
+        }
+        resp.extend_from_slice(&[0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0]);
+        resp.extend_from_slice(&query[12..end + 4]);
+        resp.extend_from_slice(&[0xc0, 0x0c]);
+        resp.extend_from_slice(&qtype.to_be_bytes());
+        resp.extend_from_slice(&[0, 1, 0, 0, 0, 60]);
+        if qtype == TYPE_A {
+            resp.extend_from_slice(&[0, 4, 10, 1, 2, 3]);
+        } else {
+            resp.extend_from_slice(&[0, 16]);
+            resp.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        }
+        resp
+    }
+
+    #[test]
+    fn parse_dns_messages() {
+        let query = build_query(7, "stream.test", TYPE_A).unwrap();
+        assert_eq!(&query[12..25], b"\x06stream\x04test\x00");
+        assert_eq!(
+            parse_response(7, &answer(&query, "stream.test")).unwrap(),
+            vec!["10.1.2.3".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(
+            parse_response(7, &answer(&query, "other.test")).unwrap_err(),
+            "NXDOMAIN"
+        );
+        assert!(parse_response(8, &answer(&query, "stream.test")).is_err());
+        assert!(build_query(1, &"a".repeat(64), TYPE_A).is_err());
+    }
+
+    #[tokio::test]
+    async fn resolve_through_doh_with_cache() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let counter = counter.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![];
+                    let mut chunk = [0u8; 1024];
+                    loop {
+                        let n = stream.read(&mut chunk).await.unwrap();
+                        if n == 0 {
+                            return;
+                        }
+                        buf.extend_from_slice(&chunk[..n]);
+                        let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+                            continue;
+                        };
+                        let head = String::from_utf8_lossy(&buf[..end]).to_lowercase();
+                        let length = head
+                            .lines()
+                            .find_map(|l| l.strip_prefix("content-length:"))
+                            .map(|v| v.trim().parse::<usize>().unwrap())
+                            .unwrap_or(0);
+                        if buf.len() < end + 4 + length {
+                            continue;
+                        }
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        let body = answer(&buf[end + 4..end + 4 + length], "stream.test");
+                        buf.drain(..end + 4 + length);
+                        let head = format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: application/dns-message\r\ncontent-length: {}\r\n\r\n",
+                            body.len()
+                        );
+                        stream.write_all(head.as_bytes()).await.unwrap();
+                        stream.write_all(&body).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        let mut config = DnsConfig::new();
+        config.servers = vec![format!("http://{}/dns-query", addr)];
+        let ips = resolve_host("Stream.Test", &config).await.unwrap();
+        assert_eq!(
+            ips,
+            vec![
+                "10.1.2.3".parse::<IpAddr>().unwrap(),
+                "2001:db8::1".parse::<IpAddr>().unwrap()
+            ]
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        // 第二次命中缓存，不再请求DNS服务器
+        assert_eq!(resolve_host("stream.test", &config).await.unwrap(), ips);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        let err = resolve_host("missing.test", &config).await.unwrap_err();
+        assert_eq!(err.reason, FailureReason::Dns);
+        assert!(err.msg.contains("NXDOMAIN"));
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+        // 解析失败的结果也会短时间缓存
+        let cached = resolve_host("missing.test", &config).await.unwrap_err();
+        assert_eq!(cached.reason, FailureReason::Dns);
+        assert_eq!(cached.msg, err.msg);
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+        // reqwest客户端通过同一个解析器使用缓存结果
+        let resolver = CachedResolver::new(config);
+        let name = "stream.test".parse::<reqwest::dns::Name>().unwrap();
+        let addrs: Vec<SocketAddr> = reqwest::dns::Resolve::resolve(&resolver, name)
+            .await
+            .unwrap()
+            .collect();
+        assert_eq!(addrs.len(), 2);
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::common::cmd::ffmpeg_headers_args;
+use crate::common::proxy::{ffmpeg_proxy_args, ProxyConfig};
+use crate::common::tls::ffmpeg_tls_args;
+use crate::common::M3uObject;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::process::Stdio;
+use tokio::process::Command;
+use tokio::time::Duration;
+
+/// 画面哈希的汉明距离不超过该值时认为是相同画面
+const FRAME_HASH_DISTANCE: u32 = 6;
+
+/// dHash使用的缩略图尺寸，每行比较相邻的两个像素得到8位
+const DHASH_WIDTH: usize = 9;
+const DHASH_HEIGHT: usize = 8;
+
+/// 源内容的指纹，用于识别多个源返回同一段占位画面
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Fingerprint {
+    #[serde(default)]
+    pub segment_hash: Option<String>, // 分片内容的md5
+    #[serde(default)]
+    pub frame_hash: Option<String>, // 画面的感知哈希（dHash，16位十六进制）
+}
+
+impl Fingerprint {
+    pub fn new() -> Fingerprint {
+        Fingerprint::default()
+    }
+
+    /// 根据下载的分片生成指纹
+    pub fn from_segment(bytes: &[u8]) -> Fingerprint {
+        Fingerprint {
+            segment_hash: Some(format!("{:x}", md5::compute(bytes))),
+            frame_hash: None,
+        }
+    }
+
+    pub fn set_frame_hash(&mut self, frame_hash: Option<String>) {
+        self.frame_hash = frame_hash
+    }
+
+    /// 是否为相同的内容：分片哈希相同，或画面哈希相近
+    pub fn is_same(&self, other: &Fingerprint) -> bool {
+        if let (Some(a), Some(b)) = (&self.segment_hash, &other.segment_hash) {
+            if a == b {
+                return true;
+            }
+        }
+        match (&self.frame_hash, &other.frame_hash) {
+            (Some(a), Some(b)) => frame_distance(a, b).is_some_and(|d| d <= FRAME_HASH_DISTANCE),
+            _ => false,
+        }
+    }
+}
+
+/// 两个画面哈希的汉明距离
+fn frame_distance(a: &str, b: &str) -> Option<u32> {
+    let a = u64::from_str_radix(a, 16).ok()?;
+    let b = u64::from_str_radix(b, 16).ok()?;
+    Some((a ^ b).count_ones())
+}
+
+/// 根据9x8的灰度像素计算dHash
+///
+/// # 参数
+/// * `gray` - 按行排列的灰度像素
+///
+/// # 返回值
+/// * `Option<String>` - 16位十六进制哈希，像素数量不对时为空
+pub fn dhash(gray: &[u8]) -> Option<String> {
+    if gray.len() != DHASH_WIDTH * DHASH_HEIGHT {
+        return None;
+    }
+    let mut hash = 0u64;
+    for row in gray.chunks(DHASH_WIDTH) {
+        for pair in row.windows(2) {
+            hash = (hash << 1) | (pair[0] > pair[1]) as u64;
+        }
+    }
+    Some(format!("{:016x}", hash))
+}
+
+/// 使用ffmpeg截取一帧缩放为9x8灰度图并计算画面哈希，输入可以是直播地址或已截取的图片
+///
+/// # 参数
+/// * `input` - 直播地址或图片路径
+/// * `headers` - 请求头
+/// * `timeout_mill_secs` - 超时时间（毫秒）
+/// * `proxy` - 拉流使用的代理
+pub async fn capture_frame_hash(
+    input: &str,
+    headers: &BTreeMap<String, String>,
+    timeout_mill_secs: u64,
+    proxy: Option<&ProxyConfig>,
+) -> Option<String> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-hide_banner", "-loglevel", "error"]);
+    cmd.args(ffmpeg_headers_args(headers));
+    cmd.args(ffmpeg_tls_args(input));
+    // ffmpeg不支持的代理不截取画面，避免绕过代理直连
+    cmd.args(ffmpeg_proxy_args(proxy).ok()?);
+    cmd.args([
+        "-i",
+        input,
+        "-frames:v",
+        "1",
+        "-vf",
+        "scale=9:8,format=gray",
+        "-f",
+        "rawvideo",
+        "-",
+    ]);
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::null());
+    cmd.kill_on_drop(true);
+    let child = cmd.spawn().ok()?;
+    let output = tokio::time::timeout(
+        Duration::from_millis(timeout_mill_secs),
+        child.wait_with_output(),
+    )
+    .await
+    .ok()?
+    .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    dhash(&output.stdout)
+}
+
+/// 内容相同的一组源
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FingerprintCluster {
+    pub fingerprint: Fingerprint, // 组内第一个源的指纹
+    pub channels: Vec<String>,    // 组内不同的频道名称
+    pub urls: Vec<String>,
+    pub placeholder: bool, // 频道名称数量达到阈值，认为是占位画面
+}
+
+fn find(parent: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    parent[i] = root;
+    root
+}
+
+/// 将一次检查中内容相同的源分组，只返回包含多个源的分组
+///
+/// # 参数
+/// * `list` - 检查后的源
+/// * `min_channels` - 分组中不同的频道名称达到该数量时认为是占位画面
+///
+/// # 返回值
+/// * `Vec<FingerprintCluster>` - 按源数量从多到少排序
+pub fn cluster_fingerprints(list: &[M3uObject], min_channels: usize) -> Vec<FingerprintCluster> {
+    let items = list
+        .iter()
+        .filter_map(|i| i.get_other_status().get_fingerprint().map(|f| (i, f)))
+        .collect::<Vec<_>>();
+    let mut parent = (0..items.len()).collect::<Vec<usize>>();
+    let mut by_segment: HashMap<&str, usize> = HashMap::new();
+    for (index, (_, fingerprint)) in items.iter().enumerate() {
+        if let Some(hash) = &fingerprint.segment_hash {
+            if let Some(first) = by_segment.get(hash.as_str()) {
+                let (a, b) = (find(&mut parent, *first), find(&mut parent, index));
+                parent[b] = a;
+            } else {
+                by_segment.insert(hash, index);
+            }
+        }
+    }
+    for i in 0..items.len() {
+        for j in (i + 1)..items.len() {
+            if items[i].1.frame_hash.is_some() && items[i].1.is_same(items[j].1) {
+                let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                parent[b] = a;
+            }
+        }
+    }
+    let mut groups: HashMap<usize, FingerprintCluster> = HashMap::new();
+    for (index, (item, fingerprint)) in items.iter().enumerate() {
+        let root = find(&mut parent, index);
+        let cluster = groups.entry(root).or_insert_with(|| FingerprintCluster {
+            fingerprint: (*fingerprint).clone(),
+            channels: vec![],
+            urls: vec![],
+            placeholder: false,
+        });
+        let name = item.get_name().trim().to_lowercase();
+        if !cluster.channels.contains(&name) {
+            cluster.channels.push(name);
+        }
+        if !cluster.urls.contains(&item.get_url()) {
+            cluster.urls.push(item.get_url());
+        }
+    }
+    let mut clusters = groups
+        .into_values()
+        .filter(|c| c.urls.len() > 1)
+        .map(|mut c| {
+            c.placeholder = c.channels.len() >= min_channels.max(2);
+            c
+        })
+        .collect::<Vec<_>>();
+    clusters.sort_by_key(|c| std::cmp::Reverse(c.urls.len()));
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::OtherStatus;
+
+    fn object(name: &str, url: &str, fingerprint: Fingerprint) -> M3uObject {
+        let mut item = M3uObject::new();
+        item.set_name(name.to_string());
+        item.set_url(url.to_string());
+        let mut status = OtherStatus::new();
+        status.set_fingerprint(Some(fingerprint));
+        item.set_other_status(status);
+        item
+    }
+
+    fn frame(hash: &str) -> Fingerprint {
+        let mut fingerprint = Fingerprint::new();
+        fingerprint.set_frame_hash(Some(hash.to_string()));
+        fingerprint
+    }
+
+    #[test]
+    fn cluster_placeholder_streams() {
+        let mut gray = vec![0u8; 72];
+        gray[0] = 255;
+        assert_eq!(dhash(&gray), Some("8000000000000000".to_string()));
+        assert_eq!(dhash(&gray[1..]), None);
+
+        let slate = Fingerprint::from_segment(b"channel unavailable");
+        let list = vec![
+            object("CCTV1", "http://a/1", slate.clone()),
+            object("CCTV2", "http://b/2", slate.clone()),
+            object("Sports", "http://c/3", frame("ff00ff00ff00ff00")),
+            object("News", "http://d/4", frame("ff00ff00ff00ff01")),
+            object("News", "http://e/5", frame("00ff00ff00ff00ff")),
+            object("Movie", "http://f/6", Fingerprint::from_segment(b"movie")),
+        ];
+        // 和分片哈希相同的源画面也相同时合并为一组
+        let mut linked = list.clone();
+        let mut both = slate.clone();
+        both.set_frame_hash(Some("ff00ff00ff00ff03".to_string()));
+        linked.push(object("Music", "http://g/7", both));
+
+        let clusters = cluster_fingerprints(&list, 3);
+        assert_eq!(clusters.len(), 2);
+        assert!(clusters.iter().all(|c| c.urls.len() == 2 && !c.placeholder));
+
+        let clusters = cluster_fingerprints(&linked, 3);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].urls.len(), 5);
+        assert_eq!(clusters[0].channels.len(), 5);
+        assert!(clusters[0].placeholder);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::common::CheckDataStatus::Unchecked;
+use crate::common::{CheckDataStatus, FailureReason, M3uObject, QualityType};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// 历史记录保留的天数，超过的记录在压缩时删除
+const HISTORY_KEEP_DAYS: i64 = 30;
+
+/// 多个任务可能同时写入历史文件
+static HISTORY_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// 一次检查结果，每行一条JSON追加写入历史文件
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryRecord {
+    pub url: String,
+    pub time: i64, // 检查时间（秒）
+    pub success: bool,
+    #[serde(default)]
+    pub delay: i32, // 延迟（毫秒）
+    #[serde(default)]
+    pub quality: Option<QualityType>,
+    #[serde(default)]
+    pub failure_reason: Option<FailureReason>,
+}
+
+impl HistoryRecord {
+    /// 根据检查后的源生成记录，未检查的源返回None
+    pub fn from_object(item: &M3uObject, time: i64) -> Option<HistoryRecord> {
+        if item.get_status() == Unchecked {
+            return None;
+        }
+        let status = item.get_other_status();
+        Some(HistoryRecord {
+            url: item.get_url(),
+            time,
+            success: item.get_status() == CheckDataStatus::Success,
+            delay: status.get_delay(),
+            quality: status.get_quality(),
+            failure_reason: status.get_failure_reason(),
+        })
+    }
+}
+
+/// 单个URL的历史统计
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UrlStats {
+    pub url: String,
+    pub uptime_24h: Option<f32>, // 24小时内检查成功的比例，没有检查记录时为空
+    pub uptime_7d: Option<f32>,
+    pub uptime_30d: Option<f32>,
+    pub checks_30d: i32,              // 30天内的检查次数
+    pub mean_latency: Option<f32>,    // 30天内检查成功时的平均延迟（毫秒）
+    pub last_seen_alive: Option<i64>, // 最后一次检查成功的时间（秒）
+    pub last_checked: i64,            // 最后一次检查的时间（秒）
+}
+
+/// 统计窗口内的成功次数和总次数
+#[derive(Default)]
+struct Window {
+    success: i32,
+    total: i32,
+}
+
+impl Window {
+    fn add(&mut self, success: bool) {
+        self.total += 1;
+        if success {
+            self.success += 1;
+        }
+    }
+
+    fn uptime(&self) -> Option<f32> {
+        if self.total == 0 {
+            None
+        } else {
+            Some(self.success as f32 / self.total as f32)
+        }
+    }
+}
+
+/// 追加本次检查的结果到历史文件，最早的记录已过期时删除过期记录
+///
+/// 文件读写在阻塞线程中执行，避免阻塞异步运行时
+///
+/// # 参数
+/// * `path` - 历史文件路径
+/// * `list` - 检查后的源
+/// * `time` - 检查时间（秒）
+pub async fn record_checks(path: &str, list: &[M3uObject], time: i64) -> io::Result<()> {
+    let records = list
+        .iter()
+        .filter_map(|i| HistoryRecord::from_object(i, time))
+        .collect::<Vec<HistoryRecord>>();
+    let path = path.to_string();
+    tokio::task::spawn_blocking(move || append_records(&path, &records, time))
+        .await
+        .map_err(io::Error::other)?
+}
+
+fn append_records(path: &str, records: &[HistoryRecord], time: i64) -> io::Result<()> {
+    let _lock = HISTORY_LOCK.lock().unwrap();
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut content = String::new();
+    for record in records {
+        content.push_str(&serde_json::to_string(record)?);
+        content.push('\n');
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(content.as_bytes())?;
+    // 记录按时间追加，第一行就是最早的记录，多保留一天避免每次检查都压缩；
+    // 没有过期的记录时即使文件很大也不压缩，避免每次都重写整个文件
+    let expired =
+        oldest_record_time(path).is_some_and(|t| t < time - (HISTORY_KEEP_DAYS + 1) * DAY_SECS);
+    if expired {
+        compact(path, time)?;
+    }
+    Ok(())
+}
+
+/// 读取历史文件第一条记录的时间
+fn oldest_record_time(path: &str) -> Option<i64> {
+    let mut line = String::new();
+    BufReader::new(File::open(path).ok()?)
+        .read_line(&mut line)
+        .ok()?;
+    serde_json::from_str::<HistoryRecord>(&line)
+        .ok()
+        .map(|r| r.time)
+}
+
+/// 删除超过保留天数的记录
+fn compact(path: &str, now: i64) -> io::Result<()> {
+    let records = read_records(path);
+    let tmp = format!("{}.tmp", path);
+    let mut file = File::create(&tmp)?;
+    for record in records
+        .iter()
+        .filter(|r| r.time >= now - HISTORY_KEEP_DAYS * DAY_SECS)
+    {
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+    }
+    fs::rename(tmp, path)
+}
+
+/// 读取历史文件中的所有记录，跳过无法解析的行
+pub fn read_records(path: &str) -> Vec<HistoryRecord> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return vec![],
+    };
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<HistoryRecord>(&line).ok())
+        .collect()
+}
+
+/// 按URL统计在线率、平均延迟和最后在线时间
+///
+/// # 参数
+/// * `records` - 历史记录
+/// * `now` - 当前时间（秒）
+///
+/// # 返回值
+/// * `HashMap<String, UrlStats>` - URL对应的统计
+pub fn compute_stats(records: &[HistoryRecord], now: i64) -> HashMap<String, UrlStats> {
+    let mut windows: HashMap<&str, [Window; 3]> = HashMap::new();
+    let mut latency: HashMap<&str, (i64, i64)> = HashMap::new();
+    let mut stats: HashMap<String, UrlStats> = HashMap::new();
+    for record in records {
+        let age = now - record.time;
+        if age <= HISTORY_KEEP_DAYS * DAY_SECS {
+            let w = windows.entry(record.url.as_str()).or_default();
+            for (i, days) in [1, 7, HISTORY_KEEP_DAYS].iter().enumerate() {
+                if age <= days * DAY_SECS {
+                    w[i].add(record.success);
+                }
+            }
+            if record.success {
+                let l = latency.entry(record.url.as_str()).or_insert((0, 0));
+                l.0 += record.delay as i64;
+                l.1 += 1;
+            }
+        }
+        let s = stats.entry(record.url.clone()).or_insert(UrlStats {
+            url: record.url.clone(),
+            uptime_24h: None,
+            uptime_7d: None,
+            uptime_30d: None,
+            checks_30d: 0,
+            mean_latency: None,
+            last_seen_alive: None,
+            last_checked: record.time,
+        });
+        s.last_checked = s.last_checked.max(record.time);
+        if record.success {
+            s.last_seen_alive = Some(s.last_seen_alive.unwrap_or(record.time).max(record.time));
+        }
+    }
+    for (url, s) in stats.iter_mut() {
+        if let Some(w) = windows.get(url.as_str()) {
+            s.uptime_24h = w[0].uptime();
+            s.uptime_7d = w[1].uptime();
+            s.uptime_30d = w[2].uptime();
+            s.checks_30d = w[2].total;
+        }
+        if let Some((sum, count)) = latency.get(url.as_str()) {
+            s.mean_latency = Some(*sum as f32 / *count as f32);
+        }
+    }
+    stats
+}
+
+/// 读取历史文件并统计
+pub fn load_stats(path: &str, now: i64) -> HashMap<String, UrlStats> {
+    compute_stats(&read_records(path), now)
+}
+
+/// 在阻塞线程中读取历史文件并统计，避免解析大文件时阻塞异步运行时
+///
+/// # 参数
+/// * `path` - 历史文件路径
+/// * `now` - 当前时间（秒）
+///
+/// # 返回值
+/// * `HashMap<String, UrlStats>` - URL对应的统计，读取失败时为空
+pub async fn load_stats_async(path: &str, now: i64) -> HashMap<String, UrlStats> {
+    let path = path.to_string();
+    tokio::task::spawn_blocking(move || load_stats(&path, now))
+        .await
+        .unwrap_or_default()
+}
+
+/// 比较两个源的历史表现，7天在线率高的在前，相同时平均延迟低的在前
+pub fn compare_rank(a: Option<&UrlStats>, b: Option<&UrlStats>) -> Ordering {
+    let uptime = |s: Option<&UrlStats>| s.and_then(|s| s.uptime_7d).unwrap_or(0.0);
+    let latency = |s: Option<&UrlStats>| s.and_then(|s| s.mean_latency).unwrap_or(f32::MAX);
+    uptime(b)
+        .partial_cmp(&uptime(a))
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| {
+            latency(a)
+                .partial_cmp(&latency(b))
+                .unwrap_or(Ordering::Equal)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(url: &str, time: i64, success: bool, delay: i32) -> HistoryRecord {
+        HistoryRecord {
+            url: url.to_string(),
+            time,
+            success,
+            delay,
+            quality: None,
+            failure_reason: None,
+        }
+    }
+
+    #[test]
+    fn compute_uptime_stats() {
+        let now = 100 * DAY_SECS;
+        let records = vec![
+            record("a", now - 60, true, 100),
+            record("a", now - 2 * DAY_SECS, false, 0),
+            record("a", now - 10 * DAY_SECS, true, 300),
+            record("a", now - 40 * DAY_SECS, true, 900),
+            record("b", now - 3 * DAY_SECS, false, 0),
+        ];
+        let stats = compute_stats(&records, now);
+        let a = stats.get("a").unwrap();
+        assert_eq!(a.uptime_24h, Some(1.0));
+        assert_eq!(a.uptime_7d, Some(0.5));
+        assert!((a.uptime_30d.unwrap() - 2.0 / 3.0).abs() < 0.001);
+        assert_eq!(a.checks_30d, 3);
+        assert_eq!(a.mean_latency, Some(200.0));
+        assert_eq!(a.last_seen_alive, Some(now - 60));
+        assert_eq!(a.last_checked, now - 60);
+        let b = stats.get("b").unwrap();
+        assert_eq!(b.uptime_24h, None);
+        assert_eq!(b.uptime_7d, Some(0.0));
+        assert_eq!(b.last_seen_alive, None);
+        assert_eq!(compare_rank(Some(a), Some(b)), Ordering::Less);
+    }
+
+    #[tokio::test]
+    async fn compact_expired_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checks.log");
+        let path = path.to_str().unwrap();
+        let now = 100 * DAY_SECS;
+        let mut content = String::new();
+        for r in [
+            record("a", now - 40 * DAY_SECS, true, 100),
+            record("a", now - 10 * DAY_SECS, true, 200),
+        ] {
+            content.push_str(&serde_json::to_string(&r).unwrap());
+            content.push('\n');
+        }
+        fs::write(path, content).unwrap();
+        // 文件很小，但最早的记录已超过保留天数
+        record_checks(path, &[], now).await.unwrap();
+        let records = read_records(path);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].time, now - 10 * DAY_SECS);
+        assert_eq!(oldest_record_time(path), Some(now - 10 * DAY_SECS));
+    }
+}
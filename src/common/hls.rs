@@ -0,0 +1,475 @@
+use crate::common::util::{get_codec_names, get_video_resolution};
+use crate::common::{FailureReason, VideoInfo};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// 深度检查失败所处的阶段
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum HlsCheckStage {
+    Playlist,        // 获取或解析主播放列表失败
+    VariantPlaylist, // 获取或解析子播放列表失败
+    Segment,         // 下载媒体分片失败
+    SegmentFormat,   // 分片不是有效的 MPEG-TS / fMP4
+}
+
+/// 主播放列表中的一个码率（EXT-X-STREAM-INF）
+#[derive(Debug, Clone)]
+pub struct HlsVariant {
+    pub uri: String,
+    pub bandwidth: u64,
+    pub resolution: Option<(i32, i32)>,
+    pub codecs: String,
+    pub frame_rate: Option<f32>,
+}
+
+impl HlsVariant {
+    /// 转换为视频信息，`uri` 需要已经是绝对地址
+    pub fn video_info(&self) -> VideoInfo {
+        let (width, height) = self.resolution.unwrap_or((0, 0));
+        let mut info = VideoInfo::new();
+        info.set_width(width);
+        info.set_height(height);
+        info.set_codec(get_codec_names(&self.codecs).0.unwrap_or_default());
+        info.set_bandwidth(Some(self.bandwidth).filter(|b| *b > 0));
+        info.set_frame_rate(self.frame_rate);
+        info.set_uri(Some(self.uri.clone()));
+        info.quality_type = get_video_resolution(height.max(0) as u32);
+        info
+    }
+}
+
+/// 媒体播放列表中的一个分片
+#[derive(Debug, Clone)]
+pub struct HlsSegment {
+    pub uri: String,
+    pub duration: f32,
+}
+
+/// 媒体播放列表
+#[derive(Debug, Clone)]
+pub struct HlsMediaPlaylist {
+    pub target_duration: f32,
+    pub media_sequence: u64,
+    pub end_list: bool,
+    pub map_uri: Option<String>, // fMP4 初始化分片（EXT-X-MAP）
+    pub key: Option<HlsKey>,     // 最后一个分片使用的加密方式（EXT-X-KEY）
+    pub segments: Vec<HlsSegment>,
+}
+
+/// 分片加密方式（EXT-X-KEY），METHOD=NONE 时不记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct HlsKey {
+    pub method: String, // AES-128、SAMPLE-AES、SAMPLE-AES-CTR
+    pub uri: Option<String>,
+    pub key_format: Option<String>, // KEYFORMAT，没有时为 identity
+}
+
+/// 深度检查时记录的加密状态
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HlsEncryption {
+    pub method: String,
+    pub key_uri: Option<String>, // 密钥的绝对地址
+    #[serde(default)]
+    pub key_format: Option<String>,
+    #[serde(default)]
+    pub drm: Option<String>, // DRM系统，例如 FairPlay、Widevine，普通AES加密时为空
+    #[serde(default)]
+    pub key_reachable: Option<bool>, // 密钥能否获取，无法探测的地址（如 skd://）为空
+    #[serde(default)]
+    pub key_failure: Option<FailureReason>, // 获取密钥失败的原因
+}
+
+impl HlsEncryption {
+    /// # 参数
+    /// * `key` - 播放列表中的加密方式
+    /// * `playlist_url` - 媒体播放列表地址（跳转后），用于解析密钥的绝对地址
+    pub fn new(key: &HlsKey, playlist_url: &str) -> HlsEncryption {
+        HlsEncryption {
+            method: key.method.clone(),
+            key_uri: key.uri.as_ref().map(|uri| resolve_uri(playlist_url, uri)),
+            key_format: key.key_format.clone(),
+            drm: detect_drm(key),
+            key_reachable: None,
+            key_failure: None,
+        }
+    }
+
+    pub fn set_key_reachable(&mut self, key_reachable: bool, key_failure: Option<FailureReason>) {
+        self.key_reachable = Some(key_reachable);
+        self.key_failure = key_failure;
+    }
+
+    pub fn is_drm(&self) -> bool {
+        self.drm.is_some()
+    }
+
+    /// 探测过并且密钥无法获取
+    pub fn is_key_unreachable(&self) -> bool {
+        self.key_reachable == Some(false)
+    }
+}
+
+/// 根据 KEYFORMAT 和密钥地址判断DRM系统，identity（明文密钥）不是DRM
+pub fn detect_drm(key: &HlsKey) -> Option<String> {
+    let key_format = key
+        .key_format
+        .as_deref()
+        .unwrap_or("identity")
+        .to_lowercase();
+    let is_skd = key.uri.as_deref().is_some_and(|u| u.starts_with("skd://"));
+    let drm = if key_format == "identity" {
+        if !is_skd {
+            return None;
+        }
+        "FairPlay"
+    } else if key_format == "com.apple.streamingkeydelivery" {
+        "FairPlay"
+    } else if key_format.contains("edef8ba9-79d6-4ace-a3c8-27dcd51d21ed") {
+        "Widevine"
+    } else if key_format == "com.microsoft.playready"
+        || key_format.contains("9a04f079-9840-4286-ab92-e65be0885f95")
+    {
+        "PlayReady"
+    } else {
+        return key.key_format.clone();
+    };
+    Some(drm.to_string())
+}
+
+#[derive(Debug, Clone)]
+pub enum HlsPlaylist {
+    Master(Vec<HlsVariant>),
+    Media(HlsMediaPlaylist),
+}
+
+/// 直播状态，由两次获取媒体播放列表的差异判断
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum LiveStatus {
+    Live,   // 播放列表在更新
+    Vod,    // 点播（EXT-X-ENDLIST）
+    Frozen, // 播放列表没有更新
+}
+
+/// 导出时将主播放列表固定为其中一个码率
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum VariantPin {
+    Highest, // 码率最高
+    Lowest,  // 码率最低
+}
+
+/// 分片容器格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentFormat {
+    MpegTs,
+    Fmp4,
+}
+
+/// 解析标签属性列表，例如 `BANDWIDTH=1280000,CODECS="avc1.4d401f,mp4a.40.2"`
+pub fn parse_attributes(_str: &str) -> Vec<(String, String)> {
+    let mut result = vec![];
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut in_key = true;
+    let mut in_quote = false;
+    for c in _str.chars() {
+        if in_key {
+            if c == '=' {
+                in_key = false;
+            } else if c != ',' {
+                key.push(c);
+            }
+            continue;
+        }
+        if c == '"' {
+            in_quote = !in_quote;
+        } else if c == ',' && !in_quote {
+            result.push((key.trim().to_uppercase(), value.clone()));
+            key.clear();
+            value.clear();
+            in_key = true;
+        } else {
+            value.push(c);
+        }
+    }
+    if !key.trim().is_empty() {
+        result.push((key.trim().to_uppercase(), value));
+    }
+    result
+}
+
+fn get_attribute(attrs: &[(String, String)], name: &str) -> Option<String> {
+    attrs
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.clone())
+}
+
+/// 解析 m3u8 播放列表内容
+///
+/// # 参数
+/// * `_body` - 播放列表文本
+///
+/// # 返回值
+/// * `Option<HlsPlaylist>` - 不是m3u8格式时返回None
+pub fn parse_playlist(_body: &str) -> Option<HlsPlaylist> {
+    let body = _body.trim_start_matches('\u{feff}').trim_start();
+    if !body.starts_with("#EXTM3U") {
+        return None;
+    }
+    let mut variants = vec![];
+    let mut media = HlsMediaPlaylist {
+        target_duration: 0.0,
+        media_sequence: 0,
+        end_list: false,
+        map_uri: None,
+        key: None,
+        segments: vec![],
+    };
+    let mut pending_variant: Option<HlsVariant> = None;
+    let mut pending_duration: Option<f32> = None;
+    for line in body.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(attr_str) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            let attrs = parse_attributes(attr_str);
+            let resolution = get_attribute(&attrs, "RESOLUTION").and_then(|r| {
+                let (w, h) = r.split_once(['x', 'X'])?;
+                Some((w.trim().parse().ok()?, h.trim().parse().ok()?))
+            });
+            pending_variant = Some(HlsVariant {
+                uri: String::new(),
+                bandwidth: get_attribute(&attrs, "BANDWIDTH")
+                    .and_then(|b| b.parse().ok())
+                    .unwrap_or(0),
+                resolution,
+                codecs: get_attribute(&attrs, "CODECS").unwrap_or_default(),
+                frame_rate: get_attribute(&attrs, "FRAME-RATE").and_then(|f| f.parse().ok()),
+            });
+        } else if let Some(v) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            media.target_duration = v.trim().parse().unwrap_or(0.0);
+        } else if let Some(v) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            media.media_sequence = v.trim().parse().unwrap_or(0);
+        } else if line.starts_with("#EXT-X-ENDLIST") {
+            media.end_list = true;
+        } else if let Some(attr_str) = line.strip_prefix("#EXT-X-KEY:") {
+            let attrs = parse_attributes(attr_str);
+            let method = get_attribute(&attrs, "METHOD").unwrap_or_default();
+            media.key = if method.is_empty() || method == "NONE" {
+                None
+            } else {
+                Some(HlsKey {
+                    method,
+                    uri: get_attribute(&attrs, "URI"),
+                    key_format: get_attribute(&attrs, "KEYFORMAT"),
+                })
+            };
+        } else if let Some(attr_str) = line.strip_prefix("#EXT-X-MAP:") {
+            media.map_uri = get_attribute(&parse_attributes(attr_str), "URI");
+        } else if let Some(v) = line.strip_prefix("#EXTINF:") {
+            let duration = v.split(',').next().unwrap_or("0");
+            pending_duration = Some(duration.trim().parse().unwrap_or(0.0));
+        } else if !line.starts_with('#') {
+            if let Some(mut variant) = pending_variant.take() {
+                variant.uri = line.to_string();
+                variants.push(variant);
+            } else if let Some(duration) = pending_duration.take() {
+                media.segments.push(HlsSegment {
+                    uri: line.to_string(),
+                    duration,
+                });
+            }
+        }
+    }
+    if !variants.is_empty() {
+        return Some(HlsPlaylist::Master(variants));
+    }
+    Some(HlsPlaylist::Media(media))
+}
+
+/// 将播放列表中的相对地址转换为绝对地址
+pub fn resolve_uri(base: &str, uri: &str) -> String {
+    match Url::parse(base).and_then(|b| b.join(uri)) {
+        Ok(u) => u.to_string(),
+        Err(_) => uri.to_string(),
+    }
+}
+
+/// 将主播放列表中的码率转换为视频信息，按码率从高到低排列，只有音频的码率会被忽略
+///
+/// # 参数
+/// * `master_url` - 主播放列表地址（跳转后），用于解析码率的绝对地址
+/// * `variants` - 主播放列表中的码率
+///
+/// # 返回值
+/// * `Vec<VideoInfo>` - 视频信息
+pub fn master_video_info(master_url: &str, variants: &[HlsVariant]) -> Vec<VideoInfo> {
+    let mut list: Vec<&HlsVariant> = variants
+        .iter()
+        .filter(|v| {
+            v.resolution.is_some() || v.codecs.is_empty() || get_codec_names(&v.codecs).0.is_some()
+        })
+        .collect();
+    list.sort_by_key(|v| std::cmp::Reverse(v.bandwidth));
+    list.iter()
+        .map(|v| {
+            let mut info = v.video_info();
+            info.set_uri(Some(resolve_uri(master_url, &v.uri)));
+            info
+        })
+        .collect()
+}
+
+/// 根据分片头部字节判断容器格式
+///
+/// MPEG-TS 每188字节以0x47同步字节开头；fMP4 以 ftyp/styp/moof 等box开头
+pub fn detect_segment_format(bytes: &[u8]) -> Option<SegmentFormat> {
+    const TS_PACKET_SIZE: usize = 188;
+    if bytes.len() >= TS_PACKET_SIZE * 3 && (0..3).all(|i| bytes[i * TS_PACKET_SIZE] == 0x47) {
+        return Some(SegmentFormat::MpegTs);
+    }
+    if bytes.len() >= 8 {
+        let box_type = &bytes[4..8];
+        let fmp4_boxes: [&[u8]; 6] = [b"ftyp", b"styp", b"moof", b"sidx", b"emsg", b"prft"];
+        if fmp4_boxes.contains(&box_type) {
+            return Some(SegmentFormat::Fmp4);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_master_playlist() {
+        let body = r#"#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=1280x720,CODECS="avc1.4d401f,mp4a.40.2",FRAME-RATE=25.000
+720/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=640000,RESOLUTION=640x360
+360/index.m3u8"#;
+        match parse_playlist(body) {
+            Some(HlsPlaylist::Master(variants)) => {
+                assert_eq!(variants.len(), 2);
+                assert_eq!(variants[0].uri, "720/index.m3u8");
+                assert_eq!(variants[0].bandwidth, 1280000);
+                assert_eq!(variants[0].resolution, Some((1280, 720)));
+                assert_eq!(variants[0].codecs, "avc1.4d401f,mp4a.40.2");
+                assert_eq!(variants[0].frame_rate, Some(25.0));
+                assert_eq!(variants[1].resolution, Some((640, 360)));
+            }
+            _ => panic!("expect master playlist"),
+        }
+    }
+
+    #[test]
+    fn master_variants_to_video_info() {
+        let body = r#"#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=640000,RESOLUTION=640x360,CODECS="mp4a.40.2,avc1.42e01e"
+360/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS="mp4a.40.2"
+audio/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=4000000,RESOLUTION=1920x1080,CODECS="hvc1.2.4.L123.B0",FRAME-RATE=50
+https://cdn.b.com/1080/index.m3u8"#;
+        let variants = match parse_playlist(body) {
+            Some(HlsPlaylist::Master(variants)) => variants,
+            _ => panic!("expect master playlist"),
+        };
+        let list = master_video_info("http://a.com/live/master.m3u8", &variants);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].height, 1080);
+        assert_eq!(list[0].codec, "hevc");
+        assert_eq!(list[0].bandwidth, Some(4000000));
+        assert_eq!(list[0].frame_rate, Some(50.0));
+        assert_eq!(
+            list[0].uri.as_deref(),
+            Some("https://cdn.b.com/1080/index.m3u8")
+        );
+        assert_eq!(list[1].codec, "h264");
+        assert_eq!(list[1].width, 640);
+        assert_eq!(
+            list[1].uri.as_deref(),
+            Some("http://a.com/live/360/index.m3u8")
+        );
+    }
+
+    #[test]
+    fn parse_encryption_key() {
+        let body = "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key?id=1\",IV=0x1\n#EXTINF:6,\n1.ts\n#EXT-X-KEY:METHOD=NONE\n#EXTINF:6,\n2.ts\n#EXT-X-KEY:METHOD=AES-128,URI=\"https://k.com/2.key\"\n#EXTINF:6,\n3.ts";
+        let media = match parse_playlist(body) {
+            Some(HlsPlaylist::Media(media)) => media,
+            _ => panic!("expect media playlist"),
+        };
+        let key = media.key.unwrap();
+        assert_eq!(key.method, "AES-128");
+        let encryption = HlsEncryption::new(&key, "http://a.com/live/index.m3u8");
+        assert_eq!(encryption.key_uri.as_deref(), Some("https://k.com/2.key"));
+        assert!(!encryption.is_drm());
+
+        let fairplay = HlsKey {
+            method: "SAMPLE-AES".to_string(),
+            uri: Some("skd://key-id".to_string()),
+            key_format: Some("com.apple.streamingkeydelivery".to_string()),
+        };
+        assert_eq!(detect_drm(&fairplay).as_deref(), Some("FairPlay"));
+        let widevine = HlsKey {
+            method: "SAMPLE-AES-CTR".to_string(),
+            uri: Some("data:text/plain;base64,AAAA".to_string()),
+            key_format: Some("urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed".to_string()),
+        };
+        assert_eq!(detect_drm(&widevine).as_deref(), Some("Widevine"));
+
+        let body = "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"1.key\"\n#EXTINF:6,\n1.ts\n#EXT-X-KEY:METHOD=NONE\n#EXTINF:6,\n2.ts";
+        match parse_playlist(body) {
+            Some(HlsPlaylist::Media(media)) => assert!(media.key.is_none()),
+            _ => panic!("expect media playlist"),
+        }
+    }
+
+    #[test]
+    fn parse_media_playlist() {
+        let body = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-MEDIA-SEQUENCE:120\n#EXT-X-MAP:URI=\"init.mp4\"\n#EXTINF:6.000,\nseg120.m4s\n#EXTINF:5.5,\nseg121.m4s\n#EXT-X-ENDLIST";
+        match parse_playlist(body) {
+            Some(HlsPlaylist::Media(media)) => {
+                assert_eq!(media.target_duration, 6.0);
+                assert_eq!(media.media_sequence, 120);
+                assert!(media.end_list);
+                assert_eq!(media.map_uri.as_deref(), Some("init.mp4"));
+                assert_eq!(media.segments.len(), 2);
+                assert_eq!(media.segments[1].uri, "seg121.m4s");
+                assert_eq!(media.segments[1].duration, 5.5);
+            }
+            _ => panic!("expect media playlist"),
+        }
+        assert!(parse_playlist("<html></html>").is_none());
+    }
+
+    #[test]
+    fn resolve_relative_uri() {
+        assert_eq!(
+            resolve_uri("http://a.com/live/index.m3u8?token=1", "seg1.ts"),
+            "http://a.com/live/seg1.ts"
+        );
+        assert_eq!(
+            resolve_uri("http://a.com/live/index.m3u8", "http://b.com/x.ts"),
+            "http://b.com/x.ts"
+        );
+    }
+
+    #[test]
+    fn detect_format() {
+        let mut ts = vec![0u8; 188 * 3];
+        for i in 0..3 {
+            ts[i * 188] = 0x47;
+        }
+        assert_eq!(detect_segment_format(&ts), Some(SegmentFormat::MpegTs));
+        let fmp4 = [0, 0, 0, 24, b'm', b'o', b'o', b'f', 0, 0, 0, 0];
+        assert_eq!(detect_segment_format(&fmp4), Some(SegmentFormat::Fmp4));
+        assert_eq!(
+            detect_segment_format(b"<html><body>404</body></html>"),
+            None
+        );
+    }
+}
@@ -1,26 +1,49 @@
-use crate::common::check::check::check_link_is_valid;
+use crate::common::cache::{cache_key, get_cached, save_cached, CacheEntry, CacheTtl};
+use crate::common::cancel::{get_cancel_token, CancelToken};
+use crate::common::check::check::{
+    check_link_by_address, check_link_is_valid, new_check_client, CheckClient, HostLimiter,
+};
 use crate::common::cmd::capture_stream_pic;
+use crate::common::dns::resolve_host;
+use crate::common::fingerprint::{
+    capture_frame_hash, cluster_fingerprints, Fingerprint, FingerprintCluster,
+};
+use crate::common::history::{compare_rank, load_stats_async, record_checks, UrlStats};
+use crate::common::hls::{HlsCheckStage, HlsEncryption, LiveStatus, VariantPin};
+use crate::common::progress::{set_progress_total, update_progress};
+use crate::common::proxy::ProxyConfig;
+use crate::common::redirect::{ExpiryAction, RedirectInfo};
 use crate::common::task::md5_str;
+use crate::common::timing::TimingInfo;
 use crate::common::translate::trad_to_simp;
+use crate::common::util::encode_url_header_value;
 use crate::common::CheckDataStatus::{Failed, Success, Unchecked};
-use crate::common::FfmpegInfo;
 use crate::common::QualityType::QualityUnknown;
 use crate::common::SourceType::{Normal, Quota};
-use crate::search::generate_channel_thumbnail_folder_name;
-use crate::utils::{
-    get_host_ip_address, get_url_host_and_port, is_ipv4, is_ipv6, is_valid_ip, remove_other_char,
+use crate::common::{
+    CheckError, CheckUrlIsAvailableResponse, ContentHealth, ContentIssue, FailureReason,
+    FfmpegInfo, ThroughputInfo,
 };
+use crate::config::base::get_base_config;
+use crate::config::get_now_check_task_id;
+use crate::config::placeholder::PlaceholderConfig;
+use crate::r#const::constant::HISTORY_FILE;
+use crate::search::generate_channel_thumbnail_folder_name;
+use crate::utils::{get_url_host_and_port, is_ipv4, is_ipv6, remove_other_char};
 use actix_rt::time;
+use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
 use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
 use std::cmp::PartialEq;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::{self, Write};
-use std::sync::mpsc;
-use std::sync::{Arc, Mutex};
+use std::net::IpAddr;
+use std::sync::Arc;
 use std::time::Duration;
-use std::{thread, vec};
+use std::vec;
+use tokio::sync::Semaphore;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct M3uExtend {
@@ -32,6 +55,8 @@ pub struct M3uExtend {
     pub tv_id: String,             //电视id
     pub user_agent: String,        // user-agent
     pub thumbnail: Option<String>, //缩略图
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>, //请求源时需要附带的请求头
 }
 
 impl M3uExtend {
@@ -45,6 +70,7 @@ impl M3uExtend {
             user_agent: "".to_string(),
             thumbnail: None,
             tv_name: "".to_string(),
+            headers: BTreeMap::new(),
         }
     }
 
@@ -77,8 +103,18 @@ impl M3uExtend {
     }
 
     pub fn set_user_agent(&mut self, user_agent: String) {
+        if user_agent.is_empty() {
+            self.headers.remove("User-Agent");
+        } else {
+            self.headers
+                .insert("User-Agent".to_string(), user_agent.clone());
+        }
         self.user_agent = user_agent
     }
+
+    pub fn set_header(&mut self, name: String, value: String) {
+        self.headers.insert(name, value);
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -114,41 +150,86 @@ impl M3uObject {
         };
     }
 
-    pub fn get_obj(self) -> M3uObject {
-        self
-    }
-
-    pub fn to_ip_address(&mut self) {
-        let (host_str, port) = get_url_host_and_port(&self.url);
-        if is_valid_ip(&host_str) {
-            self.other_status.set_ip_address(vec![host_str]);
-        } else {
-            self.other_status
-                .set_ip_address(get_host_ip_address(&host_str, port));
-        }
-    }
-
-    pub fn check_by_block(&mut self, request_time: i32, ffmpeg_check: bool, not_http_skip: bool) {
-        let url = self.url.clone();
-        let _log_url = url.clone();
-        let result = actix_rt::System::new().block_on(check_link_is_valid(
-            url,
-            request_time as u64,
-            ffmpeg_check,
-            not_http_skip,
-        ));
+    /// 使用共享的http客户端检查当前源
+    ///
+    /// # 参数
+    /// * `client` - http客户端
+    /// * `opt` - 检查参数
+    pub async fn check(&mut self, client: &CheckClient, opt: &CheckOptions) {
+        let start = std::time::Instant::now();
+        let headers = self.get_headers();
+        let mut attempts = 0;
+        let mut first_failure_reason = None;
+        let result = loop {
+            attempts += 1;
+            let result = check_link_is_valid(client, self.url.clone(), &headers, opt).await;
+            let reason = match &result {
+                Ok(_) => break result,
+                Err(e) => CheckError::reason_of(e),
+            };
+            first_failure_reason.get_or_insert(reason);
+            if !opt.retry.should_retry(attempts, reason) {
+                break result;
+            }
+            debug!(
+                "retry {} after {:?}, attempts {}",
+                self.url, reason, attempts
+            );
+            time::sleep(opt.retry.get_backoff(attempts)).await;
+        };
+        let elapsed = start.elapsed().as_millis() as i64;
         debug!("url is: {} result: {:?}", self.url.clone(), result);
-        return match result {
+        match result {
             Ok(data) => {
-                let mut o_status = OtherStatus::new();
-                o_status.set_delay(data.delay);
-                o_status.set_ffmpeg_info(data.ffmpeg_info);
+                let mut o_status = OtherStatus::from_response(data);
                 o_status.set_ip_address(self.other_status.ip_address.clone());
+                o_status.set_elapsed(elapsed);
+                o_status.set_attempts(attempts, first_failure_reason, true);
                 self.set_other_status(o_status);
                 self.set_status(Success);
             }
-            Err(_e) => self.set_status(Failed),
-        };
+            Err(e) => {
+                self.other_status.set_elapsed(elapsed);
+                self.other_status.set_failure(&e);
+                self.other_status
+                    .set_attempts(attempts, first_failure_reason, false);
+                self.set_status(Failed)
+            }
+        }
+        // 走代理时由代理连接目标地址，无法指定IP，不区分地址检查
+        if opt.family_check > 0 && opt.proxy.is_none() && self.url.starts_with("http") {
+            self.check_addresses(opt).await;
+        }
+    }
+
+    /// 分别连接解析到的IP检查当前源，记录每个地址的检查结果
+    ///
+    /// # 参数
+    /// * `opt` - 检查参数，`family_check` 为1时每个地址族只检查第一个IP，为2时检查每个IP
+    async fn check_addresses(&mut self, opt: &CheckOptions) {
+        let mut ips = self
+            .other_status
+            .ip_address
+            .iter()
+            .filter_map(|ip| ip.parse::<IpAddr>().ok())
+            .collect::<Vec<IpAddr>>();
+        if opt.family_check == 1 {
+            let v4 = ips.iter().find(|ip| ip.is_ipv4()).copied();
+            let v6 = ips.iter().find(|ip| ip.is_ipv6()).copied();
+            ips = v4.into_iter().chain(v6).collect();
+        }
+        let headers = self.get_headers();
+        let results = join_all(
+            ips.iter()
+                .map(|ip| check_link_by_address(self.url.clone(), &headers, opt, *ip)),
+        )
+        .await;
+        let list = ips
+            .iter()
+            .zip(results)
+            .map(|(ip, result)| AddressStatus::new(*ip, &result))
+            .collect();
+        self.other_status.set_address_status(list);
     }
 
     pub fn set_index(&mut self, index: i32) {
@@ -231,9 +312,9 @@ impl M3uObject {
         self.url.clone()
     }
 
-    // pub fn get_name(&self) -> String {
-    //     self.name.clone()
-    // }
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
 
     pub fn set_search_name(&mut self, search_name: String) {
         self.search_name = search_name.to_lowercase()
@@ -243,6 +324,19 @@ impl M3uObject {
         self.raw = raw
     }
 
+    #[cfg(test)]
+    pub fn get_raw(&self) -> String {
+        self.raw.clone()
+    }
+
+    /// 获取请求该源时需要附带的请求头
+    pub fn get_headers(&self) -> BTreeMap<String, String> {
+        match &self.extend {
+            Some(extend) => extend.headers.clone(),
+            None => BTreeMap::new(),
+        }
+    }
+
     pub fn generate_raw(&mut self) {
         let mut header = String::from("#EXTINF:-1");
         if let Some(extend) = &self.extend {
@@ -274,7 +368,45 @@ impl M3uObject {
             let tvg_id = crate::epg_mapping::get_best_tvg_id(None, &self.name);
             header.push_str(&format!(" tvg-id=\"{}\"", tvg_id));
         }
-        self.raw = format!("{},{}\n{}", header, self.name, self.url);
+        // 标记使用DRM保护的源，普通播放器无法播放
+        if let Some(drm) = self
+            .other_status
+            .get_encryption()
+            .and_then(|e| e.drm.as_ref())
+        {
+            header.push_str(&format!(" drm=\"{}\"", drm));
+        }
+        // 标记通过代理检查的源，播放时可能也需要使用同一个代理
+        if let Some(proxy) = self.other_status.get_proxy() {
+            header.push_str(&format!(" proxy=\"{}\"", proxy));
+        }
+        // 标记在下次任务运行前就会过期的源
+        if self.other_status.expiring {
+            if let Some(expires_at) = self.other_status.get_expires_at() {
+                if let Some(time) = chrono::DateTime::from_timestamp(expires_at, 0) {
+                    header.push_str(&format!(
+                        " expires=\"{}\"",
+                        time.with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                    ));
+                }
+            }
+        }
+        // User-Agent和Referer写成播放器选项，其它请求头附加在地址后面
+        let mut opts = String::new();
+        let mut url_headers = vec![];
+        for (name, value) in self.get_headers() {
+            match name.as_str() {
+                "User-Agent" => opts.push_str(&format!("#EXTVLCOPT:http-user-agent={}\n", value)),
+                "Referer" => opts.push_str(&format!("#EXTVLCOPT:http-referrer={}\n", value)),
+                _ => url_headers.push(format!("{}={}", name, encode_url_header_value(&value))),
+            }
+        }
+        let mut url = self.url.clone();
+        if !url_headers.is_empty() {
+            url = format!("{}|{}", url, url_headers.join("&"));
+        }
+        self.raw = format!("{},{}\n{}{}", header, self.name, opts, url);
     }
 
     pub fn set_extend(&mut self, extend: M3uExtend) {
@@ -285,9 +417,17 @@ impl M3uObject {
         self.other_status = other_status
     }
 
+    pub fn get_other_status(&self) -> &OtherStatus {
+        &self.other_status
+    }
+
     pub fn set_status(&mut self, status: CheckDataStatus) {
         self.status = status;
     }
+
+    pub fn get_status(&self) -> CheckDataStatus {
+        self.status.clone()
+    }
 }
 
 #[derive(Copy, Clone, Serialize, Deserialize)]
@@ -295,6 +435,8 @@ pub struct M3uObjectListCounter {
     check_index: i32, // 当前检查的索引
     total: i32,       // 总数
     success_count: i32, // 成功数据
+    #[serde(default)]
+    cached_count: i32, // 使用缓存结果的数量
                       // repeat_channel_count: i32,    // 频道名称重复数
                       // no_repeat_channel_count: i32, // 频道名称最终保存数
 }
@@ -312,6 +454,7 @@ impl M3uObjectListCounter {
             check_index: 0,
             total: 0,
             success_count: 0,
+            cached_count: 0,
             // repeat_channel_count: 0,
             // no_repeat_channel_count: 0,
         }
@@ -331,8 +474,16 @@ impl M3uObjectListCounter {
         self.total = total
     }
 
+    pub fn set_cached_count(&mut self, cached_count: i32) {
+        self.cached_count = cached_count
+    }
+
     pub fn print_now_status(self) {
-        debug!("\r检查进度: {}/{}\n", self.check_index, self.total);
+        debug!(
+            "\r检查进度: {}/{}\n",
+            self.check_index,
+            self.total - self.cached_count
+        );
         io::stdout().flush().unwrap();
     }
 }
@@ -357,7 +508,154 @@ pub struct SearchOptions {
     pub quality: Vec<QualityType>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// 导出时过滤源的条件，包括地址族、关键字、清晰度和检查结果，字段为空表示不过滤
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ExportFilter {
+    #[serde(default)]
+    pub ip_type: i32, // 0:全部 1:只保留IPv4 2:只保留IPv6
+    #[serde(default)]
+    pub like_list: Vec<String>, // 频道名包含其中任一关键字
+    #[serde(default)]
+    pub full_list: Vec<String>, // 频道名等于其中任一关键字
+    #[serde(default)]
+    pub quality_type_list: Vec<QualityType>, // 只保留这些清晰度的源
+    #[serde(default)]
+    pub live_status: Vec<LiveStatus>, // 只保留这些直播状态的源
+    #[serde(default)]
+    pub min_throughput_ratio: f32, // 下载速度与所需码率之比的下限，0表示不限制
+    #[serde(default)]
+    pub exclude_flaky: bool, // 排除重试后才成功的源
+    #[serde(default)]
+    pub exclude_content: Vec<ContentIssue>, // 排除检测到这些画面异常的源
+    #[serde(default)]
+    pub exclude_placeholder: bool, // 排除占位画面
+    #[serde(default)]
+    pub pin_variant: Option<VariantPin>, // 将主播放列表的地址替换为其中一个码率的地址
+    #[serde(default)]
+    pub exclude_key_unreachable: bool, // 排除加密密钥无法获取的源
+    #[serde(default)]
+    pub expiry_action: Option<ExpiryAction>, // 如何处理在expire_before之前过期的源
+    #[serde(default)]
+    pub expire_before: i64, // 秒级时间戳，一般为任务下次运行时间
+}
+
+impl ExportFilter {
+    pub fn new() -> ExportFilter {
+        ExportFilter {
+            ip_type: 0,
+            like_list: vec![],
+            full_list: vec![],
+            quality_type_list: vec![],
+            live_status: vec![],
+            min_throughput_ratio: 0.0,
+            exclude_flaky: false,
+            exclude_content: vec![],
+            exclude_placeholder: false,
+            pin_variant: None,
+            exclude_key_unreachable: false,
+            expiry_action: None,
+            expire_before: 0,
+        }
+    }
+
+    pub fn set_ip_type(&mut self, ip_type: i32) {
+        self.ip_type = ip_type
+    }
+
+    pub fn set_quality_type_list(&mut self, quality_type_list: Vec<QualityType>) {
+        self.quality_type_list = quality_type_list
+    }
+
+    pub fn set_live_status(&mut self, live_status: Vec<LiveStatus>) {
+        self.live_status = live_status
+    }
+
+    pub fn set_min_throughput_ratio(&mut self, min_throughput_ratio: f32) {
+        self.min_throughput_ratio = min_throughput_ratio
+    }
+
+    pub fn set_exclude_flaky(&mut self, exclude_flaky: bool) {
+        self.exclude_flaky = exclude_flaky
+    }
+
+    pub fn set_exclude_content(&mut self, exclude_content: Vec<ContentIssue>) {
+        self.exclude_content = exclude_content
+    }
+
+    pub fn set_exclude_placeholder(&mut self, exclude_placeholder: bool) {
+        self.exclude_placeholder = exclude_placeholder
+    }
+
+    pub fn set_pin_variant(&mut self, pin_variant: Option<VariantPin>) {
+        self.pin_variant = pin_variant
+    }
+
+    pub fn set_exclude_key_unreachable(&mut self, exclude_key_unreachable: bool) {
+        self.exclude_key_unreachable = exclude_key_unreachable
+    }
+
+    /// # 参数
+    /// * `expiry_action` - 如何处理过期的源，None表示不处理
+    /// * `expire_before` - 在这个时间（秒级时间戳）之前过期的源才处理
+    pub fn set_expiry_action(&mut self, expiry_action: Option<ExpiryAction>, expire_before: i64) {
+        self.expiry_action = expiry_action;
+        self.expire_before = expire_before
+    }
+
+    /// 源是否在expire_before之前过期，未设置处理方式时返回false
+    pub fn is_expiring(&self, status: &OtherStatus) -> bool {
+        self.expiry_action.is_some() && status.expires_before(self.expire_before)
+    }
+
+    /// 检查结果是否满足过滤条件
+    ///
+    /// # 参数
+    /// * `status` - 源的检查结果
+    ///
+    /// # 返回值
+    /// * `bool` - 满足返回true
+    pub fn is_match(&self, status: &OtherStatus) -> bool {
+        if !self.live_status.is_empty() {
+            match status.live_status {
+                Some(live_status) => {
+                    if !self.live_status.contains(&live_status) {
+                        return false;
+                    }
+                }
+                None => return false,
+            }
+        }
+        if self.exclude_flaky && status.is_flaky() {
+            return false;
+        }
+        if self
+            .exclude_content
+            .iter()
+            .any(|i| status.has_content_issue(*i))
+        {
+            return false;
+        }
+        if self.exclude_placeholder && status.placeholder {
+            return false;
+        }
+        if self.exclude_key_unreachable && status.is_key_unreachable() {
+            return false;
+        }
+        if self.expiry_action == Some(ExpiryAction::Drop) && self.is_expiring(status) {
+            return false;
+        }
+        // 没有测速结果的源无法证明能流畅播放
+        if self.min_throughput_ratio > 0.0 {
+            let ratio = status.throughput.as_ref().and_then(|t| t.ratio);
+            if ratio.unwrap_or(0.0) < self.min_throughput_ratio {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct CheckOptions {
     pub request_time: i32,
     pub concurrent: i32,
@@ -366,6 +664,85 @@ pub struct CheckOptions {
     pub ffmpeg_check: bool,
     pub same_save_num: i32,
     pub not_http_skip: bool,
+    pub deep_check: bool,
+    pub per_host_concurrent: i32, // 同一域名最大并发数，0表示不限制
+    pub liveness_check: bool,     // 是否检测直播状态（直播/点播/冻结）
+    pub throughput_segments: i32, // 测速下载的分片数量，0表示不测速
+    #[serde(default)]
+    pub content_check: i32, // 黑屏、静帧、静音检测时长（秒），0表示不检测
+    #[serde(default)]
+    pub fingerprint_check: bool, // 是否生成内容指纹用于识别占位画面
+    pub timing_check: bool,       // 是否记录DNS、连接、TLS、首字节、首个分片的耗时
+    pub family_check: i32,        // 0:不区分地址 1:分别检查IPv4/IPv6 2:分别检查每个IP
+    pub retry: RetryPolicy,       // 失败重试策略
+    pub cache_ttl: CacheTtl,      // 检查结果缓存有效期
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>, // 检查和ffprobe使用的代理
+}
+
+/// 检查失败后的重试策略
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetryPolicy {
+    #[serde(default)]
+    pub attempts: i32, // 最多重试次数，0表示不重试
+    #[serde(default = "RetryPolicy::default_backoff")]
+    pub backoff: u64, // 第一次重试前的等待时间（毫秒），之后每次翻倍
+    #[serde(default = "RetryPolicy::default_retry_on")]
+    pub retry_on: Vec<FailureReason>, // 可以重试的失败原因
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new()
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> RetryPolicy {
+        RetryPolicy {
+            attempts: 0,
+            backoff: RetryPolicy::default_backoff(),
+            retry_on: RetryPolicy::default_retry_on(),
+        }
+    }
+
+    fn default_backoff() -> u64 {
+        1000
+    }
+
+    /// 默认只重试偶发的网络错误，404、格式错误等重试也不会成功
+    fn default_retry_on() -> Vec<FailureReason> {
+        vec![
+            FailureReason::Dns,
+            FailureReason::Connect,
+            FailureReason::Timeout,
+            FailureReason::Body,
+            FailureReason::FfprobeTimeout,
+        ]
+    }
+
+    pub fn set_attempts(&mut self, attempts: i32) {
+        self.attempts = attempts
+    }
+
+    pub fn set_backoff(&mut self, backoff: u64) {
+        self.backoff = backoff
+    }
+
+    pub fn set_retry_on(&mut self, retry_on: Vec<FailureReason>) {
+        self.retry_on = retry_on
+    }
+
+    /// 第 `attempts` 次检查失败后是否需要重试
+    pub fn should_retry(&self, attempts: i32, reason: FailureReason) -> bool {
+        attempts <= self.attempts && self.retry_on.contains(&reason)
+    }
+
+    /// 第 `attempts` 次检查失败后的等待时间，指数退避
+    pub fn get_backoff(&self, attempts: i32) -> Duration {
+        let factor = 1u64 << (attempts - 1).clamp(0, 10);
+        Duration::from_millis(self.backoff.saturating_mul(factor))
+    }
 }
 
 impl M3uObjectList {
@@ -417,7 +794,96 @@ impl M3uObjectList {
     pub fn print_result(&mut self) -> String {
         let succ_num = self.counter.success_count;
         let failed_num = self.counter.total - succ_num;
-        format!("有效源: {}, 无效源: {}", succ_num, failed_num)
+        let mut result = format!("有效源: {}, 无效源: {}", succ_num, failed_num);
+        if self.counter.cached_count > 0 {
+            result.push_str(&format!(
+                ", 缓存结果: {}, 新检查: {}",
+                self.counter.cached_count,
+                self.counter.total - self.counter.cached_count
+            ));
+        }
+        let reasons = self
+            .failure_reason_counts()
+            .iter()
+            .map(|(reason, count)| format!("{:?}: {}", reason, count))
+            .collect::<Vec<String>>();
+        if !reasons.is_empty() {
+            result.push_str(&format!(", 失败原因 [{}]", reasons.join(", ")));
+        }
+        let retried_num = self
+            .list
+            .iter()
+            .filter(|i| i.other_status.get_attempts() > 1)
+            .count();
+        if retried_num > 0 {
+            let flaky_num = self
+                .list
+                .iter()
+                .filter(|i| i.status == Success && i.other_status.is_flaky())
+                .count();
+            result.push_str(&format!(", 重试后成功: {}/{}", flaky_num, retried_num));
+        }
+        for (issue, title) in [
+            (ContentIssue::Black, "黑屏"),
+            (ContentIssue::Frozen, "静帧"),
+            (ContentIssue::Silent, "静音"),
+        ] {
+            let num = self
+                .list
+                .iter()
+                .filter(|i| i.status == Success && i.other_status.has_content_issue(issue))
+                .count();
+            if num > 0 {
+                result.push_str(&format!(", {}: {}", title, num));
+            }
+        }
+        let placeholder_num = self
+            .list
+            .iter()
+            .filter(|i| i.status == Success && i.other_status.placeholder)
+            .count();
+        if placeholder_num > 0 {
+            result.push_str(&format!(", 占位画面: {}", placeholder_num));
+        }
+        let encrypted = self
+            .list
+            .iter()
+            .filter(|i| i.status == Success)
+            .filter_map(|i| i.other_status.get_encryption())
+            .collect::<Vec<&HlsEncryption>>();
+        if !encrypted.is_empty() {
+            result.push_str(&format!(
+                ", 加密: {} (DRM: {}, 密钥无法获取: {})",
+                encrypted.len(),
+                encrypted.iter().filter(|e| e.is_drm()).count(),
+                encrypted.iter().filter(|e| e.is_key_unreachable()).count()
+            ));
+        }
+        let expiry_num = self
+            .list
+            .iter()
+            .filter(|i| i.status == Success && i.other_status.get_expires_at().is_some())
+            .count();
+        if expiry_num > 0 {
+            result.push_str(&format!(", 地址带过期时间: {}", expiry_num));
+        }
+        result
+    }
+
+    /// 统计各失败原因的数量
+    pub fn failure_reason_counts(&self) -> BTreeMap<FailureReason, i32> {
+        let mut counts = BTreeMap::new();
+        for item in &self.list {
+            if item.status != Failed {
+                continue;
+            }
+            let reason = item
+                .other_status
+                .failure_reason
+                .unwrap_or(FailureReason::Other);
+            *counts.entry(reason).or_insert(0) += 1;
+        }
+        counts
     }
 
     pub fn set_counter(&mut self, counter: M3uObjectListCounter) {
@@ -430,9 +896,37 @@ impl M3uObjectList {
         }
     }
 
-    pub fn to_ip_address(&mut self) {
+    /// 并发解析所有源的域名，相同域名只解析一次，解析失败的源记录为域名解析失败
+    pub async fn to_ip_address(&mut self) {
+        let config = get_base_config().dns;
+        let hosts: HashSet<String> = self
+            .list
+            .iter()
+            .map(|item| get_url_host_and_port(&item.url).0)
+            .filter(|host| !host.is_empty())
+            .collect();
+        let results: HashMap<String, Result<Vec<IpAddr>, String>> = futures::stream::iter(hosts)
+            .map(|host| {
+                let config = &config;
+                async move {
+                    let result = resolve_host(&host, config).await.map_err(|e| e.msg);
+                    (host, result)
+                }
+            })
+            .buffer_unordered(config.concurrent.max(1))
+            .collect()
+            .await;
         for item in &mut self.list {
-            item.to_ip_address();
+            let (host, _) = get_url_host_and_port(&item.url);
+            match results.get(&host) {
+                Some(Ok(ips)) => item
+                    .other_status
+                    .set_ip_address(ips.iter().map(|ip| ip.to_string()).collect()),
+                Some(Err(msg)) => item
+                    .other_status
+                    .set_failure(&CheckError::new(FailureReason::Dns, msg.clone()).into()),
+                None => {}
+            }
         }
     }
 
@@ -444,22 +938,25 @@ impl M3uObjectList {
 
     pub fn export(
         self,
-        ip_type: i32, //0:all 1:v4 2:v6
         replace_logo_host: String,
         replace_logo_map: HashMap<String, String>,
-        like_list: Vec<String>,
-        full_list: Vec<String>,
         only_success: bool,
         export_type: i8,
-        quality_type_list: Vec<QualityType>,
+        filter: ExportFilter,
     ) -> String {
+        let ip_type = filter.ip_type;
+        let like_list = &filter.like_list;
+        let full_list = &filter.full_list;
+        let quality_type_list = &filter.quality_type_list;
         let mut save_list = vec![];
         for i in &self.list {
             let mut is_save = true;
-            // 检测ip是否符合
+            // 检测ip是否符合，分别检查过每个地址时只保留该地址族检查成功的源
             if ip_type == 1 || ip_type == 2 {
                 is_save = false;
-                if !i.other_status.ip_address.is_empty() {
+                if i.other_status.is_address_checked() {
+                    is_save = i.other_status.is_family_available(ip_type == 2);
+                } else if !i.other_status.ip_address.is_empty() {
                     for ip in &i.other_status.ip_address {
                         if ip_type == 1 && is_ipv4(ip.as_str()) {
                             is_save = true;
@@ -476,13 +973,13 @@ impl M3uObjectList {
             if is_save {
                 if like_list.len() > 0 || full_list.len() > 0 {
                     let mut is_hit_keyword = false;
-                    for k in &like_list {
+                    for k in like_list {
                         if i.search_name.contains(k.as_str()) {
                             is_hit_keyword = true
                         }
                     }
                     if !is_hit_keyword {
-                        for k in &full_list {
+                        for k in full_list {
                             if i.search_name.eq(k.as_str()) {
                                 is_hit_keyword = true
                             }
@@ -500,16 +997,26 @@ impl M3uObjectList {
                 is_save = false;
                 if i.other_status.ffmpeg_info.is_some() {
                     for v in i.other_status.ffmpeg_info.clone().unwrap().video {
-                        for k in quality_type_list.clone() {
-                            if v.quality_type.eq(&k) {
+                        for k in quality_type_list {
+                            if v.quality_type.eq(k) {
                                 is_save = true;
                             }
                         }
                     }
                 }
             }
+            if is_save && !filter.is_match(&i.other_status) {
+                is_save = false;
+            }
             if is_save {
-                save_list.push(i.clone());
+                let mut item = i.clone();
+                if let Some(pin) = filter.pin_variant {
+                    if let Some(uri) = i.other_status.get_variant_uri(pin, quality_type_list) {
+                        item.set_url(uri);
+                    }
+                }
+                item.other_status.expiring = filter.is_expiring(&i.other_status);
+                save_list.push(item);
             }
         }
         let mut new_obj = M3uObjectList::new();
@@ -574,41 +1081,36 @@ impl M3uObjectList {
         self.set_list(save_list)
     }
 
+    /// 按清晰度过滤，只删除清晰度已知且不匹配的源
+    ///
+    /// 检查失败、没有清晰度信息（媒体播放列表、直接的流、RTSP/RTMP/UDP等）的源会保留，
+    /// 避免普通检查时丢失这些源，失败的源也需要保存下来用于对比每次检查的结果
+    ///
+    /// # 参数
+    /// * `quality_list` - 需要保留的清晰度，为空时不过滤
     pub fn search_video_quality(&mut self, quality_list: Vec<QualityType>) {
         if quality_list.is_empty() {
             return;
         }
         println!("-----quality_list len {}", quality_list.len());
 
-        let mut filtered_list = vec![];
-        let mut quality_map: HashMap<QualityType, i32> = HashMap::new();
-        for item in quality_list {
-            quality_map.insert(item, 1);
-        }
-
-        for item in &self.list {
-            let mut is_save = false;
-            if item.status != Success {
-                continue;
-            }
-
-            if let Some(info) = &item.other_status.ffmpeg_info {
-                if info.video.len() > 0 {
-                    for v_q in info.video.clone() {
-                        for (k, _) in quality_map.clone() {
-                            if k == v_q.quality_type {
-                                is_save = true
-                            }
-                        }
-                    }
-                    if is_save {
-                        filtered_list.push(item.clone());
-                    }
-                }
-            }
-        }
-
-        self.set_list(filtered_list);
+        self.list.retain(|item| {
+            let known = item
+                .other_status
+                .ffmpeg_info
+                .as_ref()
+                .map(|info| {
+                    info.video
+                        .iter()
+                        .filter(|v| v.quality_type != QualityType::QualityUnknown)
+                        .map(|v| &v.quality_type)
+                        .collect::<Vec<&QualityType>>()
+                })
+                .unwrap_or_default();
+            item.status != Success
+                || known.is_empty()
+                || known.iter().any(|q| quality_list.contains(q))
+        });
     }
 
     pub async fn search(&mut self, search: SearchOptions) {
@@ -663,7 +1165,42 @@ impl M3uObjectList {
         self.set_list(unique_list)
     }
 
-    pub async fn generate_thumbnail(&mut self, concurrent: i32, timeout_millisecond: u16) {
+    /// 根据内容指纹标记占位画面：内容相同且频道名称足够多的分组，以及命中黑名单的源
+    ///
+    /// # 参数
+    /// * `config` - 占位画面配置
+    ///
+    /// # 返回值
+    /// * `Vec<FingerprintCluster>` - 被认为是占位画面的分组
+    pub fn mark_placeholders(&mut self, config: &PlaceholderConfig) -> Vec<FingerprintCluster> {
+        let clusters = cluster_fingerprints(&self.list, config.min_channels)
+            .into_iter()
+            .filter(|c| c.placeholder)
+            .collect::<Vec<_>>();
+        for item in self.list.iter_mut() {
+            let placeholder = clusters.iter().any(|c| c.urls.contains(&item.url))
+                || item
+                    .other_status
+                    .fingerprint
+                    .as_ref()
+                    .is_some_and(|f| config.list.iter().any(|b| b.fingerprint.is_same(f)));
+            item.other_status.set_placeholder(placeholder);
+        }
+        clusters
+    }
+
+    /// 截取每个源的首帧作为缩略图，只保留截取成功的源
+    ///
+    /// # 参数
+    /// * `concurrent` - 并发数
+    /// * `timeout_millisecond` - 超时时间（毫秒）
+    /// * `proxies` - 源地址对应的代理，来自下载该源的搜索源
+    pub async fn generate_thumbnail(
+        &mut self,
+        concurrent: i32,
+        timeout_millisecond: u16,
+        proxies: &HashMap<String, ProxyConfig>,
+    ) {
         let mut ffmpeg_timeout_sec = 1;
         if timeout_millisecond / 1000 >= 1 {
             ffmpeg_timeout_sec = (timeout_millisecond / 1000) as u64;
@@ -681,11 +1218,37 @@ impl M3uObjectList {
                 md5_str(v.get_url())
             );
             let url = v.get_url().clone();
+            let headers = v.get_headers();
+            let proxy = proxies.get(&url).cloned();
             let semaphore = Arc::clone(&semaphore);
             let task = tokio::spawn(async move {
                 // Acquire permit from semaphore
                 let _permit = semaphore.acquire().await.unwrap();
-                let succ = capture_stream_pic(url, img_url.clone(), ffmpeg_timeout_sec);
+                let succ = capture_stream_pic(
+                    url,
+                    img_url.clone(),
+                    ffmpeg_timeout_sec,
+                    &headers,
+                    proxy.as_ref(),
+                );
+                let mut v = v;
+                if succ {
+                    // 记录缩略图的画面哈希，用于识别占位画面
+                    let frame_hash = capture_frame_hash(
+                        &img_url,
+                        &BTreeMap::new(),
+                        ffmpeg_timeout_sec * 1000,
+                        None,
+                    )
+                    .await;
+                    let mut fingerprint = v
+                        .other_status
+                        .fingerprint
+                        .clone()
+                        .unwrap_or_else(Fingerprint::new);
+                    fingerprint.set_frame_hash(frame_hash);
+                    v.other_status.set_fingerprint(Some(fingerprint));
+                }
                 (v, succ, img_url)
             });
             tasks.push(task);
@@ -727,72 +1290,82 @@ impl M3uObjectList {
     }
 
     pub async fn check_data_new(&mut self, opt: CheckOptions) {
+        let now = chrono::Utc::now().timestamp();
         if !opt.no_check {
             let total = self.list.len();
             info!("文件中源总数： {}", total);
+            // 未过期的缓存结果直接使用，只检查其余的源
+            let mut pending_index = vec![];
+            for (index, item) in self.list.iter_mut().enumerate() {
+                let key = cache_key(&item.url, &item.get_headers(), &opt);
+                match get_cached(&key, opt.cache_ttl, now) {
+                    Some(entry) => {
+                        item.set_other_status(entry.other_status);
+                        item.set_status(if entry.success { Success } else { Failed });
+                    }
+                    None => pending_index.push(index),
+                }
+            }
             let mut counter = M3uObjectListCounter::new();
             counter.set_total(total as i32);
+            counter.set_cached_count((total - pending_index.len()) as i32);
             self.set_counter(counter);
+            // 定时任务检查时发布进度
+            let task_id = get_now_check_task_id();
+            if let Some(id) = &task_id {
+                let cached_success = self.list.iter().filter(|i| i.status == Success).count();
+                set_progress_total(
+                    id,
+                    total as i32,
+                    (total - pending_index.len()) as i32,
+                    cached_success as i32,
+                );
+            }
 
-            let data = self.list.clone();
-            let (tx, rx) = mpsc::channel();
-            let (data_tx, data_rx) = mpsc::channel();
-            let new_data_rx = Arc::new(Mutex::new(data_rx));
-
-            for _i in 0..opt.concurrent {
-                let tx_clone = tx.clone();
-                let data_rx_clone = Arc::clone(&new_data_rx);
-
-                thread::spawn(move || loop {
-                    match data_rx_clone.lock() {
-                        Ok(data) => {
-                            let mut item = {
-                                let rx_lock = data;
-                                rx_lock.recv().unwrap_or_else(|_| M3uObject::new())
-                            };
-                            if item.url == "" {
-                                break;
-                            }
-                            item.check_by_block(
-                                opt.request_time,
-                                opt.ffmpeg_check,
-                                opt.not_http_skip,
-                            );
-                            tx_clone.send(item.get_obj()).unwrap()
-                        }
-                        Err(e) => {
-                            error!("check_data_new error ---{} ", e);
-                            break;
-                        }
+            let mut pending = M3uObjectList::new();
+            pending.set_list(
+                pending_index
+                    .iter()
+                    .map(|i| self.list[*i].clone())
+                    .collect(),
+            );
+            let cancel = task_id.as_deref().and_then(get_cancel_token);
+            let res_list = pending
+                .check_list(&opt, cancel, |item| {
+                    counter.now_index_incr();
+                    counter.print_now_status();
+                    if let Some(id) = &task_id {
+                        update_progress(id, item.url.clone(), item.status == Success);
                     }
-                });
+                    debug!("checked: {} {:?}", item.url, item.status);
+                })
+                .await;
+            if let Err(e) = record_checks(HISTORY_FILE, &res_list, now).await {
+                error!("record check history error: {}", e);
             }
-            for item in data {
-                data_tx.send(item).unwrap();
+            if opt.cache_ttl.is_enabled() {
+                let entries = res_list
+                    .iter()
+                    .filter_map(|i| {
+                        CacheEntry::from_object(i, now)
+                            .map(|e| (cache_key(&i.url, &i.get_headers(), &opt), e))
+                    })
+                    .collect();
+                save_cached(entries, opt.cache_ttl, now).await;
             }
-            drop(tx); // 发送完成后关闭队列
-
-            let mut res_list = vec![];
-
-            counter.print_now_status();
-            let mut i = 0;
-            loop {
-                if i == counter.total {
-                    break;
-                }
-                let result = rx.recv();
-                match result {
-                    Ok(data) => {
-                        // 处理返回值
-                        res_list.push(data);
-                        counter.now_index_incr();
-                        counter.print_now_status();
-                        i += 1;
-                    }
-                    Err(_e) => {}
+            for (index, item) in pending_index.into_iter().zip(res_list) {
+                self.list[index] = item;
+            }
+            if opt.fingerprint_check {
+                let config = crate::config::placeholder::get_placeholder_config();
+                for cluster in self.mark_placeholders(&config) {
+                    info!(
+                        "占位画面: {} 个源, 频道 [{}]",
+                        cluster.urls.len(),
+                        cluster.channels.join(", ")
+                    );
                 }
             }
-            self.set_list(res_list.clone());
         } else {
             info!("no check----{}", opt.no_check);
             let total = self.list.len();
@@ -810,13 +1383,86 @@ impl M3uObjectList {
         }
         self.counter.set_success_count(succ_count);
         if opt.same_save_num > 0 {
-            self.do_same_save(opt.same_save_num);
+            let stats = load_stats_async(HISTORY_FILE, now).await;
+            self.do_same_save(opt.same_save_num, &stats);
         }
         if opt.sort {
             self.do_name_sort();
         }
     }
 
+    /// 并发检查所有源，每个检查完成时回调 `on_result`
+    ///
+    /// 全局并发数由 `opt.concurrent` 控制，同一域名的并发数由 `opt.per_host_concurrent` 控制，
+    /// 检查完成顺序不定，但返回的列表保持原有顺序。
+    /// 取消时正在进行的检查会被丢弃（ffprobe子进程随之结束），未完成的源保持未检查状态
+    ///
+    /// # 参数
+    /// * `opt` - 检查参数
+    /// * `cancel` - 取消信号
+    /// * `on_result` - 单个源检查完成时的回调
+    ///
+    /// # 返回值
+    /// * `Vec<M3uObject>` - 检查后的列表
+    pub async fn check_list<F>(
+        &self,
+        opt: &CheckOptions,
+        mut cancel: Option<CancelToken>,
+        mut on_result: F,
+    ) -> Vec<M3uObject>
+    where
+        F: FnMut(&M3uObject),
+    {
+        let mut res_list: Vec<Option<M3uObject>> = vec![None; self.list.len()];
+        let client = match new_check_client(opt.request_time as u64, opt.proxy.as_ref()) {
+            Ok(client) => client,
+            Err(e) => {
+                error!("check_list error ---{} ", e);
+                return self.list.clone();
+            }
+        };
+        let semaphore = Arc::new(Semaphore::new(opt.concurrent.max(1) as usize));
+        let host_limiter = HostLimiter::new(opt.per_host_concurrent.max(0) as usize);
+        let mut tasks = FuturesUnordered::new();
+        for (index, item) in self.list.iter().enumerate() {
+            let mut item = item.clone();
+            let client = &client;
+            let semaphore = &semaphore;
+            let host_limiter = &host_limiter;
+            tasks.push(async move {
+                // 先获取域名许可再获取全局许可，避免全局并发被同一域名占满
+                let (host, _) = get_url_host_and_port(&item.url);
+                let _host_permit = host_limiter.acquire(&host).await;
+                let _permit = semaphore.acquire().await;
+                item.check(client, opt).await;
+                (index, item)
+            });
+        }
+        loop {
+            let next = match cancel.as_mut() {
+                Some(token) => tokio::select! {
+                    next = tasks.next() => next,
+                    _ = token.cancelled() => {
+                        info!("检查已取消，剩余 {} 个源未完成", tasks.len());
+                        None
+                    }
+                },
+                None => tasks.next().await,
+            };
+            let Some((index, item)) = next else {
+                break;
+            };
+            on_result(&item);
+            res_list[index] = Some(item);
+        }
+        drop(tasks);
+        res_list
+            .into_iter()
+            .zip(self.list.iter())
+            .map(|(res, item)| res.unwrap_or_else(|| item.clone()))
+            .collect()
+    }
+
     pub fn get_list_len(&self) -> usize {
         self.list.len()
     }
@@ -852,7 +1498,12 @@ impl M3uObjectList {
         time::sleep(Duration::from_millis(500)).await;
     }
 
-    pub fn do_same_save(&mut self, same_save_num: i32) {
+    /// 相同名称的源只保留 `same_save_num` 个，稳定、历史在线率高、延迟低的优先保留
+    ///
+    /// # 参数
+    /// * `same_save_num` - 相同名称保存的最大数量
+    /// * `stats` - URL对应的历史统计
+    pub fn do_same_save(&mut self, same_save_num: i32, stats: &HashMap<String, UrlStats>) {
         let mut hash_list: HashMap<String, Vec<M3uObject>> = HashMap::new();
         for item in self.list.clone() {
             if item.status == Success {
@@ -866,7 +1517,14 @@ impl M3uObjectList {
             }
         }
         let mut save_list = vec![];
-        for (_, items) in hash_list {
+        for (_, mut items) in hash_list {
+            // 不稳定的源排在后面，其次按历史在线率和平均延迟排序
+            items.sort_by(|a, b| {
+                a.other_status
+                    .is_flaky()
+                    .cmp(&b.other_status.is_flaky())
+                    .then_with(|| compare_rank(stats.get(&a.url), stats.get(&b.url)))
+            });
             let mut i = 0;
             for item in items {
                 if i >= same_save_num {
@@ -1078,14 +1736,142 @@ pub struct OtherStatus {
     delay: i32,
     ip_address: Vec<String>, //ip地址
     ffmpeg_info: Option<FfmpegInfo>,
+    #[serde(default)]
+    failed_stage: Option<HlsCheckStage>, //深度检查失败的阶段
+    #[serde(default)]
+    failure_reason: Option<FailureReason>, //失败原因
+    #[serde(default)]
+    http_status: Option<u16>, //http状态码
+    #[serde(default)]
+    elapsed: i64, //检查总耗时（毫秒）
+    #[serde(default)]
+    live_status: Option<LiveStatus>, //直播状态
+    #[serde(default)]
+    throughput: Option<ThroughputInfo>, //持续下载速度
+    #[serde(default)]
+    timing: Option<TimingInfo>, //各阶段耗时
+    #[serde(default)]
+    address_status: Vec<AddressStatus>, //每个IP的检查结果
+    #[serde(default)]
+    attempts: i32, //检查次数，大于1表示经过了重试
+    #[serde(default)]
+    first_failure_reason: Option<FailureReason>, //第一次检查失败的原因
+    #[serde(default)]
+    flaky: bool, //重试后才成功，源不稳定
+    #[serde(default)]
+    content_health: Option<ContentHealth>, //黑屏、静帧、静音检测结果
+    #[serde(default)]
+    fingerprint: Option<Fingerprint>, //内容指纹
+    #[serde(default)]
+    placeholder: bool, //和很多不同频道的内容相同，或命中占位画面黑名单
+    #[serde(default)]
+    encryption: Option<HlsEncryption>, //分片加密状态
+    #[serde(default)]
+    redirect: Option<RedirectInfo>, //跳转链和地址过期时间
+    #[serde(skip)]
+    expiring: bool, //导出时标记在下次任务运行前过期
+    #[serde(default)]
+    proxy: Option<String>, //检查使用的代理，不包含认证信息
+}
+
+/// 连接指定IP的检查结果
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AddressStatus {
+    ip: String,
+    success: bool,
+    delay: i32,
+    #[serde(default)]
+    failure_reason: Option<FailureReason>,
+}
+
+impl AddressStatus {
+    pub fn new(
+        ip: IpAddr,
+        result: &Result<CheckUrlIsAvailableResponse, io::Error>,
+    ) -> AddressStatus {
+        let mut status = AddressStatus {
+            ip: ip.to_string(),
+            success: false,
+            delay: 0,
+            failure_reason: None,
+        };
+        match result {
+            Ok(data) => {
+                status.success = true;
+                status.delay = data.delay;
+            }
+            Err(e) => {
+                status.failure_reason = Some(CheckError::reason_of(e));
+            }
+        }
+        status
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.success
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        is_ipv6(&self.ip)
+    }
 }
 
 impl OtherStatus {
+    /// 根据检查成功的结果生成
+    pub fn from_response(data: CheckUrlIsAvailableResponse) -> OtherStatus {
+        let mut o_status = OtherStatus::new();
+        o_status.set_delay(data.delay);
+        o_status.set_ffmpeg_info(data.ffmpeg_info);
+        o_status.set_live_status(data.live_status);
+        o_status.set_throughput(data.throughput);
+        o_status.set_timing(data.timing);
+        o_status.set_content_health(data.content_health);
+        o_status.set_fingerprint(data.fingerprint);
+        o_status.set_encryption(data.encryption);
+        o_status.set_redirect(data.redirect);
+        o_status.set_proxy(data.proxy);
+        o_status
+    }
+
+    /// 转换为检查结果，用于返回缓存的检查结果
+    pub fn to_response(&self) -> CheckUrlIsAvailableResponse {
+        let mut data = CheckUrlIsAvailableResponse::new();
+        data.delay = self.delay;
+        data.ffmpeg_info = self.ffmpeg_info.clone();
+        data.live_status = self.live_status;
+        data.throughput = self.throughput.clone();
+        data.timing = self.timing.clone();
+        data.content_health = self.content_health.clone();
+        data.fingerprint = self.fingerprint.clone();
+        data.encryption = self.encryption.clone();
+        data.redirect = self.redirect.clone();
+        data.proxy = self.proxy.clone();
+        data
+    }
+
     pub fn new() -> OtherStatus {
         OtherStatus {
             ffmpeg_info: None,
             delay: 0,
             ip_address: vec![],
+            failed_stage: None,
+            failure_reason: None,
+            http_status: None,
+            elapsed: 0,
+            live_status: None,
+            throughput: None,
+            timing: None,
+            address_status: vec![],
+            attempts: 0,
+            first_failure_reason: None,
+            flaky: false,
+            content_health: None,
+            fingerprint: None,
+            placeholder: false,
+            encryption: None,
+            redirect: None,
+            expiring: false,
+            proxy: None,
         }
     }
 
@@ -1100,6 +1886,186 @@ impl OtherStatus {
     pub fn set_ffmpeg_info(&mut self, ffmpeg_info: Option<FfmpegInfo>) {
         self.ffmpeg_info = ffmpeg_info
     }
+
+    pub fn set_elapsed(&mut self, elapsed: i64) {
+        self.elapsed = elapsed
+    }
+
+    pub fn set_live_status(&mut self, live_status: Option<LiveStatus>) {
+        self.live_status = live_status
+    }
+
+    pub fn set_throughput(&mut self, throughput: Option<ThroughputInfo>) {
+        self.throughput = throughput
+    }
+
+    pub fn set_timing(&mut self, timing: Option<TimingInfo>) {
+        self.timing = timing
+    }
+
+    pub fn set_content_health(&mut self, content_health: Option<ContentHealth>) {
+        self.content_health = content_health
+    }
+
+    /// 是否检测到指定的画面异常，没有检测过时返回false
+    pub fn has_content_issue(&self, issue: ContentIssue) -> bool {
+        self.content_health
+            .as_ref()
+            .is_some_and(|h| h.has_issue(issue))
+    }
+
+    pub fn set_fingerprint(&mut self, fingerprint: Option<Fingerprint>) {
+        self.fingerprint = fingerprint
+    }
+
+    pub fn get_fingerprint(&self) -> Option<&Fingerprint> {
+        self.fingerprint.as_ref()
+    }
+
+    pub fn set_placeholder(&mut self, placeholder: bool) {
+        self.placeholder = placeholder
+    }
+
+    pub fn set_encryption(&mut self, encryption: Option<HlsEncryption>) {
+        self.encryption = encryption
+    }
+
+    pub fn get_encryption(&self) -> Option<&HlsEncryption> {
+        self.encryption.as_ref()
+    }
+
+    /// 探测过加密密钥并且无法获取
+    pub fn is_key_unreachable(&self) -> bool {
+        self.encryption
+            .as_ref()
+            .is_some_and(|e| e.is_key_unreachable())
+    }
+
+    pub fn set_redirect(&mut self, redirect: Option<RedirectInfo>) {
+        self.redirect = redirect
+    }
+
+    pub fn set_proxy(&mut self, proxy: Option<String>) {
+        self.proxy = proxy
+    }
+
+    pub fn get_proxy(&self) -> Option<&String> {
+        self.proxy.as_ref()
+    }
+
+    /// 地址参数中解析出的过期时间（秒级时间戳）
+    pub fn get_expires_at(&self) -> Option<i64> {
+        self.redirect.as_ref().and_then(|r| r.expires_at)
+    }
+
+    /// 是否在指定时间之前过期
+    pub fn expires_before(&self, time: i64) -> bool {
+        self.redirect
+            .as_ref()
+            .is_some_and(|r| r.expires_before(time))
+    }
+
+    pub fn set_address_status(&mut self, address_status: Vec<AddressStatus>) {
+        self.address_status = address_status
+    }
+
+    /// 是否已分别检查过每个地址
+    pub fn is_address_checked(&self) -> bool {
+        !self.address_status.is_empty()
+    }
+
+    /// 指定地址族是否有检查成功的IP
+    ///
+    /// # 参数
+    /// * `ipv6` - true检查IPv6，false检查IPv4
+    pub fn is_family_available(&self, ipv6: bool) -> bool {
+        self.address_status
+            .iter()
+            .any(|s| s.is_success() && s.is_ipv6() == ipv6)
+    }
+
+    /// 根据检查错误记录失败原因、http状态码和深度检查失败阶段
+    pub fn set_failure(&mut self, e: &io::Error) {
+        self.failure_reason = Some(CheckError::reason_of(e));
+        if let Some(err) = CheckError::from_io_error(e) {
+            self.http_status = err.http_status;
+            self.failed_stage = err.stage;
+        }
+    }
+
+    /// 记录检查次数以及第一次检查失败的原因，重试后才成功的源标记为不稳定
+    ///
+    /// # 参数
+    /// * `attempts` - 检查次数
+    /// * `first_failure_reason` - 第一次检查失败的原因，第一次成功时为空
+    /// * `success` - 最终是否成功
+    pub fn set_attempts(
+        &mut self,
+        attempts: i32,
+        first_failure_reason: Option<FailureReason>,
+        success: bool,
+    ) {
+        self.attempts = attempts;
+        self.first_failure_reason = first_failure_reason;
+        self.flaky = success && attempts > 1;
+    }
+
+    pub fn get_delay(&self) -> i32 {
+        self.delay
+    }
+
+    /// 将记录的失败信息还原为检查错误，用于返回缓存的失败结果
+    pub fn to_check_error(&self) -> CheckError {
+        let reason = self.failure_reason.unwrap_or(FailureReason::Other);
+        let mut err = CheckError::new(reason, format!("cached failure {:?}", reason));
+        err.http_status = self.http_status;
+        err.stage = self.failed_stage;
+        err
+    }
+
+    pub fn get_failure_reason(&self) -> Option<FailureReason> {
+        self.failure_reason
+    }
+
+    /// 选出导出时固定使用的码率地址，指定了清晰度时只在这些清晰度的码率中选择
+    ///
+    /// # 参数
+    /// * `pin` - 选择码率最高还是最低的
+    /// * `qualities` - 允许的清晰度，为空表示不限制
+    ///
+    /// # 返回值
+    /// * `Option<String>` - 不是主播放列表或没有符合的码率时为空
+    pub fn get_variant_uri(&self, pin: VariantPin, qualities: &[QualityType]) -> Option<String> {
+        let candidates = self
+            .ffmpeg_info
+            .as_ref()?
+            .video
+            .iter()
+            .filter(|v| v.uri.is_some())
+            .filter(|v| qualities.is_empty() || qualities.contains(&v.quality_type));
+        let key = |v: &&VideoInfo| (v.bandwidth.unwrap_or(0), v.height);
+        let chosen = match pin {
+            VariantPin::Highest => candidates.max_by_key(key),
+            VariantPin::Lowest => candidates.min_by_key(key),
+        };
+        chosen.and_then(|v| v.uri.clone())
+    }
+
+    /// 第一路视频的清晰度
+    pub fn get_quality(&self) -> Option<QualityType> {
+        self.ffmpeg_info
+            .as_ref()
+            .and_then(|f| f.video.first())
+            .map(|v| v.quality_type.clone())
+    }
+
+    pub fn get_attempts(&self) -> i32 {
+        self.attempts
+    }
+
+    pub fn is_flaky(&self) -> bool {
+        self.flaky
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, Hash, PartialEq)]
@@ -1132,6 +2098,12 @@ pub struct VideoInfo {
     pub height: i32,
     pub codec: String,
     pub quality_type: QualityType,
+    #[serde(default)]
+    pub bandwidth: Option<u64>, // 声明的码率（bps），来自HLS主播放列表或DASH清单
+    #[serde(default)]
+    pub frame_rate: Option<f32>, // 声明的帧率
+    #[serde(default)]
+    pub uri: Option<String>, // 该码率的播放地址，只有HLS主播放列表中的码率才有
 }
 
 impl VideoInfo {
@@ -1141,6 +2113,9 @@ impl VideoInfo {
             height: 0,
             codec: "".to_string(),
             quality_type: QualityUnknown,
+            bandwidth: None,
+            frame_rate: None,
+            uri: None,
         }
     }
 
@@ -1155,12 +2130,24 @@ impl VideoInfo {
     pub fn set_codec(&mut self, codec: String) {
         self.codec = codec
     }
+
+    pub fn set_bandwidth(&mut self, bandwidth: Option<u64>) {
+        self.bandwidth = bandwidth
+    }
+
+    pub fn set_frame_rate(&mut self, frame_rate: Option<f32>) {
+        self.frame_rate = frame_rate
+    }
+
+    pub fn set_uri(&mut self, uri: Option<String>) {
+        self.uri = uri
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AudioInfo {
-    codec: String,
-    channels: i32,
+    pub codec: String,
+    pub channels: i32,
 }
 
 impl AudioInfo {
@@ -1177,12 +2164,6 @@ impl AudioInfo {
     pub fn set_channels(&mut self, channels: i32) {
         self.channels = channels
     }
-    // pub fn get_channels(self) -> i32 {
-    //     self.channels
-    // }
-    // pub fn get_codec(self) -> String {
-    //     self.codec
-    // }
 }
 
 pub enum SourceType {
@@ -1193,6 +2174,7 @@ pub enum SourceType {
 
 #[allow(dead_code)]
 pub mod m3u {
+    use crate::common::proxy::ProxyConfig;
     use crate::common::util::{get_url_body, is_url, parse_normal_str, parse_quota_str};
     use crate::common::SourceType::{Normal, Quota};
     use crate::common::{M3uObjectList, SourceType};
@@ -1297,7 +2279,11 @@ pub mod m3u {
     //     return from_body(&contents);
     // }
 
-    pub async fn from_arr(_url: Vec<String>, _timeout: u64) -> Vec<String> {
+    pub async fn from_arr(
+        _url: Vec<String>,
+        _timeout: u64,
+        proxy: Option<&ProxyConfig>,
+    ) -> Vec<String> {
         let mut body_arr = vec![];
         for x in _url {
             if is_url(x.clone()) {
@@ -1320,7 +2306,7 @@ pub mod m3u {
                     }
                 }
                 println!("----fetch_url is: {}", fetch_url.clone());
-                match get_url_body(fetch_url.clone(), _timeout).await {
+                match get_url_body(fetch_url.clone(), _timeout, proxy).await {
                     Ok(data) => body_arr.push(data),
                     Err(e) => {
                         error!("url can not be open : {}, error: {}", x.clone(), e)
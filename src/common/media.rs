@@ -0,0 +1,825 @@
+use crate::common::util::get_video_resolution;
+use crate::common::{AudioInfo, FfmpegInfo, VideoInfo};
+use std::collections::HashMap;
+
+const TS_PACKET_SIZE: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+/// 每个流最多收集的数据量，SPS和音频帧头一般都在分片开头
+const ES_BUFFER_LIMIT: usize = 512 * 1024;
+
+/// 流类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StreamKind {
+    H264,
+    H265,
+    Aac,
+    Ac3,
+    Eac3,
+    MpegAudio,
+}
+
+impl StreamKind {
+    /// 根据PMT中的stream_type和描述符判断流类型
+    fn from_stream_type(stream_type: u8, descriptors: &[u8]) -> Option<StreamKind> {
+        match stream_type {
+            0x1b => Some(StreamKind::H264),
+            0x24 => Some(StreamKind::H265),
+            0x0f | 0x11 => Some(StreamKind::Aac),
+            0x81 => Some(StreamKind::Ac3),
+            0x87 => Some(StreamKind::Eac3),
+            0x03 | 0x04 => Some(StreamKind::MpegAudio),
+            // DVB私有流，通过描述符区分 AC-3(0x6a) / E-AC-3(0x7a)
+            0x06 => {
+                let mut i = 0;
+                while i + 2 <= descriptors.len() {
+                    match descriptors[i] {
+                        0x6a => return Some(StreamKind::Ac3),
+                        0x7a => return Some(StreamKind::Eac3),
+                        _ => {}
+                    }
+                    i += 2 + descriptors[i + 1] as usize;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn is_video(&self) -> bool {
+        matches!(self, StreamKind::H264 | StreamKind::H265)
+    }
+}
+
+/// 按位读取，支持指数哥伦布编码
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, pos: 0 }
+    }
+
+    fn read_bit(&mut self) -> Option<u32> {
+        let byte = *self.data.get(self.pos / 8)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(bit as u32)
+    }
+
+    fn read_bits(&mut self, n: usize) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..n {
+            value = (value << 1) | self.read_bit()?;
+        }
+        Some(value)
+    }
+
+    fn skip_bits(&mut self, n: usize) -> Option<()> {
+        if self.pos + n > self.data.len() * 8 {
+            return None;
+        }
+        self.pos += n;
+        Some(())
+    }
+
+    fn read_ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while self.read_bit()? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some((1u32 << zeros) - 1 + self.read_bits(zeros)?)
+    }
+
+    fn read_se(&mut self) -> Option<i32> {
+        let v = self.read_ue()?;
+        if v % 2 == 1 {
+            Some(v.div_ceil(2) as i32)
+        } else {
+            Some(-((v / 2) as i32))
+        }
+    }
+}
+
+/// 去掉NAL中的防竞争字节（00 00 03）
+fn remove_emulation_prevention(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &b in data {
+        if zeros >= 2 && b == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        out.push(b);
+    }
+    out
+}
+
+/// 解析H.264 SPS，返回（宽，高）
+///
+/// # 参数
+/// * `nal` - 包含NAL头的SPS数据
+pub fn parse_h264_sps(nal: &[u8]) -> Option<(i32, i32)> {
+    let rbsp = remove_emulation_prevention(nal.get(1..)?);
+    let mut r = BitReader::new(&rbsp);
+    let profile_idc = r.read_bits(8)?;
+    r.skip_bits(16)?; // constraint_set_flags + level_idc
+    r.read_ue()?; // seq_parameter_set_id
+    let mut chroma_format_idc = 1;
+    if [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135].contains(&profile_idc) {
+        chroma_format_idc = r.read_ue()?;
+        if chroma_format_idc == 3 {
+            r.skip_bits(1)?; // separate_colour_plane_flag
+        }
+        r.read_ue()?; // bit_depth_luma_minus8
+        r.read_ue()?; // bit_depth_chroma_minus8
+        r.skip_bits(1)?; // qpprime_y_zero_transform_bypass_flag
+        if r.read_bit()? == 1 {
+            let count = if chroma_format_idc != 3 { 8 } else { 12 };
+            for i in 0..count {
+                if r.read_bit()? == 1 {
+                    let size = if i < 6 { 16 } else { 64 };
+                    let mut last_scale: i32 = 8;
+                    let mut next_scale = 8;
+                    for _ in 0..size {
+                        if next_scale != 0 {
+                            let delta = r.read_se()?;
+                            next_scale = last_scale.checked_add(delta)?.rem_euclid(256);
+                        }
+                        if next_scale != 0 {
+                            last_scale = next_scale;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    r.read_ue()?; // log2_max_frame_num_minus4
+    match r.read_ue()? {
+        0 => {
+            r.read_ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            r.skip_bits(1)?;
+            r.read_se()?;
+            r.read_se()?;
+            for _ in 0..r.read_ue()? {
+                r.read_se()?;
+            }
+        }
+        _ => {}
+    }
+    r.read_ue()?; // max_num_ref_frames
+    r.skip_bits(1)?; // gaps_in_frame_num_value_allowed_flag
+    let width_mbs = r.read_ue()?.checked_add(1)?;
+    let height_map_units = r.read_ue()?.checked_add(1)?;
+    let frame_mbs_only = r.read_bit()?;
+    if frame_mbs_only == 0 {
+        r.skip_bits(1)?; // mb_adaptive_frame_field_flag
+    }
+    r.skip_bits(1)?; // direct_8x8_inference_flag
+    let (mut crop_left, mut crop_right, mut crop_top, mut crop_bottom) = (0, 0, 0, 0);
+    if r.read_bit()? == 1 {
+        crop_left = r.read_ue()?;
+        crop_right = r.read_ue()?;
+        crop_top = r.read_ue()?;
+        crop_bottom = r.read_ue()?;
+    }
+    let (sub_width, sub_height) = match chroma_format_idc {
+        0 => (1, 1),
+        1 => (2, 2),
+        2 => (2, 1),
+        _ => (1, 1),
+    };
+    // 数值来自远程的码流，异常数据不能溢出
+    let crop_unit_y = sub_height * (2 - frame_mbs_only);
+    let width = width_mbs.checked_mul(16)?;
+    let height = height_map_units.checked_mul((2 - frame_mbs_only) * 16)?;
+    let width = width.checked_sub(crop_left.checked_add(crop_right)?.checked_mul(sub_width)?)?;
+    let height = height.checked_sub(
+        crop_top
+            .checked_add(crop_bottom)?
+            .checked_mul(crop_unit_y)?,
+    )?;
+    Some((i32::try_from(width).ok()?, i32::try_from(height).ok()?))
+}
+
+/// 解析H.265 SPS，返回（宽，高）
+///
+/// # 参数
+/// * `nal` - 包含两字节NAL头的SPS数据
+pub fn parse_h265_sps(nal: &[u8]) -> Option<(i32, i32)> {
+    let rbsp = remove_emulation_prevention(nal.get(2..)?);
+    let mut r = BitReader::new(&rbsp);
+    r.skip_bits(4)?; // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = r.read_bits(3)? as usize;
+    r.skip_bits(1)?; // sps_temporal_id_nesting_flag
+                     // profile_tier_level
+    r.skip_bits(96)?;
+    let mut sub_layer_flags = vec![];
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layer_flags.push((r.read_bit()?, r.read_bit()?));
+    }
+    if max_sub_layers_minus1 > 0 {
+        r.skip_bits(2 * (8 - max_sub_layers_minus1))?;
+    }
+    for (profile_present, level_present) in sub_layer_flags {
+        if profile_present == 1 {
+            r.skip_bits(88)?;
+        }
+        if level_present == 1 {
+            r.skip_bits(8)?;
+        }
+    }
+    r.read_ue()?; // sps_seq_parameter_set_id
+    let chroma_format_idc = r.read_ue()?;
+    if chroma_format_idc == 3 {
+        r.skip_bits(1)?; // separate_colour_plane_flag
+    }
+    let width = r.read_ue()?;
+    let height = r.read_ue()?;
+    let (mut width, mut height) = (width, height);
+    if r.read_bit()? == 1 {
+        let (sub_width, sub_height) = match chroma_format_idc {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        let left = r.read_ue()?;
+        let right = r.read_ue()?;
+        let top = r.read_ue()?;
+        let bottom = r.read_ue()?;
+        width = width.checked_sub(left.checked_add(right)?.checked_mul(sub_width)?)?;
+        height = height.checked_sub(top.checked_add(bottom)?.checked_mul(sub_height)?)?;
+    }
+    Some((i32::try_from(width).ok()?, i32::try_from(height).ok()?))
+}
+
+/// 在 Annex B 格式的数据中查找SPS并解析分辨率
+fn find_sps(kind: StreamKind, es: &[u8]) -> Option<(i32, i32)> {
+    let mut i = 0;
+    while i + 3 < es.len() {
+        if es[i] == 0 && es[i + 1] == 0 && es[i + 2] == 1 {
+            let nal = &es[i + 3..];
+            let end = nal
+                .windows(3)
+                .position(|w| w == [0, 0, 1])
+                .unwrap_or(nal.len());
+            let nal = &nal[..end];
+            if let Some(&header) = nal.first() {
+                let size = match kind {
+                    StreamKind::H264 if header & 0x1f == 7 => parse_h264_sps(nal),
+                    StreamKind::H265 if (header >> 1) & 0x3f == 33 => parse_h265_sps(nal),
+                    _ => None,
+                };
+                if size.is_some() {
+                    return size;
+                }
+            }
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+/// 从音频帧头中解析（编码名称，声道数）
+fn parse_audio_frame(kind: StreamKind, es: &[u8]) -> Option<(String, i32)> {
+    for i in 0..es.len().saturating_sub(7) {
+        let h = &es[i..];
+        match kind {
+            // ADTS 帧头
+            StreamKind::Aac if h[0] == 0xff && h[1] & 0xf6 == 0xf0 => {
+                let channels = ((h[2] & 0x01) << 2) | (h[3] >> 6);
+                let channels = if channels == 7 { 8 } else { channels as i32 };
+                return Some(("aac".to_string(), channels));
+            }
+            StreamKind::Ac3 | StreamKind::Eac3 if h[0] == 0x0b && h[1] == 0x77 => {
+                let bsid = h[5] >> 3;
+                let mut r = BitReader::new(&h[4..]);
+                let (codec, acmod, lfe) = if bsid > 10 {
+                    // E-AC-3: fscod(2) numblkscod(2) acmod(3) lfeon(1)
+                    r.skip_bits(4)?;
+                    ("eac3", r.read_bits(3)?, r.read_bit()?)
+                } else {
+                    // AC-3: fscod(2) frmsizecod(6) bsid(5) bsmod(3) acmod(3) ...
+                    r.skip_bits(16)?;
+                    let acmod = r.read_bits(3)?;
+                    if acmod & 1 == 1 && acmod != 1 {
+                        r.skip_bits(2)?;
+                    }
+                    if acmod & 4 == 4 {
+                        r.skip_bits(2)?;
+                    }
+                    if acmod == 2 {
+                        r.skip_bits(2)?;
+                    }
+                    ("ac3", acmod, r.read_bit()?)
+                };
+                let channels = [2, 1, 2, 3, 3, 4, 4, 5][acmod as usize] + lfe as i32;
+                return Some((codec.to_string(), channels));
+            }
+            StreamKind::MpegAudio if h[0] == 0xff && h[1] & 0xe0 == 0xe0 => {
+                let codec = match (h[1] >> 1) & 0x03 {
+                    1 => "mp3",
+                    2 => "mp2",
+                    3 => "mp1",
+                    _ => continue,
+                };
+                let channels = if h[3] >> 6 == 3 { 1 } else { 2 };
+                return Some((codec.to_string(), channels));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// 解析PSI表（PAT/PMT），返回去掉 pointer_field 后的 section
+fn psi_section(payload: &[u8]) -> Option<&[u8]> {
+    let pointer = *payload.first()? as usize;
+    let section = payload.get(1 + pointer..)?;
+    let length = (((section.get(1)? & 0x0f) as usize) << 8) | *section.get(2)? as usize;
+    section.get(..(3 + length).min(section.len()))
+}
+
+/// 解析 MPEG-TS 分片中的音视频信息
+///
+/// # 参数
+/// * `bytes` - TS分片数据
+///
+/// # 返回值
+/// * `Option<FfmpegInfo>` - 没有识别出任何音视频流时返回None
+pub fn parse_ts(bytes: &[u8]) -> Option<FfmpegInfo> {
+    let mut pmt_pids = vec![];
+    let mut streams: HashMap<u16, StreamKind> = HashMap::new();
+    let mut stream_order = vec![];
+    let mut es_data: HashMap<u16, Vec<u8>> = HashMap::new();
+    for packet in bytes.chunks_exact(TS_PACKET_SIZE) {
+        if packet[0] != TS_SYNC_BYTE {
+            continue;
+        }
+        let pusi = packet[1] & 0x40 != 0;
+        let pid = (((packet[1] & 0x1f) as u16) << 8) | packet[2] as u16;
+        let afc = (packet[3] >> 4) & 0x03;
+        let mut offset = 4;
+        if afc & 0x02 != 0 {
+            offset += 1 + packet[4] as usize;
+        }
+        if afc & 0x01 == 0 || offset >= TS_PACKET_SIZE {
+            continue;
+        }
+        let payload = &packet[offset..];
+        if pid == 0 && pusi {
+            if let Some(section) = psi_section(payload) {
+                let end = section.len().saturating_sub(4);
+                let mut i = 8;
+                while i + 4 <= end {
+                    let program = ((section[i] as u16) << 8) | section[i + 1] as u16;
+                    let pmt_pid = (((section[i + 2] & 0x1f) as u16) << 8) | section[i + 3] as u16;
+                    if program != 0 && !pmt_pids.contains(&pmt_pid) {
+                        pmt_pids.push(pmt_pid);
+                    }
+                    i += 4;
+                }
+            }
+        } else if pmt_pids.contains(&pid) && pusi {
+            if let Some(section) = psi_section(payload) {
+                if section.len() < 12 || section[0] != 0x02 {
+                    continue;
+                }
+                let end = section.len().saturating_sub(4);
+                let program_info_length =
+                    (((section[10] & 0x0f) as usize) << 8) | section[11] as usize;
+                let mut i = 12 + program_info_length;
+                while i + 5 <= end {
+                    let stream_type = section[i];
+                    let es_pid = (((section[i + 1] & 0x1f) as u16) << 8) | section[i + 2] as u16;
+                    let info_length =
+                        (((section[i + 3] & 0x0f) as usize) << 8) | section[i + 4] as usize;
+                    let descriptors = section.get(i + 5..i + 5 + info_length).unwrap_or(&[]);
+                    if let Some(kind) = StreamKind::from_stream_type(stream_type, descriptors) {
+                        if streams.insert(es_pid, kind).is_none() {
+                            stream_order.push(es_pid);
+                        }
+                    }
+                    i += 5 + info_length;
+                }
+            }
+        } else if streams.contains_key(&pid) {
+            let buf = es_data.entry(pid).or_default();
+            if buf.len() >= ES_BUFFER_LIMIT {
+                continue;
+            }
+            let mut data = payload;
+            // PES包头：00 00 01 stream_id len(2) flags(2) header_length(1)
+            if pusi && data.len() >= 9 && data[..3] == [0, 0, 1] {
+                data = data.get(9 + data[8] as usize..).unwrap_or(&[]);
+            } else if buf.is_empty() {
+                // 还没遇到PES起始包
+                continue;
+            }
+            buf.extend_from_slice(data);
+        }
+    }
+
+    let mut video_list = vec![];
+    let mut audio = None;
+    for pid in stream_order {
+        let kind = streams[&pid];
+        let es = match es_data.get(&pid) {
+            Some(es) => es,
+            None => continue,
+        };
+        if kind.is_video() {
+            let mut video_info = VideoInfo::new();
+            video_info.set_codec(
+                if kind == StreamKind::H264 {
+                    "h264"
+                } else {
+                    "hevc"
+                }
+                .to_string(),
+            );
+            if let Some((width, height)) = find_sps(kind, es) {
+                video_info.set_width(width);
+                video_info.set_height(height);
+                video_info.quality_type = get_video_resolution(height as u32);
+            }
+            video_list.push(video_info);
+        } else if audio.is_none() {
+            if let Some((codec, channels)) = parse_audio_frame(kind, es) {
+                let mut audio_info = AudioInfo::new();
+                audio_info.set_codec(codec);
+                audio_info.set_channels(channels);
+                audio = Some(audio_info);
+            }
+        }
+    }
+    build_ffmpeg_info(video_list, audio)
+}
+
+fn build_ffmpeg_info(video_list: Vec<VideoInfo>, audio: Option<AudioInfo>) -> Option<FfmpegInfo> {
+    if video_list.is_empty() && audio.is_none() {
+        return None;
+    }
+    let mut ffmpeg_info = FfmpegInfo::new();
+    if let Some(audio) = audio {
+        ffmpeg_info.set_audio(audio);
+    }
+    ffmpeg_info.set_video(video_list);
+    Some(ffmpeg_info)
+}
+
+/// 遍历MP4 box，返回（类型，内容）
+fn mp4_boxes(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut boxes = vec![];
+    let mut i = 0;
+    while i + 8 <= data.len() {
+        let mut size =
+            u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]) as usize;
+        let box_type = [data[i + 4], data[i + 5], data[i + 6], data[i + 7]];
+        let mut header = 8;
+        if size == 1 && i + 16 <= data.len() {
+            let mut large = [0u8; 8];
+            large.copy_from_slice(&data[i + 8..i + 16]);
+            size = u64::from_be_bytes(large) as usize;
+            header = 16;
+        } else if size == 0 {
+            size = data.len() - i;
+        }
+        // 64位的box大小来自远程数据，相加可能溢出
+        let end = match i.checked_add(size) {
+            Some(end) if size >= header && end <= data.len() => end,
+            _ => break,
+        };
+        boxes.push((box_type, &data[i + header..end]));
+        i = end;
+    }
+    boxes
+}
+
+/// 解析 fMP4 初始化分片（包含moov）中的音视频信息
+///
+/// # 参数
+/// * `bytes` - 初始化分片数据
+///
+/// # 返回值
+/// * `Option<FfmpegInfo>` - 没有识别出任何音视频流时返回None
+pub fn parse_fmp4_init(bytes: &[u8]) -> Option<FfmpegInfo> {
+    let mut video_list = vec![];
+    let mut audio = None;
+    let mut pending = vec![bytes];
+    while let Some(data) = pending.pop() {
+        for (box_type, content) in mp4_boxes(data) {
+            match &box_type {
+                b"moov" | b"trak" | b"mdia" | b"minf" | b"stbl" => pending.push(content),
+                b"stsd" if content.len() > 8 => {
+                    // version/flags(4) + entry_count(4)
+                    for (entry_type, entry) in mp4_boxes(&content[8..]) {
+                        match &entry_type {
+                            b"avc1" | b"avc3" | b"hev1" | b"hvc1" if entry.len() >= 28 => {
+                                let mut video_info = VideoInfo::new();
+                                let codec = if entry_type.starts_with(b"avc") {
+                                    "h264"
+                                } else {
+                                    "hevc"
+                                };
+                                video_info.set_codec(codec.to_string());
+                                let width = u16::from_be_bytes([entry[24], entry[25]]) as i32;
+                                let height = u16::from_be_bytes([entry[26], entry[27]]) as i32;
+                                video_info.set_width(width);
+                                video_info.set_height(height);
+                                video_info.quality_type = get_video_resolution(height as u32);
+                                video_list.push(video_info);
+                            }
+                            b"mp4a" | b"ac-3" | b"ec-3" | b"Opus" if entry.len() >= 18 => {
+                                if audio.is_some() {
+                                    continue;
+                                }
+                                let codec = match &entry_type {
+                                    b"mp4a" => "aac",
+                                    b"ac-3" => "ac3",
+                                    b"ec-3" => "eac3",
+                                    _ => "opus",
+                                };
+                                let mut audio_info = AudioInfo::new();
+                                audio_info.set_codec(codec.to_string());
+                                audio_info
+                                    .set_channels(u16::from_be_bytes([entry[16], entry[17]]) as i32);
+                                audio = Some(audio_info);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    build_ffmpeg_info(video_list, audio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按位写入，用于构造测试数据
+    struct BitWriter {
+        data: Vec<u8>,
+        bits: usize,
+    }
+
+    impl BitWriter {
+        fn new() -> BitWriter {
+            BitWriter {
+                data: vec![],
+                bits: 0,
+            }
+        }
+
+        fn write_bits(&mut self, value: u32, n: usize) {
+            for i in (0..n).rev() {
+                if self.bits % 8 == 0 {
+                    self.data.push(0);
+                }
+                let bit = ((value >> i) & 1) as u8;
+                *self.data.last_mut().unwrap() |= bit << (7 - self.bits % 8);
+                self.bits += 1;
+            }
+        }
+
+        fn write_ue(&mut self, value: u32) {
+            let v = value + 1;
+            let len = 32 - v.leading_zeros() as usize;
+            self.write_bits(0, len - 1);
+            self.write_bits(v, len);
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            self.write_bits(1, 1); // rbsp_stop_one_bit
+            self.data
+        }
+    }
+
+    /// 构造 1920x1080 的 H.264 High profile SPS
+    fn h264_sps_1080p() -> Vec<u8> {
+        h264_sps(119, 67)
+    }
+
+    /// 构造 H.264 High profile SPS，裁剪掉底部8行
+    ///
+    /// # 参数
+    /// * `width_mbs_minus1` - 宽度的宏块数减1
+    /// * `height_map_units_minus1` - 高度的宏块数减1
+    fn h264_sps(width_mbs_minus1: u32, height_map_units_minus1: u32) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_bits(100, 8); // profile_idc
+        w.write_bits(0, 8);
+        w.write_bits(40, 8); // level_idc
+        w.write_ue(0); // sps_id
+        w.write_ue(1); // chroma_format_idc
+        w.write_ue(0);
+        w.write_ue(0);
+        w.write_bits(0, 1);
+        w.write_bits(0, 1); // seq_scaling_matrix_present_flag
+        w.write_ue(0); // log2_max_frame_num_minus4
+        w.write_ue(0); // pic_order_cnt_type
+        w.write_ue(2);
+        w.write_ue(4); // max_num_ref_frames
+        w.write_bits(0, 1);
+        w.write_ue(width_mbs_minus1); // 120 * 16 = 1920
+        w.write_ue(height_map_units_minus1); // 68 * 16 = 1088
+        w.write_bits(1, 1); // frame_mbs_only_flag
+        w.write_bits(1, 1);
+        w.write_bits(1, 1); // frame_cropping_flag
+        w.write_ue(0);
+        w.write_ue(0);
+        w.write_ue(0);
+        w.write_ue(4); // 1088 - 4 * 2 = 1080
+        w.write_bits(0, 1); // vui_parameters_present_flag
+        let mut nal = vec![0x67];
+        nal.extend(w.finish());
+        nal
+    }
+
+    /// 构造 1280x720 的 H.265 SPS
+    fn h265_sps_720p() -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_bits(0, 4);
+        w.write_bits(0, 3); // max_sub_layers_minus1
+        w.write_bits(1, 1);
+        for _ in 0..12 {
+            w.write_bits(0, 8); // profile_tier_level
+        }
+        w.write_ue(0);
+        w.write_ue(1); // chroma_format_idc
+        w.write_ue(1280);
+        w.write_ue(720);
+        w.write_bits(0, 1);
+        let mut nal = vec![0x42, 0x01];
+        nal.extend(w.finish());
+        nal
+    }
+
+    fn ts_packet(pid: u16, pusi: bool, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![
+            TS_SYNC_BYTE,
+            ((pusi as u8) << 6) | (pid >> 8) as u8,
+            pid as u8,
+            0x10,
+        ];
+        packet.extend_from_slice(payload);
+        packet.resize(TS_PACKET_SIZE, 0xff);
+        packet
+    }
+
+    fn psi_payload(table_id: u8, body: &[u8]) -> Vec<u8> {
+        // pointer_field + table_id + section_length + 5字节表头 + 内容 + CRC
+        let length = 5 + body.len() + 4;
+        let mut payload = vec![0, table_id, 0xb0 | (length >> 8) as u8, length as u8];
+        payload.extend_from_slice(&[0, 1, 0xc1, 0, 0]);
+        payload.extend_from_slice(body);
+        payload.extend_from_slice(&[0, 0, 0, 0]);
+        payload
+    }
+
+    fn pes_payload(stream_id: u8, es: &[u8]) -> Vec<u8> {
+        let mut payload = vec![0, 0, 1, stream_id, 0, 0, 0x80, 0, 0];
+        payload.extend_from_slice(es);
+        payload
+    }
+
+    #[test]
+    fn parse_sps() {
+        assert_eq!(parse_h264_sps(&h264_sps_1080p()), Some((1920, 1080)));
+        assert_eq!(parse_h265_sps(&h265_sps_720p()), Some((1280, 720)));
+    }
+
+    #[test]
+    fn parse_oversized_sps() {
+        // 宽高乘以16后超出u32
+        assert_eq!(parse_h264_sps(&h264_sps(u32::MAX - 1, 67)), None);
+        assert_eq!(parse_h264_sps(&h264_sps(119, 0x1000_0000)), None);
+        // 裁剪的偏移量相加后超出u32
+        let mut w = BitWriter::new();
+        w.write_bits(0, 4);
+        w.write_bits(0, 3);
+        w.write_bits(1, 1);
+        for _ in 0..12 {
+            w.write_bits(0, 8);
+        }
+        w.write_ue(0);
+        w.write_ue(1);
+        w.write_ue(1280);
+        w.write_ue(720);
+        w.write_bits(1, 1); // conformance_window_flag
+        for _ in 0..4 {
+            w.write_ue(u32::MAX - 1);
+        }
+        let mut nal = vec![0x42, 0x01];
+        nal.extend(w.finish());
+        assert_eq!(parse_h265_sps(&nal), None);
+    }
+
+    #[test]
+    fn skip_malformed_mp4_box() {
+        let mut data = 16u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"ftyp");
+        data.extend_from_slice(b"iso6\0\0\0\0");
+        // size为1时使用64位大小，接近u64::MAX时与偏移量相加会溢出
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"moov");
+        data.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+        data.extend_from_slice(&[0u8; 16]);
+        let boxes = mp4_boxes(&data);
+        assert_eq!(boxes.len(), 1);
+        assert_eq!(&boxes[0].0, b"ftyp");
+        assert!(parse_fmp4_init(&data).is_none());
+    }
+
+    #[test]
+    fn parse_ts_segment() {
+        let mut ts = vec![];
+        // PAT: 节目1的PMT PID为 0x1000
+        ts.extend(ts_packet(0, true, &psi_payload(0, &[0, 1, 0xf0, 0x00])));
+        // PMT: PCR PID + 视频(H.264, 0x100) + 音频(AAC, 0x101)
+        ts.extend(ts_packet(
+            0x1000,
+            true,
+            &psi_payload(
+                2,
+                &[
+                    0xe1, 0x00, 0xf0, 0x00, 0x1b, 0xe1, 0x00, 0xf0, 0x00, 0x0f, 0xe1, 0x01, 0xf0,
+                    0x00,
+                ],
+            ),
+        ));
+        let mut video_es = vec![0, 0, 0, 1];
+        video_es.extend(h264_sps_1080p());
+        video_es.extend_from_slice(&[0, 0, 0, 1, 0x68, 0xce]);
+        ts.extend(ts_packet(0x100, true, &pes_payload(0xe0, &video_es)));
+        // ADTS: AAC LC, 48kHz, 2声道
+        let adts = [0xff, 0xf1, 0x4c, 0x80, 0x01, 0x7f, 0xfc, 0x21];
+        ts.extend(ts_packet(0x101, true, &pes_payload(0xc0, &adts)));
+
+        let info = parse_ts(&ts).unwrap();
+        assert_eq!(info.video.len(), 1);
+        assert_eq!(info.video[0].codec, "h264");
+        assert_eq!(info.video[0].width, 1920);
+        assert_eq!(info.video[0].height, 1080);
+        let audio = info.audio.unwrap();
+        assert_eq!(audio.codec, "aac");
+        assert_eq!(audio.channels, 2);
+
+        assert!(parse_ts(b"not a ts").is_none());
+    }
+
+    #[test]
+    fn parse_fmp4() {
+        fn mp4_box(box_type: &[u8], content: &[u8]) -> Vec<u8> {
+            let mut b = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+            b.extend_from_slice(box_type);
+            b.extend_from_slice(content);
+            b
+        }
+        let mut avc1 = vec![0u8; 78];
+        avc1[24..26].copy_from_slice(&1280u16.to_be_bytes());
+        avc1[26..28].copy_from_slice(&720u16.to_be_bytes());
+        let mut mp4a = vec![0u8; 28];
+        mp4a[16..18].copy_from_slice(&6u16.to_be_bytes());
+        let mut stsd_content = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd_content.extend(mp4_box(b"avc1", &avc1));
+        let video_trak = mp4_box(
+            b"trak",
+            &mp4_box(
+                b"mdia",
+                &mp4_box(b"minf", &mp4_box(b"stbl", &mp4_box(b"stsd", &stsd_content))),
+            ),
+        );
+        let mut stsd_content = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd_content.extend(mp4_box(b"mp4a", &mp4a));
+        let audio_trak = mp4_box(
+            b"trak",
+            &mp4_box(
+                b"mdia",
+                &mp4_box(b"minf", &mp4_box(b"stbl", &mp4_box(b"stsd", &stsd_content))),
+            ),
+        );
+        let mut init = mp4_box(b"ftyp", b"iso6\0\0\0\0");
+        init.extend(mp4_box(b"moov", &[video_trak, audio_trak].concat()));
+
+        let info = parse_fmp4_init(&init).unwrap();
+        assert_eq!(info.video[0].codec, "h264");
+        assert_eq!(info.video[0].height, 720);
+        let audio = info.audio.unwrap();
+        assert_eq!(audio.codec, "aac");
+        assert_eq!(audio.channels, 6);
+    }
+}
@@ -1,8 +1,23 @@
 // 导出子模块
+pub mod cache; // 检查结果缓存
+pub mod cancel; // 任务取消
 pub mod check; // 检查相关功能
 pub mod cmd;
+pub mod dash; // MPEG-DASH清单解析
+pub mod diff; // 任务运行结果对比
+pub mod dns; // 域名解析和缓存
+pub mod fingerprint; // 内容指纹和占位画面识别
+pub mod history; // 检查历史记录和在线率统计
+pub mod hls; // HLS播放列表解析
 pub mod m3u;
+pub mod media; // TS/fMP4音视频信息解析
+pub mod probe; // RTSP/RTMP/UDP协议探测
+pub mod progress; // 任务检查进度
+pub mod proxy; // http和socks5代理
+pub mod redirect; // 跳转链和地址过期时间
 pub mod task; // 任务管理相关功能
+pub mod timing; // HTTP请求各阶段耗时
+pub mod tls; // http客户端TLS配置
 pub mod translate;
 pub mod util;
 // 通用工具函数
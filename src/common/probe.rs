@@ -0,0 +1,590 @@
+use crate::common::{CheckError, FailureReason};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use url::Url;
+
+const RTSP_DEFAULT_PORT: u16 = 554;
+const RTMP_DEFAULT_PORT: u16 = 1935;
+const RTMP_HANDSHAKE_SIZE: usize = 1536;
+const RTMP_DEFAULT_CHUNK_SIZE: usize = 128;
+const RTMP_MSG_SET_CHUNK_SIZE: u8 = 1;
+const RTMP_MSG_AMF3_COMMAND: u8 = 17;
+const RTMP_MSG_AMF0_COMMAND: u8 = 20;
+const RTSP_MAX_HEADER_SIZE: usize = 64 * 1024;
+
+/// 判断是否为支持原生探测的协议
+pub fn is_probe_scheme(scheme: &str) -> bool {
+    matches!(scheme, "rtsp" | "rtmp" | "udp" | "rtp")
+}
+
+/// 按协议探测非HTTP源
+///
+/// # 参数
+/// * `url` - 源地址，支持 rtsp/rtmp/udp/rtp
+/// * `timeout` - 超时时间（毫秒）
+///
+/// # 返回值
+/// * `Result<i64, CheckError>` - 成功返回首次响应的耗时（毫秒）
+pub async fn probe_stream(url: &Url, timeout: u64) -> Result<i64, CheckError> {
+    let start = Instant::now();
+    let probe = async {
+        match url.scheme() {
+            "rtsp" => probe_rtsp(url).await,
+            "rtmp" => probe_rtmp(url).await,
+            "udp" | "rtp" => probe_udp(url).await,
+            scheme => Err(CheckError::new(
+                FailureReason::UnsupportedScheme,
+                format!("scheme {} not support", scheme),
+            )),
+        }
+    };
+    match tokio::time::timeout(Duration::from_millis(timeout), probe).await {
+        Ok(Ok(first_response)) => Ok((first_response - start).as_millis() as i64),
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(CheckError::new(
+            FailureReason::Timeout,
+            format!("probe {} timeout", url),
+        )),
+    }
+}
+
+/// 将网络错误转换为检查错误
+pub fn io_error(e: io::Error) -> CheckError {
+    let msg = e.to_string();
+    let reason = if msg.contains("failed to lookup address") {
+        FailureReason::Dns
+    } else {
+        match e.kind() {
+            io::ErrorKind::TimedOut => FailureReason::Timeout,
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::AddrNotAvailable => FailureReason::Connect,
+            _ => FailureReason::Other,
+        }
+    };
+    CheckError::new(reason, msg)
+}
+
+fn handshake_error(msg: String) -> CheckError {
+    CheckError::new(FailureReason::Handshake, msg)
+}
+
+async fn connect(url: &Url, default_port: u16) -> Result<TcpStream, CheckError> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| CheckError::new(FailureReason::InvalidUrl, "url has no host".to_string()))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = url.port().unwrap_or(default_port);
+    TcpStream::connect((host.as_str(), port))
+        .await
+        .map_err(io_error)
+}
+
+/// 读取一个RTSP响应，返回（状态码，响应头，内容）
+async fn read_rtsp_response(
+    stream: &mut TcpStream,
+) -> Result<(u16, HashMap<String, String>, Vec<u8>), CheckError> {
+    let mut buf = vec![];
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if buf.len() > RTSP_MAX_HEADER_SIZE {
+            return Err(handshake_error("rtsp header too large".to_string()));
+        }
+        let n = stream.read(&mut chunk).await.map_err(io_error)?;
+        if n == 0 {
+            return Err(handshake_error("rtsp connection closed".to_string()));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+    let header = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = header.lines();
+    let status_line = lines.next().unwrap_or_default();
+    if !status_line.starts_with("RTSP/") {
+        return Err(handshake_error(format!(
+            "not a rtsp response: {}",
+            status_line
+        )));
+    }
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or_else(|| handshake_error(format!("invalid status line: {}", status_line)))?;
+    let mut headers = HashMap::new();
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    let content_length = headers
+        .get("content-length")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = buf[header_end..].to_vec();
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await.map_err(io_error)?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+    Ok((status, headers, body))
+}
+
+fn rtsp_status_error(method: &str, status: u16) -> CheckError {
+    let mut err = CheckError::new(
+        FailureReason::HttpStatus,
+        format!("rtsp {} status is {}", method, status),
+    );
+    err.set_http_status(status);
+    err
+}
+
+/// RTSP探测：发送 OPTIONS 和 DESCRIBE，要求返回200并带有SDP描述
+async fn probe_rtsp(url: &Url) -> Result<Instant, CheckError> {
+    let mut stream = connect(url, RTSP_DEFAULT_PORT).await?;
+    let mut request_url = url.clone();
+    let _ = request_url.set_username("");
+    let _ = request_url.set_password(None);
+    let mut auth = String::new();
+    if !url.username().is_empty() {
+        use base64::Engine;
+        let credential = format!("{}:{}", url.username(), url.password().unwrap_or_default());
+        auth = format!(
+            "Authorization: Basic {}\r\n",
+            base64::engine::general_purpose::STANDARD.encode(credential)
+        );
+    }
+
+    let options = format!(
+        "OPTIONS {} RTSP/1.0\r\nCSeq: 1\r\nUser-Agent: iptv-checker\r\n{}\r\n",
+        request_url, auth
+    );
+    stream
+        .write_all(options.as_bytes())
+        .await
+        .map_err(io_error)?;
+    let (status, _, _) = read_rtsp_response(&mut stream).await?;
+    let first_response = Instant::now();
+    if status != 200 {
+        return Err(rtsp_status_error("OPTIONS", status));
+    }
+
+    let describe = format!(
+        "DESCRIBE {} RTSP/1.0\r\nCSeq: 2\r\nUser-Agent: iptv-checker\r\nAccept: application/sdp\r\n{}\r\n",
+        request_url, auth
+    );
+    stream
+        .write_all(describe.as_bytes())
+        .await
+        .map_err(io_error)?;
+    let (status, _, body) = read_rtsp_response(&mut stream).await?;
+    if status != 200 {
+        return Err(rtsp_status_error("DESCRIBE", status));
+    }
+    let sdp = String::from_utf8_lossy(&body);
+    if !sdp.lines().any(|l| l.starts_with("m=")) {
+        return Err(handshake_error("rtsp describe has no media".to_string()));
+    }
+    Ok(first_response)
+}
+
+fn amf0_string(buf: &mut Vec<u8>, value: &str) {
+    buf.push(0x02);
+    buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn amf0_number(buf: &mut Vec<u8>, value: f64) {
+    buf.push(0x00);
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn amf0_object_key(buf: &mut Vec<u8>, key: &str) {
+    buf.extend_from_slice(&(key.len() as u16).to_be_bytes());
+    buf.extend_from_slice(key.as_bytes());
+}
+
+/// 读取AMF0编码的字符串
+fn amf0_read_string(data: &[u8]) -> Option<String> {
+    if data.len() < 3 || data[0] != 0x02 {
+        return None;
+    }
+    let len = u16::from_be_bytes([data[1], data[2]]) as usize;
+    data.get(3..3 + len)
+        .map(|s| String::from_utf8_lossy(s).to_string())
+}
+
+/// 根据地址拆分出 RTMP 的 app 和 tcUrl，最后一段路径为流名称
+fn rtmp_app(url: &Url) -> (String, String) {
+    let path = url.path().trim_matches('/');
+    let app = match path.rsplit_once('/') {
+        Some((app, _)) => app.to_string(),
+        None => path.to_string(),
+    };
+    let mut tc_url = format!("rtmp://{}", url.host_str().unwrap_or_default());
+    if let Some(port) = url.port() {
+        tc_url.push_str(&format!(":{}", port));
+    }
+    tc_url.push_str(&format!("/{}", app));
+    (app, tc_url)
+}
+
+/// 生成 connect 命令消息体
+fn rtmp_connect_body(app: &str, tc_url: &str) -> Vec<u8> {
+    let mut body = vec![];
+    amf0_string(&mut body, "connect");
+    amf0_number(&mut body, 1.0);
+    body.push(0x03);
+    amf0_object_key(&mut body, "app");
+    amf0_string(&mut body, app);
+    amf0_object_key(&mut body, "flashVer");
+    amf0_string(&mut body, "LNX 9,0,124,2");
+    amf0_object_key(&mut body, "tcUrl");
+    amf0_string(&mut body, tc_url);
+    amf0_object_key(&mut body, "fpad");
+    body.extend_from_slice(&[0x01, 0x00]);
+    amf0_object_key(&mut body, "capabilities");
+    amf0_number(&mut body, 15.0);
+    amf0_object_key(&mut body, "audioCodecs");
+    amf0_number(&mut body, 3191.0);
+    amf0_object_key(&mut body, "videoCodecs");
+    amf0_number(&mut body, 252.0);
+    amf0_object_key(&mut body, "videoFunction");
+    amf0_number(&mut body, 1.0);
+    body.extend_from_slice(&[0x00, 0x00, 0x09]);
+    body
+}
+
+/// 将消息按块大小拆分为 chunk，使用 chunk stream 3
+fn rtmp_chunks(type_id: u8, body: &[u8], chunk_size: usize) -> Vec<u8> {
+    let mut out = vec![0x03, 0, 0, 0];
+    out.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    out.push(type_id);
+    out.extend_from_slice(&0u32.to_le_bytes());
+    for (i, part) in body.chunks(chunk_size).enumerate() {
+        if i > 0 {
+            out.push(0xC3);
+        }
+        out.extend_from_slice(part);
+    }
+    out
+}
+
+#[derive(Default)]
+struct RtmpChunkState {
+    length: usize,
+    type_id: u8,
+    extended_timestamp: bool,
+    buf: Vec<u8>,
+}
+
+/// RTMP chunk 读取器，将 chunk 组装为完整消息
+pub struct RtmpChunkReader {
+    chunk_size: usize,
+    streams: HashMap<u32, RtmpChunkState>,
+}
+
+impl RtmpChunkReader {
+    pub fn new() -> RtmpChunkReader {
+        RtmpChunkReader {
+            chunk_size: RTMP_DEFAULT_CHUNK_SIZE,
+            streams: HashMap::new(),
+        }
+    }
+
+    /// 读取一条完整消息，返回（消息类型，消息体）
+    pub async fn read_message<R: AsyncRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+    ) -> io::Result<(u8, Vec<u8>)> {
+        loop {
+            let first = reader.read_u8().await?;
+            let fmt = first >> 6;
+            let csid = match (first & 0x3f) as u32 {
+                0 => 64 + reader.read_u8().await? as u32,
+                1 => {
+                    let low = reader.read_u8().await? as u32;
+                    let high = reader.read_u8().await? as u32;
+                    64 + low + high * 256
+                }
+                id => id,
+            };
+            let state = self.streams.entry(csid).or_default();
+            if fmt <= 2 {
+                let mut header = [0u8; 11];
+                let size = [11, 7, 3][fmt as usize];
+                reader.read_exact(&mut header[..size]).await?;
+                state.extended_timestamp = header[..3] == [0xff, 0xff, 0xff];
+                if fmt <= 1 {
+                    state.length =
+                        u32::from_be_bytes([0, header[3], header[4], header[5]]) as usize;
+                    state.type_id = header[6];
+                }
+            }
+            if state.extended_timestamp {
+                reader.read_u32().await?;
+            }
+            let n = (state.length - state.buf.len()).min(self.chunk_size);
+            let mut part = vec![0u8; n];
+            reader.read_exact(&mut part).await?;
+            state.buf.extend_from_slice(&part);
+            if state.buf.len() < state.length {
+                continue;
+            }
+            let type_id = state.type_id;
+            let body = std::mem::take(&mut state.buf);
+            if type_id == RTMP_MSG_SET_CHUNK_SIZE && body.len() >= 4 {
+                let size = u32::from_be_bytes([body[0], body[1], body[2], body[3]]) & 0x7fffffff;
+                self.chunk_size = size.max(1) as usize;
+            }
+            return Ok((type_id, body));
+        }
+    }
+}
+
+/// RTMP探测：完成握手并发送 connect，要求服务端返回 _result
+async fn probe_rtmp(url: &Url) -> Result<Instant, CheckError> {
+    let mut stream = connect(url, RTMP_DEFAULT_PORT).await?;
+    // C0 + C1
+    let mut c0c1 = vec![0u8; 1 + RTMP_HANDSHAKE_SIZE];
+    c0c1[0] = 0x03;
+    for (i, b) in c0c1[9..].iter_mut().enumerate() {
+        *b = (i % 251) as u8;
+    }
+    stream.write_all(&c0c1).await.map_err(io_error)?;
+    // S0 + S1 + S2
+    let mut s0s1s2 = vec![0u8; 1 + RTMP_HANDSHAKE_SIZE * 2];
+    stream
+        .read_exact(&mut s0s1s2)
+        .await
+        .map_err(|e| handshake_error(format!("rtmp handshake error {}", e)))?;
+    let first_response = Instant::now();
+    if s0s1s2[0] != 0x03 {
+        return Err(handshake_error(format!(
+            "unsupported rtmp version {}",
+            s0s1s2[0]
+        )));
+    }
+    // C2 原样返回 S1
+    stream
+        .write_all(&s0s1s2[1..1 + RTMP_HANDSHAKE_SIZE])
+        .await
+        .map_err(io_error)?;
+
+    let (app, tc_url) = rtmp_app(url);
+    let body = rtmp_connect_body(&app, &tc_url);
+    stream
+        .write_all(&rtmp_chunks(
+            RTMP_MSG_AMF0_COMMAND,
+            &body,
+            RTMP_DEFAULT_CHUNK_SIZE,
+        ))
+        .await
+        .map_err(io_error)?;
+
+    let mut reader = RtmpChunkReader::new();
+    loop {
+        let (type_id, body) = reader
+            .read_message(&mut stream)
+            .await
+            .map_err(|e| handshake_error(format!("rtmp read error {}", e)))?;
+        let command = match type_id {
+            RTMP_MSG_AMF0_COMMAND => amf0_read_string(&body),
+            RTMP_MSG_AMF3_COMMAND if !body.is_empty() => amf0_read_string(&body[1..]),
+            _ => continue,
+        };
+        match command.as_deref() {
+            Some("_result") => return Ok(first_response),
+            Some("_error") => {
+                return Err(handshake_error(format!("rtmp connect to {} rejected", app)))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// UDP/RTP探测：监听地址（组播地址会加入组播组），收到数据即认为有效
+async fn probe_udp(url: &Url) -> Result<Instant, CheckError> {
+    let host = url
+        .host_str()
+        .unwrap_or_default()
+        .trim_start_matches('@')
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = url.port().ok_or_else(|| {
+        CheckError::new(FailureReason::InvalidUrl, "udp url has no port".to_string())
+    })?;
+    let ip: IpAddr = if host.is_empty() {
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    } else {
+        host.parse().map_err(|_| {
+            CheckError::new(
+                FailureReason::InvalidUrl,
+                format!("invalid udp host {}", host),
+            )
+        })?
+    };
+    // 组播地址需要绑定在任意地址上再加入组播组
+    let bind_ip = match ip {
+        IpAddr::V4(v4) if v4.is_multicast() => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(v6) if v6.is_multicast() => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        ip => ip,
+    };
+    let socket = UdpSocket::bind(SocketAddr::new(bind_ip, port))
+        .await
+        .map_err(io_error)?;
+    match ip {
+        IpAddr::V4(v4) if v4.is_multicast() => socket
+            .join_multicast_v4(v4, Ipv4Addr::UNSPECIFIED)
+            .map_err(io_error)?,
+        IpAddr::V6(v6) if v6.is_multicast() => {
+            socket.join_multicast_v6(&v6, 0).map_err(io_error)?
+        }
+        _ => {}
+    }
+    let mut buf = vec![0u8; 65536];
+    let n = socket.recv(&mut buf).await.map_err(io_error)?;
+    let first_response = Instant::now();
+    let data = &buf[..n];
+    // RTP版本号为2；裸UDP一般直接承载TS
+    let valid = match url.scheme() {
+        "rtp" => data.len() >= 12 && data[0] >> 6 == 2,
+        _ => data.first() == Some(&0x47) || (data.len() >= 12 && data[0] >> 6 == 2),
+    };
+    if !valid {
+        return Err(CheckError::new(
+            FailureReason::InvalidSegment,
+            format!("received {} bytes of unknown data", n),
+        ));
+    }
+    Ok(first_response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn probe_rtsp_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            for _ in 0..2 {
+                let n = socket.read(&mut buf).await.unwrap();
+                let req = String::from_utf8_lossy(&buf[..n]).to_string();
+                let resp = if req.starts_with("OPTIONS") {
+                    "RTSP/1.0 200 OK\r\nCSeq: 1\r\nPublic: DESCRIBE, SETUP, PLAY\r\n\r\n"
+                        .to_string()
+                } else {
+                    let sdp = "v=0\r\ns=test\r\nm=video 0 RTP/AVP 96\r\n";
+                    format!(
+                        "RTSP/1.0 200 OK\r\nCSeq: 2\r\nContent-Type: application/sdp\r\nContent-Length: {}\r\n\r\n{}",
+                        sdp.len(),
+                        sdp
+                    )
+                };
+                socket.write_all(resp.as_bytes()).await.unwrap();
+            }
+        });
+        let url = Url::parse(&format!("rtsp://{}/live/1", addr)).unwrap();
+        assert!(probe_stream(&url, 3000).await.is_ok());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(b"RTSP/1.0 401 Unauthorized\r\nCSeq: 1\r\n\r\n")
+                .await
+                .unwrap();
+        });
+        let url = Url::parse(&format!("rtsp://{}/live/1", addr)).unwrap();
+        let err = probe_stream(&url, 3000).await.unwrap_err();
+        assert_eq!(err.reason, FailureReason::HttpStatus);
+        assert_eq!(err.http_status, Some(401));
+    }
+
+    #[tokio::test]
+    async fn probe_rtmp_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut c0c1 = vec![0u8; 1 + RTMP_HANDSHAKE_SIZE];
+            socket.read_exact(&mut c0c1).await.unwrap();
+            let mut s0s1s2 = vec![0x03];
+            s0s1s2.extend_from_slice(&[0u8; RTMP_HANDSHAKE_SIZE]);
+            s0s1s2.extend_from_slice(&c0c1[1..]);
+            socket.write_all(&s0s1s2).await.unwrap();
+            let mut c2 = vec![0u8; RTMP_HANDSHAKE_SIZE];
+            socket.read_exact(&mut c2).await.unwrap();
+            let mut reader = RtmpChunkReader::new();
+            let (type_id, body) = reader.read_message(&mut socket).await.unwrap();
+            assert_eq!(type_id, RTMP_MSG_AMF0_COMMAND);
+            assert_eq!(amf0_read_string(&body).as_deref(), Some("connect"));
+            // 先修改块大小，再返回 _result
+            let mut out = rtmp_chunks(RTMP_MSG_SET_CHUNK_SIZE, &4096u32.to_be_bytes(), 128);
+            let mut result = vec![];
+            amf0_string(&mut result, "_result");
+            amf0_number(&mut result, 1.0);
+            result.extend_from_slice(&[0x05; 200]);
+            out.extend(rtmp_chunks(RTMP_MSG_AMF0_COMMAND, &result, 4096));
+            socket.write_all(&out).await.unwrap();
+        });
+        let url = Url::parse(&format!("rtmp://{}/live/stream", addr)).unwrap();
+        assert!(probe_stream(&url, 3000).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn probe_udp_packets() {
+        let port = {
+            let s = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            s.local_addr().unwrap().port()
+        };
+        let sender = tokio::spawn(async move {
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let mut packet = vec![0u8; 188];
+            packet[0] = 0x47;
+            for _ in 0..50 {
+                let _ = socket.send_to(&packet, ("127.0.0.1", port)).await;
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+        let url = Url::parse(&format!("udp://127.0.0.1:{}", port)).unwrap();
+        assert!(probe_stream(&url, 3000).await.is_ok());
+        sender.abort();
+
+        // 没有数据时超时
+        let url = Url::parse(&format!("rtp://127.0.0.1:{}", port)).unwrap();
+        let err = probe_stream(&url, 200).await.unwrap_err();
+        assert_eq!(err.reason, FailureReason::Timeout);
+    }
+
+    #[test]
+    fn rtmp_app_from_url() {
+        let url = Url::parse("rtmp://a.com:1936/live/stream1").unwrap();
+        assert_eq!(
+            rtmp_app(&url),
+            ("live".to_string(), "rtmp://a.com:1936/live".to_string())
+        );
+    }
+}
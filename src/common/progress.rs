@@ -0,0 +1,155 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use tokio::sync::watch;
+
+/// 任务检查进度
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TaskProgress {
+    pub task_id: String,
+    pub checked: i32, // 已检查数，包含使用缓存的源
+    pub total: i32,
+    pub success: i32,
+    pub cached: i32,         // 使用缓存结果的数量
+    pub current_url: String, // 最近检查完成的源
+    pub start_time: i64,     // 开始时间（毫秒）
+    pub eta: Option<i64>,    // 预计剩余时间（秒），还没有检查完成的源时为空
+    pub finished: bool,
+}
+
+impl TaskProgress {
+    pub fn new(task_id: String, start_time: i64) -> TaskProgress {
+        TaskProgress {
+            task_id,
+            start_time,
+            ..TaskProgress::default()
+        }
+    }
+
+    /// 设置总数，缓存的结果直接计入已检查
+    ///
+    /// # 参数
+    /// * `total` - 源总数
+    /// * `cached` - 使用缓存结果的数量
+    /// * `cached_success` - 缓存结果中成功的数量
+    /// * `now` - 当前时间（毫秒）
+    pub fn set_total(&mut self, total: i32, cached: i32, cached_success: i32, now: i64) {
+        self.total = total;
+        self.cached = cached;
+        self.checked = cached;
+        self.success = cached_success;
+        // 从开始检查时计算速度，不包含获取订阅的耗时
+        self.start_time = now;
+        self.eta = None;
+    }
+
+    /// 记录一个检查完成的源，并按已检查的速度估算剩余时间
+    ///
+    /// # 参数
+    /// * `url` - 源地址
+    /// * `success` - 是否检查成功
+    /// * `now` - 当前时间（毫秒）
+    pub fn record(&mut self, url: String, success: bool, now: i64) {
+        self.checked += 1;
+        if success {
+            self.success += 1;
+        }
+        self.current_url = url;
+        let fresh = (self.checked - self.cached) as i64;
+        let left = (self.total - self.checked).max(0) as i64;
+        self.eta = Some((now - self.start_time).max(0) * left / fresh / 1000);
+    }
+
+    pub fn finish(&mut self) {
+        self.finished = true;
+        self.eta = Some(0);
+    }
+}
+
+/// 各任务的进度，任务结束后保留最后一次的状态
+static PROGRESS: Lazy<RwLock<HashMap<String, watch::Sender<TaskProgress>>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+fn modify_progress<F>(task_id: &str, f: F)
+where
+    F: FnOnce(&mut TaskProgress),
+{
+    if let Some(sender) = PROGRESS.read().unwrap().get(task_id) {
+        sender.send_modify(f);
+    }
+}
+
+/// 开始记录任务进度，旧的订阅会在此时结束
+pub fn start_progress(task_id: &str) {
+    let (sender, _) = watch::channel(TaskProgress::new(task_id.to_string(), now_millis()));
+    PROGRESS
+        .write()
+        .unwrap()
+        .insert(task_id.to_string(), sender);
+}
+
+/// 设置任务的源总数
+pub fn set_progress_total(task_id: &str, total: i32, cached: i32, cached_success: i32) {
+    let now = now_millis();
+    modify_progress(task_id, |p| p.set_total(total, cached, cached_success, now));
+}
+
+/// 记录任务中一个检查完成的源
+pub fn update_progress(task_id: &str, url: String, success: bool) {
+    let now = now_millis();
+    modify_progress(task_id, |p| p.record(url, success, now));
+}
+
+/// 标记任务结束
+pub fn finish_progress(task_id: &str) {
+    modify_progress(task_id, |p| p.finish());
+}
+
+/// 获取任务当前进度
+pub fn get_progress(task_id: &str) -> Option<TaskProgress> {
+    PROGRESS
+        .read()
+        .unwrap()
+        .get(task_id)
+        .map(|s| s.borrow().clone())
+}
+
+/// 订阅任务进度变化
+pub fn subscribe_progress(task_id: &str) -> Option<watch::Receiver<TaskProgress>> {
+    PROGRESS.read().unwrap().get(task_id).map(|s| s.subscribe())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_eta() {
+        let mut progress = TaskProgress::new("t".to_string(), 0);
+        progress.set_total(10, 2, 1, 1000);
+        assert_eq!(progress.checked, 2);
+        assert_eq!(progress.eta, None);
+        progress.record("a".to_string(), true, 3000);
+        progress.record("b".to_string(), false, 5000);
+        assert_eq!(progress.checked, 4);
+        assert_eq!(progress.success, 2);
+        assert_eq!(progress.current_url, "b");
+        // 2个源用时4秒，剩余6个
+        assert_eq!(progress.eta, Some(12));
+
+        start_progress("progress_eta");
+        let mut rx = subscribe_progress("progress_eta").unwrap();
+        set_progress_total("progress_eta", 1, 0, 0);
+        update_progress("progress_eta", "a".to_string(), true);
+        finish_progress("progress_eta");
+        assert!(rx.has_changed().unwrap());
+        let last = rx.borrow_and_update().clone();
+        assert!(last.finished);
+        assert_eq!(get_progress("progress_eta"), Some(last));
+    }
+}
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// 2000-01-01 到 2100-01-01 之间的时间戳才认为是过期时间
+const MIN_EXPIRY_TIMESTAMP: i64 = 946684800;
+const MAX_EXPIRY_TIMESTAMP: i64 = 4102444800;
+
+/// 跳转链和地址过期时间
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RedirectInfo {
+    pub chain: Vec<String>, // 依次跳转到的地址，不包含原地址
+    pub final_url: String,  // 最终地址
+    #[serde(default)]
+    pub expires_at: Option<i64>, // 预计失效时间（秒级时间戳）
+    #[serde(default)]
+    pub expiry_param: Option<String>, // 过期时间来自哪个参数
+}
+
+impl RedirectInfo {
+    /// # 参数
+    /// * `_url` - 原地址
+    /// * `chain` - 依次跳转到的地址
+    pub fn new(_url: &str, chain: Vec<String>) -> RedirectInfo {
+        let final_url = chain.last().cloned().unwrap_or_else(|| _url.to_string());
+        // 优先使用最终地址中的过期时间，其次是跳转过程中和原地址中的
+        let expiry = chain
+            .iter()
+            .rev()
+            .map(|u| u.as_str())
+            .chain(std::iter::once(_url))
+            .find_map(parse_url_expiry);
+        let (expiry_param, expires_at) = match expiry {
+            Some((param, time)) => (Some(param), Some(time)),
+            None => (None, None),
+        };
+        RedirectInfo {
+            chain,
+            final_url,
+            expires_at,
+            expiry_param,
+        }
+    }
+
+    /// 是否在指定时间之前过期，没有过期时间时返回false
+    pub fn expires_before(&self, time: i64) -> bool {
+        self.expires_at.is_some_and(|e| e < time)
+    }
+}
+
+/// 导出时如何处理在下次任务运行前过期的源
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ExpiryAction {
+    Warn, // 保留并在 `#EXTINF` 中标注过期时间
+    Drop, // 不导出
+}
+
+fn parse_decimal(value: &str) -> Option<i64> {
+    let mut time = value.parse::<i64>().ok()?;
+    // 毫秒时间戳
+    if time > MAX_EXPIRY_TIMESTAMP {
+        time /= 1000;
+    }
+    Some(time)
+}
+
+/// 解析十进制或十六进制的时间戳，10位纯数字按十进制处理
+fn parse_decimal_or_hex(value: &str) -> Option<i64> {
+    if value.len() == 10 && value.chars().all(|c| c.is_ascii_digit()) {
+        return parse_decimal(value);
+    }
+    i64::from_str_radix(value, 16).ok()
+}
+
+/// 从地址参数中解析过期时间
+///
+/// 支持 `expires`/`x-expires`（十进制）、腾讯云 `txTime`（十六进制）、网宿 `wsABSTime`/`wsTime`
+/// 和阿里云 `auth_key`（`时间戳-随机数-uid-md5`）
+///
+/// # 参数
+/// * `_url` - 地址
+///
+/// # 返回值
+/// * `Option<(String, i64)>` - （参数名，秒级时间戳）
+pub fn parse_url_expiry(_url: &str) -> Option<(String, i64)> {
+    let parsed = Url::parse(_url).ok()?;
+    let params: Vec<(String, String)> = parsed
+        .query_pairs()
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    for name in [
+        "expires",
+        "x-expires",
+        "txtime",
+        "wsabstime",
+        "wstime",
+        "auth_key",
+    ] {
+        let (key, value) = match params.iter().find(|(k, _)| k.to_lowercase() == name) {
+            Some(param) => param,
+            None => continue,
+        };
+        let time = match name {
+            "txtime" => i64::from_str_radix(value, 16).ok(),
+            "wsabstime" | "wstime" => parse_decimal_or_hex(value),
+            "auth_key" => value.split('-').next().and_then(parse_decimal),
+            _ => parse_decimal(value),
+        };
+        if let Some(time) =
+            time.filter(|t| (MIN_EXPIRY_TIMESTAMP..MAX_EXPIRY_TIMESTAMP).contains(t))
+        {
+            return Some((key.clone(), time));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_token_expiry() {
+        assert_eq!(
+            parse_url_expiry("http://a.com/live.m3u8?wsSecret=abc&txTime=6710B0C0"),
+            Some(("txTime".to_string(), 0x6710B0C0))
+        );
+        assert_eq!(
+            parse_url_expiry("http://a.com/live.m3u8?wsSecret=abc&wsTime=1729146000"),
+            Some(("wsTime".to_string(), 1729146000))
+        );
+        assert_eq!(
+            parse_url_expiry("http://a.com/1.m3u8?auth_key=1729146000-0-0-8e1b7a8f"),
+            Some(("auth_key".to_string(), 1729146000))
+        );
+        assert_eq!(
+            parse_url_expiry("http://a.com/1.m3u8?Expires=1729146000123"),
+            Some(("Expires".to_string(), 1729146000))
+        );
+        assert_eq!(parse_url_expiry("http://a.com/1.m3u8?expires=abc"), None);
+        assert_eq!(parse_url_expiry("http://a.com/1.m3u8?id=1"), None);
+
+        let info = RedirectInfo::new(
+            "http://a.com/live/1.m3u8",
+            vec![
+                "http://b.com/gslb?id=1".to_string(),
+                "http://cdn.c.com/1.m3u8?wsSecret=x&wsABSTime=6710B0C0".to_string(),
+            ],
+        );
+        assert_eq!(
+            info.final_url,
+            "http://cdn.c.com/1.m3u8?wsSecret=x&wsABSTime=6710B0C0"
+        );
+        assert_eq!(info.expires_at, Some(0x6710B0C0));
+        assert!(info.expires_before(0x6710B0C0 + 1));
+        assert!(!info.expires_before(0x6710B0C0));
+        assert!(!RedirectInfo::new("http://a.com/1.m3u8", vec![]).expires_before(i64::MAX));
+    }
+}
@@ -1,4 +1,10 @@
-use crate::common::do_check;
+use crate::common::cancel::{self, register_cancel, remove_cancel};
+use crate::common::diff::{load_result, save_diff, RunDiff};
+use crate::common::progress::{finish_progress, start_progress};
+use crate::common::proxy::{effective_proxy, ProxyConfig};
+use crate::common::util::from_video_resolution;
+use crate::common::{do_check, CheckOptions, RetryPolicy, SearchOptions};
+use crate::config::base::get_base_config;
 use crate::config::task::{file_config, save_task_to_file};
 use crate::config::{get_now_check_task_id, save_task, save_task_config, set_now_check_id};
 use actix_web::{web, HttpResponse, Responder};
@@ -9,7 +15,7 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Error, ErrorKind, Read, Result};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -25,6 +31,10 @@ pub struct TaskInfo {
 
     pub is_running: bool,
 
+    // 最后一次运行是否被取消或超时
+    #[serde(default)]
+    pub cancelled: bool,
+
     // 任务状态
     pub task_status: TaskStatus,
 }
@@ -44,6 +54,7 @@ impl TaskInfo {
             last_run_time: 0,
             next_run_time: 0,
             is_running: false,
+            cancelled: false,
         };
     }
 
@@ -80,6 +91,9 @@ pub struct TaskContent {
     check_timeout: i32,
     // 并发数
     concurrent: i32,
+    // 同一域名并发数
+    #[serde(default)]
+    per_host_concurrent: i32,
     // 是否支持排序
     #[serde(default)]
     sort: bool,
@@ -97,13 +111,54 @@ pub struct TaskContent {
     #[serde(default)]
     not_http_skip: bool,
 
+    // 深度检查HLS
+    #[serde(default)]
+    deep_check: bool,
+
+    // 检测直播状态（直播/点播/冻结）
+    #[serde(default)]
+    liveness_check: bool,
+
+    // 记录DNS、连接、TLS、首字节、首个分片的耗时
+    #[serde(default)]
+    timing_check: bool,
+
+    // 0:不区分地址 1:分别检查IPv4/IPv6 2:分别检查每个IP
+    #[serde(default)]
+    family_check: i32,
+
+    // 失败重试策略
+    #[serde(default)]
+    retry: RetryPolicy,
+
+    // 测速下载的分片数量，0表示不测速
+    #[serde(default)]
+    throughput_segments: i32,
+
+    // 黑屏、静帧、静音检测时长（秒），0表示不检测
+    #[serde(default)]
+    content_check: i32,
+
+    // 生成内容指纹用于识别占位画面
+    #[serde(default)]
+    fingerprint_check: bool,
+
     // 视频质量
     #[serde(default)]
     video_quality: Vec<String>,
+
+    // 最长运行时间（秒），超过后取消任务并保存已检查的结果，0表示不限制
+    #[serde(default)]
+    max_duration: i32,
+
+    // 下载订阅和检查使用的代理，为空时使用全局代理
+    #[serde(default)]
+    proxy: Option<ProxyConfig>,
 }
 
 const DEFAULT_TIMEOUT: i32 = 30000;
 const DEFAULT_CONCURRENT: i32 = 30;
+const DEFAULT_PER_HOST_CONCURRENT: i32 = 4;
 
 pub fn md5_str(input: String) -> String {
     let digest = md5::compute(input);
@@ -124,12 +179,23 @@ impl TaskContent {
             check_timeout: 0,
             sort: false,
             concurrent: 1,
+            per_host_concurrent: 0,
             no_check: false,
             rename: false,
             ffmpeg_check: false,
             same_save_num: 0,
             not_http_skip: false,
+            deep_check: false,
+            liveness_check: false,
+            timing_check: false,
+            throughput_segments: 0,
+            content_check: 0,
+            fingerprint_check: false,
+            family_check: 0,
+            retry: RetryPolicy::new(),
             video_quality: vec![],
+            max_duration: 0,
+            proxy: None,
         }
     }
 
@@ -170,6 +236,9 @@ impl TaskContent {
         if self.concurrent > 0 {
             ori.set_concurrent(self.concurrent);
         }
+        if self.per_host_concurrent > 0 {
+            ori.set_per_host_concurrent(self.per_host_concurrent);
+        }
         if self.ffmpeg_check {
             ori.set_ffmpeg_check(self.ffmpeg_check);
         }
@@ -179,6 +248,30 @@ impl TaskContent {
         if self.not_http_skip {
             ori.set_not_http_skip(self.not_http_skip);
         }
+        if self.deep_check {
+            ori.set_deep_check(self.deep_check);
+        }
+        if self.liveness_check {
+            ori.set_liveness_check(self.liveness_check);
+        }
+        if self.timing_check {
+            ori.set_timing_check(self.timing_check);
+        }
+        if self.throughput_segments > 0 {
+            ori.set_throughput_segments(self.throughput_segments);
+        }
+        if self.content_check > 0 {
+            ori.set_content_check(self.content_check);
+        }
+        ori.set_fingerprint_check(self.fingerprint_check);
+        if self.family_check > 0 {
+            ori.set_family_check(self.family_check);
+        }
+        ori.set_retry(self.retry.clone());
+        if self.max_duration > 0 {
+            ori.set_max_duration(self.max_duration);
+        }
+        ori.set_proxy(self.proxy.clone().filter(|p| !p.is_empty()));
         ori.set_video_quality(self.video_quality.clone());
         ori.set_same_save_num(self.same_save_num);
         ori.set_run_type(self.run_type.clone());
@@ -229,6 +322,10 @@ impl TaskContent {
         self.concurrent = concurrent
     }
 
+    pub fn set_per_host_concurrent(&mut self, per_host_concurrent: i32) {
+        self.per_host_concurrent = per_host_concurrent
+    }
+
     pub fn set_ffmpeg_check(&mut self, ffmpeg_check: bool) {
         self.ffmpeg_check = ffmpeg_check
     }
@@ -245,6 +342,46 @@ impl TaskContent {
         self.not_http_skip = not_http_skip
     }
 
+    pub fn set_deep_check(&mut self, deep_check: bool) {
+        self.deep_check = deep_check
+    }
+
+    pub fn set_liveness_check(&mut self, liveness_check: bool) {
+        self.liveness_check = liveness_check
+    }
+
+    pub fn set_retry(&mut self, retry: RetryPolicy) {
+        self.retry = retry
+    }
+
+    pub fn set_family_check(&mut self, family_check: i32) {
+        self.family_check = family_check
+    }
+
+    pub fn set_timing_check(&mut self, timing_check: bool) {
+        self.timing_check = timing_check
+    }
+
+    pub fn set_throughput_segments(&mut self, throughput_segments: i32) {
+        self.throughput_segments = throughput_segments
+    }
+
+    pub fn set_content_check(&mut self, content_check: i32) {
+        self.content_check = content_check
+    }
+
+    pub fn set_fingerprint_check(&mut self, fingerprint_check: bool) {
+        self.fingerprint_check = fingerprint_check
+    }
+
+    pub fn set_max_duration(&mut self, max_duration: i32) {
+        self.max_duration = max_duration
+    }
+
+    pub fn set_proxy(&mut self, proxy: Option<ProxyConfig>) {
+        self.proxy = proxy
+    }
+
     pub fn set_same_save_num(&mut self, same_save_num: i32) {
         self.same_save_num = same_save_num
     }
@@ -262,6 +399,14 @@ impl TaskContent {
         }
     }
 
+    pub fn get_per_host_concurrent(self) -> i32 {
+        if self.per_host_concurrent == 0 {
+            DEFAULT_PER_HOST_CONCURRENT
+        } else {
+            self.per_host_concurrent
+        }
+    }
+
     pub fn get_http_timeout(self) -> i32 {
         let default_val = DEFAULT_TIMEOUT;
         if self.http_timeout > 0 {
@@ -376,60 +521,89 @@ impl Task {
         let _ = save_task_config();
         let urls = self.clone().original.get_urls();
         let out_out_file = self.clone().original.result_name;
-        let mut keyword_like = vec![];
-        if self.clone().original.keyword_like.len() > 0 {
-            keyword_like = self.clone().original.keyword_like
-        }
-        let mut keyword_dislike = vec![];
-        if self.clone().original.keyword_dislike.len() > 0 {
-            keyword_dislike = self.clone().original.keyword_dislike
-        }
-        let mut sort = false;
-        if self.clone().original.sort {
-            sort = self.clone().original.sort;
-        }
         let task_id = self.clone().id.clone();
         // 设置当前任务id
         set_now_check_id(Some(self.clone().id.clone()));
+        start_progress(&task_id);
+        let cancel_token = register_cancel(&task_id);
+        let max_duration = self.clone().original.max_duration;
         let http_timeout = self.clone().original.get_http_timeout();
-        let concurrent = self.clone().original.get_current();
-        let no_check = self.clone().original.no_check;
-        let check_timeout = self.clone().original.get_check_timeout();
         let rename = self.clone().original.rename;
-        let ffmpeg_check = self.clone().original.ffmpeg_check;
-        let same_save_num = self.clone().original.same_save_num;
-        let not_http_skip = self.clone().original.not_http_skip;
-        let video_quality = self.clone().original.video_quality;
-        let rename_channel_name = 0;
-        let export_file = false;
+        let original = self.clone().original;
+        let check_timeout = original.clone().get_check_timeout();
+        let concurrent = original.clone().get_current();
+        let per_host_concurrent = original.clone().get_per_host_concurrent();
+        let search = SearchOptions {
+            keyword_full_match: vec![],
+            keyword_like: original.keyword_like.clone(),
+            keyword_dislike: original.keyword_dislike.clone(),
+            ipv4: false,
+            ipv6: false,
+            exclude_url: vec![],
+            exclude_host: vec![],
+            quality: from_video_resolution(original.video_quality.clone()),
+        };
+        let opt = CheckOptions {
+            request_time: check_timeout,
+            concurrent,
+            sort: original.sort,
+            no_check: original.no_check,
+            ffmpeg_check: original.ffmpeg_check,
+            same_save_num: original.same_save_num,
+            not_http_skip: original.not_http_skip,
+            deep_check: original.deep_check,
+            per_host_concurrent,
+            liveness_check: original.liveness_check,
+            timing_check: original.timing_check,
+            throughput_segments: original.throughput_segments,
+            content_check: original.content_check,
+            fingerprint_check: original.fingerprint_check,
+            family_check: original.family_check,
+            retry: original.retry.clone(),
+            cache_ttl: get_base_config().get_cache_ttl(),
+            proxy: effective_proxy(original.proxy.clone()),
+        };
+        // 保存上次的结果用于对比
+        let previous = load_result(&out_out_file).map(|l| l.get_list());
+        let previous_time = match self.task_info.last_run_time {
+            0 => None,
+            t => Some(t as i64),
+        };
         let rt = tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()
             .unwrap();
-        rt.block_on(async {
+        let result = rt.block_on(async {
             debug!("start taskId: {}", task_id);
-            let _ = do_check(
+            // 超过最长运行时间时取消任务
+            let timer = (max_duration > 0).then(|| {
+                let task_id = task_id.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(max_duration as u64)).await;
+                    info!("task {} exceeded max duration {}s", task_id, max_duration);
+                    cancel::cancel_task(&task_id);
+                })
+            });
+            let result = do_check(
                 urls,
                 out_out_file.clone(),
                 http_timeout,
-                true,
-                check_timeout,
-                concurrent,
-                keyword_like.clone(),
-                keyword_dislike.clone(),
-                sort,
-                no_check,
+                false,
                 rename,
-                ffmpeg_check,
-                same_save_num,
-                not_http_skip,
-                video_quality,
-                export_file,
-                rename_channel_name,
+                search,
+                opt,
+                None,
             )
             .await;
+            if let Some(timer) = timer {
+                timer.abort();
+            }
             debug!("end taskId: {}", task_id);
+            result
         });
+        remove_cancel(&task_id);
+        finish_progress(&task_id);
+        self.task_info.cancelled = cancel_token.is_cancelled();
         self.task_info.task_status = TaskStatus::Pending;
         self.task_info.is_running = false;
         let now_time = now() as i32;
@@ -444,6 +618,23 @@ impl Task {
             }
         }
         self.task_info.last_run_time = now_time;
+        // 检查失败时结果文件还是上次的，不生成对比
+        match result {
+            Ok(_) => {
+                if let Some(current) = load_result(&out_out_file) {
+                    let diff = RunDiff::compare(
+                        &previous.unwrap_or_default(),
+                        &current.get_list(),
+                        previous_time,
+                        now_time as i64,
+                    );
+                    if let Err(e) = save_diff(&out_out_file, &diff) {
+                        error!("Failed to save diff of task {}: {}", self.id.clone(), e);
+                    }
+                }
+            }
+            Err(e) => error!("Failed to check task {}: {}", self.id.clone(), e),
+        }
         // 更新任务信息
         if let Err(e) = save_task(self.id.clone(), self.clone().get_task()) {
             error!("Failed to update task {}: {}", self.id.clone(), e);
@@ -496,6 +687,17 @@ impl TaskManager {
         }
     }
 
+    /// 取消正在运行的任务
+    ///
+    /// # 返回值
+    /// * `bool` - 任务没有在运行时返回false
+    pub fn cancel_task(&self, id: String) -> Result<bool> {
+        if file_config::get_task(&id)?.is_none() {
+            return Ok(false);
+        }
+        Ok(cancel::cancel_task(&id))
+    }
+
     pub fn get_task(&self, id: String) -> Option<Task> {
         if let Ok(Some(task)) = file_config::get_task(&id) {
             Some(task)
@@ -658,6 +860,30 @@ pub async fn delete_task(
     }
 }
 
+pub async fn cancel_task(
+    task_manager: web::Data<Arc<TaskManager>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let mut resp = HashMap::new();
+    match task_manager.cancel_task(path.into_inner().to_string()) {
+        Ok(true) => {
+            resp.insert("code", String::from("200"));
+            resp.insert("msg", String::from("success"));
+            HttpResponse::Ok().json(resp)
+        }
+        Ok(false) => {
+            resp.insert("code", String::from("400"));
+            resp.insert("msg", String::from("Task is not running"));
+            HttpResponse::Ok().json(resp)
+        }
+        Err(_) => {
+            resp.insert("code", String::from("500"));
+            resp.insert("msg", String::from("internal error"));
+            HttpResponse::Ok().json(resp)
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TaskListResponse {
     pub list: Vec<Task>,
@@ -0,0 +1,186 @@
+use crate::common::dns::lookup_host;
+use crate::common::probe::io_error;
+use crate::common::{CheckError, FailureReason};
+use crate::config::base::get_base_config;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use url::Url;
+
+/// 请求各阶段耗时（毫秒），没有经过的阶段为空
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TimingInfo {
+    pub dns: Option<i64>,           // 域名解析
+    pub connect: Option<i64>,       // TCP连接
+    pub tls: Option<i64>,           // TLS握手，仅https
+    pub ttfb: Option<i64>,          // 发出请求到收到首字节
+    pub first_segment: Option<i64>, // 下载第一个媒体分片
+}
+
+impl TimingInfo {
+    pub fn new() -> TimingInfo {
+        TimingInfo::default()
+    }
+
+    pub fn set_first_segment(&mut self, first_segment: Option<i64>) {
+        self.first_segment = first_segment
+    }
+}
+
+fn elapsed_ms(start: Instant) -> i64 {
+    start.elapsed().as_millis() as i64
+}
+
+/// 分阶段测量一次http(s)请求的耗时
+///
+/// 单独建立一个连接，依次记录域名解析、TCP连接、TLS握手以及首字节耗时
+///
+/// # 参数
+/// * `url` - 请求地址，仅支持 http/https
+/// * `headers` - 请求头
+/// * `timeout` - 超时时间（毫秒）
+///
+/// # 返回值
+/// * `Result<TimingInfo, CheckError>` - 各阶段耗时
+pub async fn measure_timing(
+    url: &Url,
+    headers: &BTreeMap<String, String>,
+    timeout: u64,
+) -> Result<TimingInfo, CheckError> {
+    match tokio::time::timeout(Duration::from_millis(timeout), measure(url, headers)).await {
+        Ok(res) => res,
+        Err(_) => Err(CheckError::new(
+            FailureReason::Timeout,
+            format!("timing {} timeout", url),
+        )),
+    }
+}
+
+async fn measure(url: &Url, headers: &BTreeMap<String, String>) -> Result<TimingInfo, CheckError> {
+    let https = match url.scheme() {
+        "http" => false,
+        "https" => true,
+        scheme => {
+            return Err(CheckError::new(
+                FailureReason::UnsupportedScheme,
+                format!("scheme {} not support", scheme),
+            ))
+        }
+    };
+    let host = url
+        .host_str()
+        .ok_or_else(|| CheckError::new(FailureReason::InvalidUrl, "url has no host".to_string()))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let mut timing = TimingInfo::new();
+
+    let start = Instant::now();
+    // 不使用解析缓存，记录真实的解析耗时
+    let ip = match host.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => lookup_host(&host, &get_base_config().dns).await?[0],
+    };
+    let addr = SocketAddr::new(ip, port);
+    timing.dns = Some(elapsed_ms(start));
+
+    let start = Instant::now();
+    let stream = TcpStream::connect(addr).await.map_err(io_error)?;
+    timing.connect = Some(elapsed_ms(start));
+
+    let request = build_request(url, headers);
+    if https {
+        let start = Instant::now();
+        // 与检查使用的http客户端保持一致的TLS配置
+        let tls = get_base_config().tls;
+        let connector = tls
+            .native_connector(tls.verify_url(url.as_str()))
+            .map_err(|e| CheckError::new(FailureReason::Tls, e.to_string()))?;
+        let stream = tokio_native_tls::TlsConnector::from(connector)
+            .connect(&host, stream)
+            .await
+            .map_err(|e| CheckError::new(FailureReason::Tls, e.to_string()))?;
+        timing.tls = Some(elapsed_ms(start));
+        timing.ttfb = Some(first_byte(stream, &request).await?);
+    } else {
+        timing.ttfb = Some(first_byte(stream, &request).await?);
+    }
+    Ok(timing)
+}
+
+fn build_request(url: &Url, headers: &BTreeMap<String, String>) -> String {
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+        path = format!("{}?{}", path, query);
+    }
+    let mut host = url.host_str().unwrap_or_default().to_string();
+    if let Some(port) = url.port() {
+        host = format!("{}:{}", host, port);
+    }
+    let mut request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n", path, host);
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("Connection: close\r\n\r\n");
+    request
+}
+
+async fn first_byte<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    request: &str,
+) -> Result<i64, CheckError> {
+    let start = Instant::now();
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(io_error)?;
+    let mut buf = [0u8; 1];
+    let n = stream.read(&mut buf).await.map_err(io_error)?;
+    if n == 0 {
+        return Err(CheckError::new(
+            FailureReason::Body,
+            "connection closed before response".to_string(),
+        ));
+    }
+    Ok(elapsed_ms(start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn measure_http_timing() {
+        // 本地http服务，收到请求后延迟100毫秒再响应
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let n = socket.read(&mut buf).await.unwrap();
+            let req = String::from_utf8_lossy(&buf[..n]).to_string();
+            assert!(req.starts_with("GET /live/index.m3u8?token=1 HTTP/1.1"));
+            assert!(req.contains("User-Agent: iptv-test"));
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let _ = socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await;
+        });
+        let url = Url::parse(&format!("http://{}/live/index.m3u8?token=1", addr)).unwrap();
+        let mut headers = BTreeMap::new();
+        headers.insert("User-Agent".to_string(), "iptv-test".to_string());
+        let timing = measure_timing(&url, &headers, 3000).await.unwrap();
+        assert!(timing.dns.is_some());
+        assert!(timing.connect.is_some());
+        assert!(timing.tls.is_none());
+        assert!(timing.ttfb.unwrap() >= 100);
+
+        let url = Url::parse("http://127.0.0.1:1/").unwrap();
+        let err = measure_timing(&url, &headers, 3000).await.unwrap_err();
+        assert_eq!(err.reason, FailureReason::Connect);
+    }
+}
@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Error;
+use tokio_native_tls::native_tls;
+use url::Url;
+
+/// 客户端证书
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ClientCertConfig {
+    pub cert: String, // 证书文件路径，配置了key时为PEM格式，否则为PKCS#12格式
+    #[serde(default)]
+    pub key: String, // PEM格式（PKCS#8）私钥文件路径
+    #[serde(default)]
+    pub password: String, // PKCS#12证书的密码
+}
+
+/// http客户端的TLS配置，检查、获取订阅、下载EPG和代理请求共用
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TlsConfig {
+    #[serde(default)]
+    pub strict: bool, // 校验服务端证书，默认不校验
+    #[serde(default)]
+    pub ca_bundles: Vec<String>, // 额外信任的CA证书文件，PEM格式，一个文件可以包含多个证书
+    #[serde(default)]
+    pub client_cert: Option<ClientCertConfig>, // 客户端证书
+    #[serde(default)]
+    pub insecure_hosts: Vec<String>, // 严格模式下仍不校验证书的域名，`*.example.com` 匹配所有子域名
+}
+
+impl TlsConfig {
+    pub fn new() -> TlsConfig {
+        TlsConfig {
+            strict: false,
+            ca_bundles: vec![],
+            client_cert: None,
+            insecure_hosts: vec![],
+        }
+    }
+
+    /// 域名是否在例外列表中
+    pub fn is_insecure_host(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_lowercase();
+        self.insecure_hosts.iter().any(|h| {
+            let h = h.trim().to_lowercase();
+            match h.strip_prefix("*.") {
+                Some(suffix) => host == suffix || host.ends_with(&format!(".{}", suffix)),
+                None => host == h,
+            }
+        })
+    }
+
+    /// 请求指定地址时是否校验证书
+    ///
+    /// # 参数
+    /// * `_url` - 请求地址
+    pub fn verify_url(&self, _url: &str) -> bool {
+        if !self.strict {
+            return false;
+        }
+        match Url::parse(_url)
+            .ok()
+            .and_then(|u| u.host_str().map(String::from))
+        {
+            Some(host) => !self.is_insecure_host(&host),
+            None => true,
+        }
+    }
+
+    /// 读取所有CA证书文件，返回PEM格式的证书列表
+    fn ca_pems(&self) -> Result<Vec<String>, Error> {
+        let mut pems = vec![];
+        for path in &self.ca_bundles {
+            let content = fs::read_to_string(path)
+                .map_err(|e| Error::other(format!("read ca bundle {} error {}", path, e)))?;
+            let end = "-----END CERTIFICATE-----";
+            for block in content.split_inclusive(end) {
+                if let Some(start) = block.find("-----BEGIN CERTIFICATE-----") {
+                    if block.ends_with(end) {
+                        pems.push(block[start..].to_string());
+                    }
+                }
+            }
+        }
+        Ok(pems)
+    }
+
+    /// 将TLS配置应用到reqwest客户端
+    ///
+    /// # 参数
+    /// * `builder` - 客户端构造器
+    /// * `verify` - 是否校验服务端证书，一般由 `verify_url` 得到
+    ///
+    /// # 返回值
+    /// * `Result<reqwest::ClientBuilder, Error>` - 证书文件无法读取或格式错误时返回错误
+    pub fn apply(
+        &self,
+        mut builder: reqwest::ClientBuilder,
+        verify: bool,
+    ) -> Result<reqwest::ClientBuilder, Error> {
+        builder = builder.danger_accept_invalid_certs(!verify);
+        for pem in self.ca_pems()? {
+            let cert = reqwest::Certificate::from_pem(pem.as_bytes())
+                .map_err(|e| Error::other(format!("invalid ca certificate {}", e)))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        if let Some(client_cert) = &self.client_cert {
+            let cert = fs::read(&client_cert.cert).map_err(|e| {
+                Error::other(format!("read client cert {} error {}", client_cert.cert, e))
+            })?;
+            let identity = if client_cert.key.is_empty() {
+                reqwest::Identity::from_pkcs12_der(&cert, &client_cert.password)
+            } else {
+                let key = fs::read(&client_cert.key).map_err(|e| {
+                    Error::other(format!("read client key {} error {}", client_cert.key, e))
+                })?;
+                reqwest::Identity::from_pkcs8_pem(&cert, &key)
+            }
+            .map_err(|e| Error::other(format!("invalid client certificate {}", e)))?;
+            builder = builder.identity(identity);
+        }
+        Ok(builder)
+    }
+
+    /// 创建与http客户端配置一致的TLS连接器，用于手动建立TLS连接
+    ///
+    /// # 参数
+    /// * `verify` - 是否校验服务端证书
+    pub fn native_connector(&self, verify: bool) -> Result<native_tls::TlsConnector, Error> {
+        let mut builder = native_tls::TlsConnector::builder();
+        builder.danger_accept_invalid_certs(!verify);
+        for pem in self.ca_pems()? {
+            let cert = native_tls::Certificate::from_pem(pem.as_bytes())
+                .map_err(|e| Error::other(format!("invalid ca certificate {}", e)))?;
+            builder.add_root_certificate(cert);
+        }
+        if let Some(client_cert) = &self.client_cert {
+            let cert = fs::read(&client_cert.cert)?;
+            let identity = if client_cert.key.is_empty() {
+                native_tls::Identity::from_pkcs12(&cert, &client_cert.password)
+            } else {
+                native_tls::Identity::from_pkcs8(&cert, &fs::read(&client_cert.key)?)
+            }
+            .map_err(|e| Error::other(format!("invalid client certificate {}", e)))?;
+            builder.identity(identity);
+        }
+        builder
+            .build()
+            .map_err(|e| Error::other(format!("tls connector build error {}", e)))
+    }
+
+    /// 转换为ffmpeg/ffprobe的TLS参数，只对https地址生效
+    ///
+    /// ffmpeg只支持一个CA文件和PEM格式的客户端证书，多个CA文件时只使用第一个
+    pub fn ffmpeg_args(&self, _url: &str) -> Vec<String> {
+        if !_url.starts_with("https://") || !self.verify_url(_url) {
+            return vec![];
+        }
+        let mut args = vec!["-tls_verify".to_string(), "1".to_string()];
+        if let Some(ca) = self.ca_bundles.first() {
+            args.extend(["-ca_file".to_string(), ca.clone()]);
+        }
+        if let Some(client_cert) = self.client_cert.as_ref().filter(|c| !c.key.is_empty()) {
+            args.extend([
+                "-cert_file".to_string(),
+                client_cert.cert.clone(),
+                "-key_file".to_string(),
+                client_cert.key.clone(),
+            ]);
+        }
+        args
+    }
+}
+
+/// 按Base配置中的TLS配置生成ffmpeg/ffprobe参数，需要放在输入地址之前
+///
+/// # 参数
+/// * `_url` - 输入地址
+pub fn ffmpeg_tls_args(_url: &str) -> Vec<String> {
+    crate::config::base::get_base_config().tls.ffmpeg_args(_url)
+}
+
+/// 按Base配置中的TLS配置创建访问指定地址的reqwest客户端构造器
+///
+/// # 参数
+/// * `_url` - 请求地址，用于判断是否在例外域名中
+pub fn client_builder(_url: &str) -> Result<reqwest::ClientBuilder, Error> {
+    let tls = crate::config::base::get_base_config().tls;
+    tls.apply(reqwest::Client::builder(), tls.verify_url(_url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_mode_with_insecure_hosts() {
+        let mut tls = TlsConfig::new();
+        assert!(!tls.verify_url("https://a.com/1.m3u8"));
+        assert!(tls.ffmpeg_args("https://a.com/1.m3u8").is_empty());
+
+        tls.strict = true;
+        tls.insecure_hosts = vec!["*.cdn.com".to_string(), "Self-Signed.tv".to_string()];
+        assert!(tls.verify_url("https://a.com/1.m3u8"));
+        assert!(!tls.verify_url("https://cdn.com/1.m3u8"));
+        assert!(!tls.verify_url("https://edge.cdn.com:8443/1.m3u8"));
+        assert!(tls.verify_url("https://notcdn.com/1.m3u8"));
+        assert!(!tls.verify_url("https://self-signed.tv/1.m3u8"));
+        assert_eq!(
+            tls.ffmpeg_args("https://a.com/1.m3u8"),
+            vec!["-tls_verify", "1"]
+        );
+        assert!(tls.ffmpeg_args("http://a.com/1.m3u8").is_empty());
+
+        tls.ca_bundles = vec!["/nonexistent/ca.pem".to_string()];
+        assert!(tls.apply(reqwest::Client::builder(), true).is_err());
+    }
+}
@@ -85,18 +85,15 @@ pub fn trad_to_simp(input: &str) -> String {
 mod tests {
     use super::*;
 
-    use crate::utils::{get_host_ip_address, get_url_host_and_port, is_valid_ip};
+    use crate::common::dns::{resolve_host, DnsConfig};
+    use crate::utils::get_url_host_and_port;
 
-    #[test]
-    fn address() {
+    #[tokio::test]
+    async fn address() {
         let url = "http://drive.mxmy.net:8888/udp/239.3.1.188:8001";
-        let (host_str, port) = get_url_host_and_port(&url);
-        if is_valid_ip(&host_str) {
-            println!("Valid ip: {}", host_str);
-        } else {
-            let list = get_host_ip_address(&host_str, port);
-            println!("list: {:?}", list);
-        }
+        let (host_str, _) = get_url_host_and_port(url);
+        let list = resolve_host(&host_str, &DnsConfig::new()).await;
+        println!("list: {:?}", list);
     }
 
     #[test]
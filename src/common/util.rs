@@ -1,10 +1,13 @@
+use crate::common::proxy::{apply_proxy, ProxyConfig};
+use crate::common::tls::client_builder;
 use crate::common::QualityType::{
     Quality1080P, Quality240P, Quality2K, Quality360P, Quality480P, Quality4K, Quality720P,
     Quality8K, QualityUnknown,
 };
 use crate::common::{M3uExt, M3uExtend, M3uObject, M3uObjectList, QualityType};
 use crate::utils::translator_t2s;
-use reqwest::Error;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use std::io::Error;
 use url::Url;
 
 /// 获取URL的内容
@@ -12,16 +15,27 @@ use url::Url;
 /// # 参数
 /// * `_url` - 要获取内容的URL
 /// * `timeout` - 超时时间（毫秒）
+/// * `proxy` - 使用的代理
 ///
 /// # 返回值
 /// * `Result<String, Error>` - 成功返回URL内容，失败返回错误
-pub async fn get_url_body(_url: String, timeout: u64) -> Result<String, Error> {
-    let client = reqwest::Client::builder()
+pub async fn get_url_body(
+    _url: String,
+    timeout: u64,
+    proxy: Option<&ProxyConfig>,
+) -> Result<String, Error> {
+    let client = apply_proxy(client_builder(&_url)?, proxy)?
         .timeout(std::time::Duration::from_millis(timeout))
-        .danger_accept_invalid_certs(true)
         .build()
-        .unwrap();
-    client.get(_url.to_owned()).send().await?.text().await
+        .map_err(Error::other)?;
+    client
+        .get(_url.to_owned())
+        .send()
+        .await
+        .map_err(Error::other)?
+        .text()
+        .await
+        .map_err(Error::other)
 }
 
 /// 检查内容是否为M3U8格式
@@ -35,6 +49,56 @@ pub fn check_body_is_m3u8_format(_body: String) -> bool {
     _body.starts_with("#EXTM3U")
 }
 
+/// 将 `avc1.4d401f,mp4a.40.2` 这样的编码标识转换为与ffprobe一致的编码名称
+///
+/// # 参数
+/// * `codecs` - 逗号分隔的编码标识（HLS的CODECS或DASH的codecs属性）
+///
+/// # 返回值
+/// * `(Option<String>, Option<String>)` - （第一个视频编码，第一个音频编码）
+pub fn get_codec_names(codecs: &str) -> (Option<String>, Option<String>) {
+    let mut video = None;
+    let mut audio = None;
+    for codec in codecs.split(',') {
+        let prefix = codec.trim().split('.').next().unwrap_or("");
+        let (name, is_audio) = match prefix {
+            "" => continue,
+            "avc1" | "avc3" => ("h264", false),
+            "hvc1" | "hev1" => ("hevc", false),
+            "av01" => ("av1", false),
+            "vp09" => ("vp9", false),
+            "vp08" => ("vp8", false),
+            "mp4a" => ("aac", true),
+            "ac-3" => ("ac3", true),
+            "ec-3" => ("eac3", true),
+            "opus" | "Opus" => ("opus", true),
+            other => (other, false),
+        };
+        if is_audio {
+            audio.get_or_insert(name.to_string());
+        } else {
+            video.get_or_insert(name.to_string());
+        }
+    }
+    (video, audio)
+}
+
+/// 检查内容是否为MPEG-DASH清单（根元素为MPD的XML）
+///
+/// # 参数
+/// * `_body` - 要检查的内容
+///
+/// # 返回值
+/// * `bool` - 如果是MPD格式返回true，否则返回false
+pub fn check_body_is_dash_format(_body: &str) -> bool {
+    let body = _body.trim_start_matches('\u{feff}').trim_start();
+    if !body.starts_with('<') {
+        return false;
+    }
+    let head: String = body.chars().take(2048).collect();
+    head.contains("<MPD") || head.contains(":MPD")
+}
+
 /// 检查字符串是否为IPv6格式
 ///
 /// # 参数
@@ -96,6 +160,8 @@ pub fn parse_normal_str(_body: String) -> M3uObjectList {
     let mut index = 1;
     let mut one_m3u = Vec::new();
     let mut save_mode = false;
+    // 写在 #EXTINF 之前的 #EXTVLCOPT 选项
+    let mut pending_opts = Vec::new();
 
     // 逐行解析M3U内容
     for x in exp_line {
@@ -105,6 +171,9 @@ pub fn parse_normal_str(_body: String) -> M3uObjectList {
             if x.starts_with("#EXTINF") {
                 save_mode = true;
                 one_m3u.push(x);
+                one_m3u.append(&mut pending_opts);
+            } else if !save_mode && x.starts_with("#EXTVLCOPT") {
+                pending_opts.push(x);
             } else {
                 if save_mode {
                     one_m3u.push(x);
@@ -150,6 +219,72 @@ fn parse_m3u_header(_str: String) -> M3uExt {
     }
 }
 
+/// `normalize_header_name` 能识别出的请求头名称
+const KNOWN_HEADER_NAMES: [&str; 4] = ["User-Agent", "Referer", "Origin", "Cookie"];
+
+/// 地址后附加请求头时，值中需要编码的字符
+const URL_HEADER_VALUE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'%')
+    .add(b'&')
+    .add(b'=')
+    .add(b'|');
+
+/// 将播放器的请求头选项名转换为http请求头名称
+///
+/// # 参数
+/// * `name` - 选项名，例如 `http-user-agent`、`http-referrer`、`User-Agent`
+///
+/// # 返回值
+/// * `String` - 请求头名称，例如 `User-Agent`、`Referer`
+pub fn normalize_header_name(name: &str) -> String {
+    let name = name.trim();
+    match name.to_lowercase().as_str() {
+        "http-user-agent" | "user-agent" => "User-Agent".to_string(),
+        "http-referrer" | "http-referer" | "referrer" | "referer" => "Referer".to_string(),
+        "http-origin" | "origin" => "Origin".to_string(),
+        "http-cookie" | "cookie" => "Cookie".to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// 拆分地址后面以 `|` 附加的请求头，例如 `http://a.com/1.m3u8|User-Agent=xxx&Referer=yyy`，
+/// 请求头的值按百分号编码解码
+///
+/// # 参数
+/// * `_url` - 可能带请求头后缀的地址
+///
+/// # 返回值
+/// * `(String, Vec<(String, String)>)` - 去掉后缀的地址以及请求头
+pub fn split_url_headers(_url: &str) -> (String, Vec<(String, String)>) {
+    let (url, suffix) = match _url.split_once('|') {
+        Some((url, suffix)) => (url, suffix),
+        None => return (_url.to_string(), vec![]),
+    };
+    let mut headers = vec![];
+    for pair in suffix.split('&') {
+        if let Some((name, value)) = pair.split_once('=') {
+            if !name.trim().is_empty() {
+                let value = percent_decode_str(value.trim()).decode_utf8_lossy();
+                headers.push((normalize_header_name(name), value.to_string()));
+            }
+        }
+    }
+    (url.trim().to_string(), headers)
+}
+
+/// 编码附加在地址后面的请求头的值，和 `split_url_headers` 的解码对应
+///
+/// # 参数
+/// * `value` - 请求头的值
+///
+/// # 返回值
+/// * `String` - 编码后的值
+pub fn encode_url_header_value(value: &str) -> String {
+    utf8_percent_encode(value, URL_HEADER_VALUE).to_string()
+}
+
 /// 解析单个M3U条目
 ///
 /// # 参数
@@ -159,7 +294,7 @@ fn parse_m3u_header(_str: String) -> M3uExt {
 /// # 返回值
 /// * `Option<M3uObject>` - 解析后的M3U对象
 fn parse_one_m3u(_arr: Vec<&str>, index: i32) -> Option<M3uObject> {
-    let url = _arr.last().unwrap().to_string();
+    let (url, url_headers) = split_url_headers(_arr.last().unwrap());
     if _arr.first().unwrap().starts_with("#EXTINF") && is_url(url.to_owned()) {
         let mut extend = M3uExtend::new();
 
@@ -185,6 +320,33 @@ fn parse_one_m3u(_arr: Vec<&str>, index: i32) -> Option<M3uObject> {
         if let Some(user_agent) = _arr.first().unwrap().split("user-agent=\"").nth(1) {
             extend.set_user_agent(user_agent.split('"').next().unwrap().to_owned())
         }
+        if let Some(referrer) = _arr.first().unwrap().split("http-referrer=\"").nth(1) {
+            extend.set_header(
+                "Referer".to_string(),
+                referrer.split('"').next().unwrap().to_owned(),
+            )
+        }
+        // 解析 #EXTVLCOPT:http-user-agent=xxx 等播放器选项
+        for line in _arr.iter() {
+            if let Some(opt) = line.trim().strip_prefix("#EXTVLCOPT:") {
+                if let Some((name, value)) = opt.split_once('=') {
+                    let name = normalize_header_name(name);
+                    if name == "User-Agent" {
+                        extend.set_user_agent(value.trim().to_owned());
+                    } else if KNOWN_HEADER_NAMES.contains(&name.as_str()) {
+                        // 只保留能识别为请求头的选项
+                        extend.set_header(name, value.trim().to_owned());
+                    }
+                }
+            }
+        }
+        for (name, value) in url_headers {
+            if name == "User-Agent" {
+                extend.set_user_agent(value);
+            } else {
+                extend.set_header(name, value);
+            }
+        }
 
         // 解析频道名称
         let exp: Vec<&str> = _arr.first().unwrap().split(',').collect();
@@ -235,6 +397,10 @@ pub fn parse_quota_str(_body: String) -> M3uObjectList {
         match one_c.get(1) {
             Some(purl) => {
                 url = purl.replace('\r', "").to_string();
+                // 请求头后缀中可能包含逗号，取第一个逗号之后的全部内容
+                if url.contains('|') {
+                    url = x.split_once(',').unwrap().1.replace('\r', "");
+                }
             }
             None => {}
         }
@@ -248,6 +414,15 @@ pub fn parse_quota_str(_body: String) -> M3uObjectList {
                 let mut m3u_obj = M3uObject::new();
                 let mut extend = M3uExtend::new();
                 extend.set_group_title(now_group.clone());
+                let (_url, url_headers) = split_url_headers(&url);
+                url = _url;
+                for (name, value) in url_headers {
+                    if name == "User-Agent" {
+                        extend.set_user_agent(value);
+                    } else {
+                        extend.set_header(name, value);
+                    }
+                }
                 m3u_obj.set_extend(extend);
                 m3u_obj.set_index(index);
                 m3u_obj.set_url(url.to_string());
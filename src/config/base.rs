@@ -1,3 +1,7 @@
+use crate::common::cache::CacheTtl;
+use crate::common::dns::DnsConfig;
+use crate::common::proxy::ProxyConfig;
+use crate::common::tls::TlsConfig;
 use crate::r#const::constant::{BASE_CONFIG_JSON_CONTENT, BASE_JSON};
 use crate::utils::file_exists;
 use once_cell::sync::Lazy;
@@ -13,6 +17,16 @@ pub struct BaseConfig {
     pub host: String,
     pub replace_string: bool,
     pub remote_url2local_images: bool,
+    #[serde(default)]
+    pub cache_success_ttl: i64, // 检查成功结果的缓存时间（秒），0表示不缓存
+    #[serde(default)]
+    pub cache_failure_ttl: i64, // 检查失败结果的缓存时间（秒），0表示不缓存
+    #[serde(default)]
+    pub tls: TlsConfig, // 所有http请求共用的TLS配置
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>, // 全局代理，任务和搜索源没有单独配置代理时使用
+    #[serde(default)]
+    pub dns: DnsConfig, // 域名解析配置
 }
 
 impl BaseConfig {
@@ -21,8 +35,18 @@ impl BaseConfig {
             host: String::default(),
             replace_string: false,
             remote_url2local_images: false,
+            cache_success_ttl: 0,
+            cache_failure_ttl: 0,
+            tls: TlsConfig::new(),
+            proxy: None,
+            dns: DnsConfig::new(),
         }
     }
+
+    /// 检查结果缓存有效期
+    pub fn get_cache_ttl(&self) -> CacheTtl {
+        CacheTtl::new(self.cache_success_ttl, self.cache_failure_ttl)
+    }
 }
 
 static BASE_MAP: Lazy<RwLock<BaseConfig>> = Lazy::new(|| {
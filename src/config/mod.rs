@@ -21,6 +21,9 @@ pub mod base;
 // EPG配置模块
 pub mod epg;
 
+// 占位画面配置模块
+pub mod placeholder;
+
 // 导出file_config模块中的所有内容
 pub use task::file_config::*;
 
@@ -34,6 +37,7 @@ pub use task::file_config::*;
 /// - core/logos.json - Logo配置
 /// - core/base.json - Base配置
 /// - core/epg.json - EPG配置
+/// - core/placeholder.json - 占位画面配置
 pub fn init_all_config_files() {
     task::init_task_config();
     search::create_search_file();
@@ -42,6 +46,7 @@ pub fn init_all_config_files() {
     logos::create_logos_file();
     base::create_base_file();
     epg::create_epg_file();
+    placeholder::create_placeholder_file();
     // 兼容：若 logos.json 有 host 且 base.json 为空，则同步到 base.json
     base::sync_host_from_logos_if_needed();
 }
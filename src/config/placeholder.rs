@@ -0,0 +1,113 @@
+use crate::common::fingerprint::Fingerprint;
+use crate::r#const::constant::{PLACEHOLDER_CONFIG_JSON_CONTENT, PLACEHOLDER_JSON};
+use crate::utils::file_exists;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::RwLock;
+
+/// 黑名单中已知的占位画面
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaceholderItem {
+    #[serde(flatten)]
+    pub fingerprint: Fingerprint,
+    #[serde(default)]
+    pub note: String, // 备注，例如画面内容
+}
+
+/// 占位画面配置结构体
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaceholderConfig {
+    #[serde(default = "PlaceholderConfig::default_min_channels")]
+    pub min_channels: usize, // 内容相同的源中不同的频道名称达到该数量时认为是占位画面
+    #[serde(default)]
+    pub list: Vec<PlaceholderItem>, // 占位画面黑名单
+}
+
+impl PlaceholderConfig {
+    fn new() -> Self {
+        PlaceholderConfig {
+            min_channels: PlaceholderConfig::default_min_channels(),
+            list: Vec::new(),
+        }
+    }
+
+    fn default_min_channels() -> usize {
+        3
+    }
+}
+
+static PLACEHOLDER_MAP: Lazy<RwLock<PlaceholderConfig>> = Lazy::new(|| {
+    let p = Path::new(get_placeholder_file_path().as_str()).to_owned();
+    RwLock::new(read_placeholder_json(&p))
+});
+
+pub fn get_placeholder_config() -> PlaceholderConfig {
+    PLACEHOLDER_MAP.read().unwrap().clone()
+}
+
+fn read_placeholder_json<P: AsRef<Path>>(path: P) -> PlaceholderConfig {
+    match fs::read_to_string(&path) {
+        Ok(s) => {
+            if s.trim().is_empty() {
+                eprintln!("placeholder: file {:?} is empty", path.as_ref());
+                return PlaceholderConfig::new();
+            }
+            match serde_json::from_str::<PlaceholderConfig>(&s) {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!(
+                        "placeholder: failed to parse JSON from {:?}: {}",
+                        path.as_ref(),
+                        e
+                    );
+                    PlaceholderConfig::new()
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("placeholder: failed to read {:?}: {}", path.as_ref(), e);
+            PlaceholderConfig::new()
+        }
+    }
+}
+
+/// 更新整个占位画面配置
+pub fn update_placeholder_config(config: PlaceholderConfig) -> Result<(), String> {
+    let mut map = PLACEHOLDER_MAP.write().unwrap();
+    *map = config;
+    drop(map);
+    save_placeholder_to_file()
+}
+
+/// 保存占位画面配置到文件
+pub fn save_placeholder_to_file() -> Result<(), String> {
+    let map = PLACEHOLDER_MAP.read().unwrap();
+    let json = serde_json::to_string_pretty(&*map)
+        .map_err(|e| format!("Failed to serialize placeholder config: {}", e))?;
+    fs::write(get_placeholder_file_path(), json)
+        .map_err(|e| format!("Failed to write placeholder config: {}", e))?;
+    Ok(())
+}
+
+pub fn get_placeholder_file_path() -> String {
+    format!("./{}", PLACEHOLDER_JSON)
+}
+
+pub fn create_placeholder_file() {
+    let path = get_placeholder_file_path();
+    if !file_exists(&path) {
+        if let Some(parent) = Path::new(path.as_str()).parent() {
+            fs::create_dir_all(parent)
+                .unwrap_or_else(|_| panic!("Failed to create directory: {:?}", parent));
+        }
+        let mut fd =
+            fs::File::create(&path).unwrap_or_else(|_| panic!("Failed to create file: {}", path));
+        fd.write_all(PLACEHOLDER_CONFIG_JSON_CONTENT.as_bytes())
+            .unwrap_or_else(|_| panic!("Failed to write file: {}", path));
+        fd.flush()
+            .unwrap_or_else(|_| panic!("Failed to flush file: {}", path));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::common::proxy::ProxyConfig;
 use crate::r#const::constant::{SEARCH_CONFIG_JSON_CONTENT, SEARCH_JSON};
 use crate::utils::file_exists;
 use once_cell::sync::Lazy;
@@ -20,6 +21,8 @@ pub struct SearchSource {
     pub include_files: Vec<String>,
     pub extensions: Vec<String>,
     pub parse_type: String,
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>, // 下载这个搜索源使用的代理，为空时使用全局代理
 }
 
 impl SearchConfig {
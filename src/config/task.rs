@@ -1,4 +1,4 @@
-use crate::common::task::Task;
+use crate::common::task::{Task, TaskStatus};
 use crate::r#const::constant::{TASK_DATA, TASK_JSON};
 use crate::utils::file_exists;
 use log::{error, info};
@@ -115,6 +115,35 @@ pub fn init_task_config() {
     }
 }
 
+/// 清理进程异常退出后遗留的运行状态（当前任务ID和任务的运行中标记）
+///
+/// # 返回值
+/// * `Vec<String>` - 被重置的任务ID
+pub fn reconcile_running_state() -> Vec<String> {
+    let mut ids = vec![];
+    {
+        let mut config = TASK_MAP.write().unwrap();
+        if let Some(now) = config.now.take() {
+            ids.push(now);
+        }
+        for (id, task) in config.task.iter_mut() {
+            if task.task_info.is_running || task.task_info.task_status == TaskStatus::InProgress {
+                task.task_info.is_running = false;
+                task.task_info.task_status = TaskStatus::Pending;
+                if !ids.contains(id) {
+                    ids.push(id.clone());
+                }
+            }
+        }
+    }
+    if !ids.is_empty() {
+        if let Err(e) = save_task_to_file() {
+            error!("Failed to save task config: {}", e);
+        }
+    }
+    ids
+}
+
 /// 配置管理模块
 pub mod file_config {
     use std::collections::HashMap;
@@ -181,4 +210,15 @@ pub mod file_config {
         let config = TASK_MAP.read().unwrap();
         Ok(config.task.clone())
     }
+
+    /// 获取输出指定结果文件的任务下次运行时间（秒级时间戳），没有计划运行时返回None
+    pub fn get_next_run_time(result_name: &str) -> Option<i64> {
+        let config = TASK_MAP.read().unwrap();
+        config
+            .task
+            .values()
+            .find(|t| t.original.get_result_name() == result_name)
+            .map(|t| t.task_info.next_run_time as i64)
+            .filter(|t| *t > 0)
+    }
 }
@@ -5,6 +5,7 @@ pub static SEARCH_JSON: &str = "static/core/search.json";
 pub static FAVOURITE_JSON: &str = "static/core/favourite.json";
 pub static BASE_JSON: &str = "static/core/base.json";
 pub static EPG_JSON: &str = "static/core/epg.json";
+pub static PLACEHOLDER_JSON: &str = "static/core/placeholder.json";
 pub static TRANSLATE_FILE: &str = "./src/assets/translate.txt";
 
 pub static STATIC_FOLDER: &str = "./static/";
@@ -16,6 +17,10 @@ pub static INPUT_LIVE_FOLDER: &str = "./static/live/";
 pub static OUTPUT_FOLDER: &str = "./static/output/";
 pub static OUTPUT_THUMBNAIL_FOLDER: &str = "./static/thumbnail/";
 pub static LOGS_FOLDER: &str = "./static/logs/";
+pub static HISTORY_FOLDER: &str = "./static/history/";
+pub static HISTORY_FILE: &str = "./static/history/checks.log";
+pub static CACHE_FOLDER: &str = "./static/cache/";
+pub static CHECK_CACHE_FILE: &str = "./static/cache/check.json";
 pub static LOGOS_FOLDER: &str = "/static/core/logos/";
 
 pub static FAVOURITE_CONFIG_JSON_CONTENT: &str = r#"{
@@ -94,7 +99,26 @@ pub static LOGOS_CONFIG_JSON_CONTENT: &str = r#"{
 pub static BASE_CONFIG_JSON_CONTENT: &str = r#"{
   "host": "",
   "replace_string": false,
-  "remote_url2local_images": false
+  "remote_url2local_images": false,
+  "cache_success_ttl": 0,
+  "cache_failure_ttl": 0,
+  "tls": {
+    "strict": false,
+    "ca_bundles": [],
+    "client_cert": null,
+    "insecure_hosts": []
+  },
+  "proxy": null,
+  "dns": {
+    "servers": [],
+    "cache_ttl": 300,
+    "concurrent": 32
+  }
+}"#;
+
+pub static PLACEHOLDER_CONFIG_JSON_CONTENT: &str = r#"{
+  "min_channels": 3,
+  "list": []
 }"#;
 
 pub static EPG_CONFIG_JSON_CONTENT: &str = r#"{
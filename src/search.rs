@@ -1,4 +1,6 @@
 use crate::common::m3u::m3u::list_str2obj;
+use crate::common::proxy::{apply_proxy, effective_proxy, ProxyConfig};
+use crate::common::tls::client_builder;
 use crate::common::{M3uObject, M3uObjectList, SearchParams};
 use crate::config;
 use crate::config::epg::get_epg_config;
@@ -196,8 +198,9 @@ async fn fetch_github_home_page(
     url: String,
     include_files: Vec<String>,
     valid_extensions: Vec<String>,
+    proxy: Option<&ProxyConfig>,
 ) -> Vec<GithubInfo> {
-    match get_url_body(url.clone()).await {
+    match get_url_body(url.clone(), proxy).await {
         Ok(body) => parse_github_home_page_body_to_m3u_link(
             &body,
             include_files.clone(),
@@ -215,8 +218,9 @@ async fn fetch_github_sub_page(
     url: String,
     include_files: Vec<String>,
     valid_extensions: Vec<String>,
+    proxy: Option<&ProxyConfig>,
 ) -> Vec<GithubInfo> {
-    match get_url_body(url.clone()).await {
+    match get_url_body(url.clone(), proxy).await {
         Ok(body) => parse_github_sub_page_body_to_m3u_link(
             &body,
             include_files.clone(),
@@ -327,8 +331,8 @@ fn epg_list_to_m3u_file(list: Vec<EpgM3u8Info>, file_name: String) -> Result<(),
     Ok(())
 }
 
-async fn fetch_epg_page(url: String) -> Vec<EpgM3u8Info> {
-    match get_url_body(url.clone()).await {
+async fn fetch_epg_page(url: String, proxy: Option<&ProxyConfig>) -> Vec<EpgM3u8Info> {
+    match get_url_body(url.clone(), proxy).await {
         Ok(body) => epg_live_stream_html_parse(body.as_str()),
         Err(e) => {
             error!("Failed to fetch epg page {}: {}", url, e);
@@ -337,11 +341,10 @@ async fn fetch_epg_page(url: String) -> Vec<EpgM3u8Info> {
     }
 }
 
-async fn get_url_body(_url: String) -> Result<String, Error> {
-    let client = reqwest::Client::builder()
-        .danger_accept_invalid_certs(true)
+async fn get_url_body(_url: String, proxy: Option<&ProxyConfig>) -> Result<String, Error> {
+    let client = apply_proxy(client_builder(&_url)?, proxy)?
         .build()
-        .unwrap();
+        .map_err(Error::other)?;
     let resp = client.get(_url.to_owned()).send().await;
     return match resp {
         Ok(res) => {
@@ -411,9 +414,8 @@ fn filename_from_epg_url(url_str: &str) -> String {
 
 /// 下载 URL 返回字节
 async fn get_url_bytes(url: &str) -> Result<Vec<u8>, Error> {
-    let client = reqwest::Client::builder()
+    let client = client_builder(url)?
         .timeout(std::time::Duration::from_secs(60))
-        .danger_accept_invalid_certs(true)
         .build()
         .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
     let bytes = client
@@ -641,6 +643,11 @@ pub async fn init_epg_data() -> EpgParseData {
     epg_data
 }
 
+/// 搜索源文件使用的代理，key为文件路径，保存在搜索文件夹旁边，生成缩略图时使用
+fn get_search_proxy_file() -> String {
+    format!("{}.proxy.json", get_search_folder().trim_end_matches('/'))
+}
+
 pub async fn init_search_data() -> Result<(), Error> {
     let exists = check_search_data_exists().expect("Failed to check search data");
     if exists {
@@ -652,15 +659,25 @@ pub async fn init_search_data() -> Result<(), Error> {
     // 下线相关文件
     let config = read_search_configs().await.expect("配置获取失败");
     let mut i = 0;
+    let mut file_proxies: HashMap<String, ProxyConfig> = HashMap::new();
     for fetch_values in config.source {
+        // 搜索源没有单独配置代理时使用全局代理
+        let proxy = effective_proxy(fetch_values.proxy.clone());
+        let mut save_proxy = |save_name: &String| {
+            if let Some(p) = &proxy {
+                file_proxies.insert(save_name.clone(), p.clone());
+            }
+        };
+        let proxy = proxy.as_ref();
         match fetch_values.parse_type {
             SearchConfigParseType::EpgLivestreamPageUrl => {
                 if fetch_values.urls.len() > 0 {
                     let fetch_url = fetch_values.urls[0].clone();
-                    let list = fetch_epg_page(fetch_url.clone()).await;
+                    let list = fetch_epg_page(fetch_url.clone(), proxy).await;
                     // 将list转换成m3u文件
-                    let save_status =
-                        epg_list_to_m3u_file(list, format!("{}100-{}.m3u", get_search_folder(), i));
+                    let save_name = format!("{}100-{}.m3u", get_search_folder(), i);
+                    save_proxy(&save_name);
+                    let save_status = epg_list_to_m3u_file(list, save_name);
                     match save_status {
                         Ok(()) => {
                             info!("{} file save success", fetch_url.clone());
@@ -678,17 +695,17 @@ pub async fn init_search_data() -> Result<(), Error> {
                         url.clone(),
                         fetch_values.include_files.clone(),
                         fetch_values.extensions.clone(),
+                        proxy,
                     )
                     .await;
                     debug!("{:?}", m3u_and_txt_files);
                     // 下载m3u文件
                     for _url in m3u_and_txt_files {
                         i += 1;
-                        save_data(
-                            _url.download_url.clone(),
-                            format!("{}200-{}{}", get_search_folder(), i, _url.extension),
-                        )
-                        .await;
+                        let save_name =
+                            format!("{}200-{}{}", get_search_folder(), i, _url.extension);
+                        save_proxy(&save_name);
+                        save_data(_url.download_url.clone(), save_name, proxy).await;
                     }
                 }
             }
@@ -698,17 +715,17 @@ pub async fn init_search_data() -> Result<(), Error> {
                         url.clone(),
                         fetch_values.include_files.clone(),
                         fetch_values.extensions.clone(),
+                        proxy,
                     )
                     .await;
                     debug!("{:?}", m3u_and_txt_files);
                     // 下载m3u文件
                     for _url in m3u_and_txt_files {
                         i += 1;
-                        save_data(
-                            _url.download_url.clone(),
-                            format!("{}300-{}{}", get_search_folder(), i, _url.extension),
-                        )
-                        .await;
+                        let save_name =
+                            format!("{}300-{}{}", get_search_folder(), i, _url.extension);
+                        save_proxy(&save_name);
+                        save_data(_url.download_url.clone(), save_name, proxy).await;
                     }
                 }
             }
@@ -719,21 +736,27 @@ pub async fn init_search_data() -> Result<(), Error> {
                         ext = ".txt"
                     }
                     i += 1;
-                    save_data(
-                        url.clone(),
-                        format!("{}400-{}{}", get_search_folder(), i, ext),
-                    )
-                    .await;
+                    let save_name = format!("{}400-{}{}", get_search_folder(), i, ext);
+                    save_proxy(&save_name);
+                    save_data(url.clone(), save_name, proxy).await;
                 }
             }
         }
     }
+    match serde_json::to_string(&file_proxies) {
+        Ok(content) => {
+            if let Err(e) = fs::write(get_search_proxy_file(), content) {
+                error!("Failed to save search proxy file: {}", e);
+            }
+        }
+        Err(e) => error!("Failed to save search proxy file: {}", e),
+    }
     Ok(())
 }
 
-async fn save_data(url: String, save_name: String) {
+async fn save_data(url: String, save_name: String, proxy: Option<&ProxyConfig>) {
     let fetch_url = url.clone();
-    let save_status = download_target_files(fetch_url.clone(), save_name.to_string()).await;
+    let save_status = download_target_files(fetch_url.clone(), save_name.to_string(), proxy).await;
     match save_status {
         Ok(_) => {
             info!("{} file save success", fetch_url.clone());
@@ -744,8 +767,12 @@ async fn save_data(url: String, save_name: String) {
     }
 }
 
-async fn download_target_files(_url: String, save_path: String) -> Result<(), Error> {
-    match crate::common::util::get_url_body(_url.clone(), 20000).await {
+async fn download_target_files(
+    _url: String,
+    save_path: String,
+    proxy: Option<&ProxyConfig>,
+) -> Result<(), Error> {
+    match crate::common::util::get_url_body(_url.clone(), 20000, proxy).await {
         Ok(contents) => {
             // 创建一个新文件，如果文件已存在，则会覆盖它
             let mut file = fs::File::create(save_path)?;
@@ -774,6 +801,7 @@ pub struct SearchSource {
     pub include_files: Vec<String>,
     pub extensions: Vec<String>,
     pub parse_type: SearchConfigParseType,
+    pub proxy: Option<ProxyConfig>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -857,6 +885,7 @@ pub async fn read_search_configs() -> Result<SearchConfigs, Box<dyn std::error::
                 error!("Invalid parse type: {}", source.parse_type);
                 SearchConfigParseType::RawSources
             }),
+            proxy: source.proxy,
         });
     }
 
@@ -866,12 +895,12 @@ pub async fn read_search_configs() -> Result<SearchConfigs, Box<dyn std::error::
 pub async fn do_search(search_params: SearchParams) -> Result<(), Error> {
     match init_search_data().await {
         Ok(()) => {
-            let mut m3u_data = load_m3u_data()?;
+            let (mut m3u_data, proxies) = load_m3u_data()?;
             m3u_data.t2s();
             m3u_data.search(search_params.search_options).await;
             if search_params.thumbnail {
                 m3u_data
-                    .generate_thumbnail(search_params.concurrent, search_params.timeout)
+                    .generate_thumbnail(search_params.concurrent, search_params.timeout, &proxies)
                     .await;
             }
             info!("list2 --- total {}", m3u_data.get_list_len());
@@ -891,11 +920,16 @@ pub async fn do_search(search_params: SearchParams) -> Result<(), Error> {
 pub fn clear_search_folder() -> std::io::Result<()> {
     let p = get_search_folder();
     fs::remove_dir_all(p.clone())?;
+    let _ = fs::remove_file(get_search_proxy_file());
     info!("Deleted directory: {}", &p.clone());
     Ok(())
 }
 
-fn load_m3u_data() -> std::io::Result<M3uObjectList> {
+/// 读取搜索文件夹中的所有源
+///
+/// # 返回值
+/// * `(M3uObjectList, HashMap<String, ProxyConfig>)` - 所有源，以及源地址对应的搜索源代理
+fn load_m3u_data() -> std::io::Result<(M3uObjectList, HashMap<String, ProxyConfig>)> {
     let p = get_search_folder();
     let path = std::path::Path::new(&p);
     let mut file_names = vec![];
@@ -909,13 +943,24 @@ fn load_m3u_data() -> std::io::Result<M3uObjectList> {
             }
         }
     }
+    let file_proxies: HashMap<String, ProxyConfig> = fs::read_to_string(get_search_proxy_file())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
     let mut contents = vec![];
+    let mut proxies = HashMap::new();
     for file_name in file_names {
-        let content = fs::read_to_string(format!("{}{}", p, file_name.clone()))?;
+        let file_path = format!("{}{}", p, file_name.clone());
+        let content = fs::read_to_string(&file_path)?;
+        if let Some(proxy) = file_proxies.get(&file_path) {
+            for item in list_str2obj(vec![content.clone()], true).get_list() {
+                proxies.insert(item.get_url(), proxy.clone());
+            }
+        }
         contents.push(content)
     }
     let result = list_str2obj(contents, true);
-    Ok(result)
+    Ok((result, proxies))
 }
 pub fn generate_channel_thumbnail_folder_name() -> String {
     // 获取当前本地时间
@@ -1034,7 +1079,7 @@ mod tests {
     #[tokio::test]
     async fn test_init_epg_data() {
         // 先下载文件
-        let data = init_epg_data();
+        let data = init_epg_data().await;
         data.download().await.unwrap();
         // 获取下载的文件
 
@@ -6,7 +6,7 @@ use regex::Regex;
 use std::fs;
 use std::fs::File;
 use std::io::{Error, ErrorKind, Read};
-use std::net::{IpAddr, Ipv4Addr, ToSocketAddrs};
+use std::net::{IpAddr, Ipv4Addr};
 use std::process::Command;
 use url::Url;
 
@@ -94,20 +94,6 @@ pub fn translator_t2s(str: &str) -> String {
     str.to_string()
 }
 
-pub fn is_valid_ip(host: &str) -> bool {
-    // 尝试解析为 IPv4
-    if host.parse::<Ipv4Addr>().is_ok() {
-        return true;
-    }
-
-    // 尝试解析为 IPv6
-    if is_ipv6(host) {
-        return true;
-    }
-
-    false
-}
-
 pub fn is_ipv4(host: &str) -> bool {
     host.parse::<Ipv4Addr>().is_ok()
 }
@@ -136,31 +122,12 @@ pub fn get_url_host_and_port(url_str: &str) -> (String, u16) {
     ("".to_string(), 0)
 }
 
-pub fn get_host_ip_address(domain: &str, port: u16) -> Vec<String> {
-    let mut list = vec![];
-
-    // 使用`ToSocketAddrs`将域名解析为SocketAddr
-    match (domain, port).to_socket_addrs() {
-        Ok(addrs) => {
-            for addr in addrs {
-                list.push(addr.ip().to_string());
-            }
-        }
-        Err(e) => {
-            println!("Failed to resolve domain: {}", e);
-        }
-    }
-    list
-}
-
 /// 测试模块
 #[cfg(test)]
 mod tests {
+    use crate::common::dns::{resolve_host, DnsConfig};
     use crate::common::util::parse_normal_str;
-    use crate::utils::{
-        get_host_ip_address, get_url_host_and_port, is_valid_ip, remove_other_char,
-        translator_t2s,
-    };
+    use crate::utils::{get_url_host_and_port, remove_other_char, translator_t2s};
     use std::net::IpAddr;
     use url::Url;
 
@@ -182,6 +149,39 @@ https://stream1.freetv.fun/8c0a0439191a3ba401897378bc2226a7edda1e571cb356ac7c7f4
         }
     }
 
+    #[test]
+    fn parse_data_headers() {
+        let mut data = parse_normal_str(String::from(
+            r#"#EXTM3U
+#EXTINF:-1 tvg-name="CCTV1" http-referrer="http://a.com/",cctv1
+#EXTVLCOPT:http-user-agent=Mozilla/5.0 (X11, Linux)
+http://a.com/1.m3u8
+#EXTVLCOPT:http-referrer=http://b.com/
+#EXTINF:-1 tvg-name="CCTV2",cctv2
+http://b.com/2.m3u8|User-Agent=okhttp&X-Token=abc&Cookie=a%3D1%26b%3D2"#,
+        ))
+        .get_list();
+        let first = data[0].get_headers();
+        assert_eq!(first.get("User-Agent").unwrap(), "Mozilla/5.0 (X11, Linux)");
+        assert_eq!(first.get("Referer").unwrap(), "http://a.com/");
+        assert_eq!(data[1].get_url(), "http://b.com/2.m3u8");
+        let second = data[1].get_headers();
+        assert_eq!(second.get("User-Agent").unwrap(), "okhttp");
+        assert_eq!(second.get("Referer").unwrap(), "http://b.com/");
+        assert_eq!(second.get("X-Token").unwrap(), "abc");
+        assert_eq!(second.get("Cookie").unwrap(), "a=1&b=2");
+
+        data[1].generate_raw();
+        let raw = data[1].get_raw();
+        assert!(raw.contains("#EXTVLCOPT:http-user-agent=okhttp\n"));
+        assert!(raw.contains("#EXTVLCOPT:http-referrer=http://b.com/\n"));
+        assert!(raw.ends_with("http://b.com/2.m3u8|Cookie=a%3D1%26b%3D2&X-Token=abc"));
+
+        // 重新解析生成的内容得到相同的请求头
+        let reparsed = parse_normal_str(format!("#EXTM3U\n{}", raw)).get_list();
+        assert_eq!(reparsed[0].get_headers(), second);
+    }
+
     #[tokio::test]
     async fn test_ipv6() {
         // let str = "[2409:8087:1e01:23::10]";
@@ -189,12 +189,10 @@ https://stream1.freetv.fun/8c0a0439191a3ba401897378bc2226a7edda1e571cb356ac7c7f4
         // println!("{}", data);
         let str = "http://[2409:8087:1e01:23::10]:8112/000000001000/1000000001000018602/1.m3u8?channel-id=ystenlive&Contentid=1000000001000018602&livemode=1&stbId=m";
 
-        let (host_str, port) = get_url_host_and_port(&str);
-        if is_valid_ip(&host_str) {
-            println!("222---{}", host_str);
-        } else {
-            println!("11----{:?}", get_host_ip_address(&host_str, port))
-        }
+        let (host_str, _) = get_url_host_and_port(str);
+        // IP地址直接返回，不会发起解析
+        let list = resolve_host(&host_str, &DnsConfig::new()).await.unwrap();
+        println!("222---{:?}", list);
     }
 
     #[tokio::test]
@@ -1,16 +1,28 @@
-use crate::common::{check, QualityType};
+use crate::common::cache::{cache_key, get_cached, save_cached, CacheEntry};
+use crate::common::diff::{load_diff, load_result};
+use crate::common::fingerprint::cluster_fingerprints;
+use crate::common::history::{load_stats_async, UrlStats};
+use crate::common::hls::{LiveStatus, VariantPin};
+use crate::common::progress::{get_progress, subscribe_progress};
+use crate::common::proxy::effective_proxy;
+use crate::common::redirect::ExpiryAction;
+use crate::common::{
+    check, CheckOptions, ContentIssue, ExportFilter, FailureReason, OtherStatus, QualityType, RetryPolicy,
+};
 use crate::common::task::{
-    add_task, delete_task, get_file_contents, list_task, run_task, update_task, TaskManager,
+    add_task, cancel_task, delete_task, get_file_contents, list_task, run_task, update_task, TaskManager,
 };
+use crate::common::tls::client_builder;
 use crate::common::translate::init_from_default_file;
 use crate::common::M3uObjectList;
 use crate::common::M3uExt;
 use crate::config::favourite::FavouriteConfig;
 use crate::config::favourite::{get_favourite_map, reload_favourite_map};
 use crate::config::search::SearchConfig;
-use crate::config::{get_all_tasks, get_task};
+use crate::config::task::reconcile_running_state;
+use crate::config::{get_all_tasks, get_next_run_time, get_task};
 use crate::r#const::constant::{
-    INPUT_SEARCH_FOLDER, LOGOS_FOLDER, OUTPUT_FOLDER, STATIC_FOLDER, UPLOAD_FOLDER,
+    HISTORY_FILE, INPUT_SEARCH_FOLDER, LOGOS_FOLDER, OUTPUT_FOLDER, STATIC_FOLDER, UPLOAD_FOLDER,
 };
 use crate::search;
 use crate::search::{init_epg_data, init_search_data};
@@ -25,7 +37,7 @@ use chrono::Local;
 use clokwerk::{Scheduler, TimeUnits};
 use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::fs::File;
 use std::io::{Read, Write};
@@ -63,6 +75,10 @@ async fn update_global_config(req: web::Json<UpdateGlobalConfigRequest>) -> impl
 struct CheckUrlIsAvailableRequest {
     url: String,
     timeout: Option<i32>,
+    liveness: Option<bool>,  // 是否检测直播状态
+    throughput: Option<i32>, // 测速下载的分片数量
+    content: Option<i32>,    // 黑屏、静帧、静音检测时长（秒）
+    timing: Option<bool>,    // 是否记录DNS、连接、TLS、首字节、首个分片的耗时
 }
 
 /// 检查URL是否可用的API端点
@@ -72,8 +88,67 @@ async fn check_url_is_available(req: web::Query<CheckUrlIsAvailableRequest>) ->
     if let Some(i) = req.timeout {
         timeout = i;
     }
-    let res =
-        check::check::check_link_is_valid(req.url.to_owned(), timeout as u64, true, false).await;
+    let proxy = effective_proxy(None);
+    let client = match check::check::new_check_client(timeout as u64, proxy.as_ref()) {
+        Ok(client) => client,
+        Err(e) => {
+            error!("check_url_is_available error {}", e);
+            return HttpResponse::InternalServerError().body("{\"msg\":\"internal error\"}");
+        }
+    };
+    let opt = CheckOptions {
+        request_time: timeout,
+        concurrent: 1,
+        sort: false,
+        no_check: false,
+        ffmpeg_check: true,
+        same_save_num: 0,
+        not_http_skip: false,
+        deep_check: false,
+        per_host_concurrent: 0,
+        liveness_check: req.liveness.unwrap_or(false),
+        throughput_segments: req.throughput.unwrap_or(0),
+        content_check: req.content.unwrap_or(0),
+        fingerprint_check: false,
+        timing_check: req.timing.unwrap_or(false),
+        family_check: 0,
+        retry: RetryPolicy::new(),
+        cache_ttl: crate::config::base::get_base_config().get_cache_ttl(),
+        proxy,
+    };
+    let now = Local::now().timestamp();
+    // 缓存key包含检测直播状态、测速、检测画面等参数，只复用相同参数的检查结果
+    let key = cache_key(&req.url, &BTreeMap::new(), &opt);
+    let res = match get_cached(&key, opt.cache_ttl, now) {
+        Some(entry) if entry.success => Ok(entry.other_status.to_response()),
+        Some(entry) => Err(entry.other_status.to_check_error().into()),
+        None => {
+            let res = check::check::check_link_is_valid(
+                &client,
+                req.url.to_owned(),
+                &BTreeMap::new(),
+                &opt,
+            )
+            .await;
+            if opt.cache_ttl.is_enabled() {
+                let other_status = match &res {
+                    Ok(data) => OtherStatus::from_response(data.clone()),
+                    Err(e) => {
+                        let mut other_status = OtherStatus::new();
+                        other_status.set_failure(e);
+                        other_status
+                    }
+                };
+                let entry = CacheEntry {
+                    time: now,
+                    success: res.is_ok(),
+                    other_status,
+                };
+                save_cached(vec![(key, entry)], opt.cache_ttl, now).await;
+            }
+            res
+        }
+    };
     match res {
         Ok(mut data) => {
             if data.ffmpeg_info.is_some() {
@@ -149,11 +224,19 @@ async fn fetch_m3u_body(req: web::Query<FetchM3uBodyRequest>) -> impl Responder
     if let Some(i) = req.timeout {
         timeout = i;
     }
-    let client = reqwest::Client::builder()
-        .timeout(time::Duration::from_millis(timeout as u64))
-        .danger_accept_invalid_certs(true)
-        .build()
-        .unwrap();
+    let client = match client_builder(&req.url).and_then(|builder| {
+        builder
+            .timeout(time::Duration::from_millis(timeout as u64))
+            .build()
+            .map_err(std::io::Error::other)
+    }) {
+        Ok(client) => client,
+        Err(e) => {
+            error!("fetch_m3u_body client error {}", e);
+            return HttpResponse::InternalServerError()
+                .body("{\"msg\":\"internal error, tls config error\"}");
+        }
+    };
     let resp = client.get(req.url.to_owned()).send().await;
     match resp {
         Ok(res) => {
@@ -294,10 +377,16 @@ struct OpenUrlRequest {
 /// 获取URL内容的API端点
 #[get("/system/open-url")]
 async fn system_open_url(req: web::Query<OpenUrlRequest>) -> impl Responder {
-    let client = reqwest::Client::builder()
-        .danger_accept_invalid_certs(true)
-        .build()
-        .unwrap();
+    let client = match client_builder(&req.url)
+        .and_then(|builder| builder.build().map_err(std::io::Error::other))
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to build http client: {}", e);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"msg": format!("Failed to build http client: {}", e)}));
+        }
+    };
 
     match client.get(&req.url).send().await {
         Ok(resp) => {
@@ -722,6 +811,27 @@ async fn update_epg_config(req: web::Json<crate::config::epg::EpgConfig>) -> imp
     }
 }
 
+/// 获取占位画面配置（分组阈值和指纹黑名单）
+#[get("/system/placeholder-config")]
+async fn get_placeholder_config() -> impl Responder {
+    HttpResponse::Ok().json(crate::config::placeholder::get_placeholder_config())
+}
+
+/// 更新占位画面配置
+#[post("/system/placeholder-config")]
+async fn update_placeholder_config(
+    req: web::Json<crate::config::placeholder::PlaceholderConfig>,
+) -> impl Responder {
+    match crate::config::placeholder::update_placeholder_config(req.into_inner()) {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({"msg": "success"})),
+        Err(e) => {
+            log::error!("Failed to update placeholder config: {}", e);
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"msg": format!("Failed to save configuration: {}", e)}))
+        }
+    }
+}
+
 /// 更新Logo配置API端点
 #[post("/media/logos/update")]
 async fn update_logo_config(req: web::Json<LogoConfig>) -> impl Responder {
@@ -748,6 +858,14 @@ struct QRequest {
     i: i8,     // ip类型 默认 0 ， ipv4: 1, ipv6:2
     r: i8,     // 输出结果 默认 0 m3u, 1 text
     q: Option<i32>,
+    l: Option<i32>, // 直播状态 1 直播, 2 点播, 4 冻结，可相加
+    t: Option<f32>, // 下载速度与所需码率之比的下限，例如 1.2
+    f: Option<i8>,  // 1 排除重试后才成功的不稳定源
+    b: Option<i32>, // 排除画面异常的源 1 黑屏, 2 静帧, 4 静音，可相加
+    p: Option<i8>,  // 1 排除占位画面
+    v: Option<i8>,  // 将主播放列表固定为其中一个码率 1 最高, 2 最低，指定了q时只在这些清晰度中选择
+    k: Option<i8>,  // 1 排除加密密钥无法获取的源
+    e: Option<i8>,  // 在任务下次运行前过期的源 1 标注过期时间, 2 不导出
 }
 
 /// 获取任务内容的请求结构体
@@ -771,6 +889,7 @@ struct TaskDetailResponse {
     #[serde(flatten)]
     task: serde_json::Value,
     check_result: Vec<TaskContentItem>,
+    failure_stats: BTreeMap<FailureReason, i32>, // 各失败原因的数量
 }
 
 /// 获取任务详情API端点
@@ -802,6 +921,7 @@ pub async fn get_task_detail(
     let logos_map = crate::config::logos::get_logos_map();
     let host = crate::config::logos::get_logos_config().host;
     let mut check_result = Vec::new();
+    let mut failure_stats = BTreeMap::new();
     // 获取任务内容（复用 get_task_content 的逻辑）
     let file_name = format!(
         "{}{}.json",
@@ -819,15 +939,18 @@ pub async fn get_task_detail(
             let ser_res = serde_json::from_str::<M3uObjectList>(&json_content);
             match ser_res {
                 Ok(m3u_obj) => {
+                    failure_stats = m3u_obj.failure_reason_counts();
+                    let ip_filter = |ip_type| {
+                        let mut filter = ExportFilter::new();
+                        filter.set_ip_type(ip_type);
+                        filter
+                    };
                     let all_content_m3u = &m3u_obj.clone().export(
-                        0,
                         host.clone(),
                         logos_map.clone(),
-                        vec![],
-                        vec![],
                         only_succ,
                         0,
-                        vec![],
+                        ExportFilter::new(),
                     );
                     check_result.push(TaskContentItem {
                         content_type: "sub".to_string(),
@@ -835,14 +958,11 @@ pub async fn get_task_detail(
                         url: format!("q?c={}&i={}", task_info.original.get_result_name(), 0),
                     });
                     let v4_content_m3u = &m3u_obj.clone().export(
-                        1,
                         host.clone(),
                         logos_map.clone(),
-                        vec![],
-                        vec![],
                         only_succ,
                         0,
-                        vec![],
+                        ip_filter(1),
                     );
                     check_result.push(TaskContentItem {
                         content_type: "ipv4".to_string(),
@@ -850,14 +970,11 @@ pub async fn get_task_detail(
                         url: format!("q?c={}&i={}", task_info.original.get_result_name(), 1,),
                     });
                     let v6_content_m3u = m3u_obj.clone().export(
-                        2,
                         host.clone(),
                         logos_map.clone(),
-                        vec![],
-                        vec![],
                         only_succ,
                         0,
-                        vec![],
+                        ip_filter(2),
                     );
                     check_result.push(TaskContentItem {
                         content_type: "ipv6".to_string(),
@@ -879,6 +996,7 @@ pub async fn get_task_detail(
     let response = TaskDetailResponse {
         task: task_json,
         check_result,
+        failure_stats,
     };
 
     HttpResponse::Ok().json(response)
@@ -1273,6 +1391,31 @@ fn to_bin_prefixed(n: i32) -> String {
     format!("{:0width$b}", n, width = 8)
 }
 
+/// 将直播状态掩码转换为直播状态列表
+///
+/// # 参数
+/// * `n` - 1 直播, 2 点播, 4 冻结，可相加
+pub fn get_live_status_by_mask(n: i32) -> Vec<LiveStatus> {
+    let mut list = vec![];
+    for (bit, status) in [(1, LiveStatus::Live), (2, LiveStatus::Vod), (4, LiveStatus::Frozen)] {
+        if n & bit != 0 {
+            list.push(status);
+        }
+    }
+    list
+}
+
+/// 根据掩码获取画面异常类型，1 黑屏, 2 静帧, 4 静音
+pub fn get_content_issue_by_mask(n: i32) -> Vec<ContentIssue> {
+    let mut list = vec![];
+    for (bit, issue) in [(1, ContentIssue::Black), (2, ContentIssue::Frozen), (4, ContentIssue::Silent)] {
+        if n & bit != 0 {
+            list.push(issue);
+        }
+    }
+    list
+}
+
 // 1 0 1 0 1 0
 pub fn get_str_to_quality(n:i32) -> Vec<QualityType> {
     let mut qualities = Vec::new();
@@ -1325,10 +1468,36 @@ async fn q_m3u(req: web::Query<QRequest>) -> impl Responder {
 
     let logos_map = crate::config::logos::get_logos_map();
     let host = crate::config::logos::get_logos_config().host;
-    let mut qualities: Vec<QualityType> = Vec::new();
-    if req.q.is_some() {
-        qualities = get_str_to_quality(req.q.unwrap());
+    let mut filter = ExportFilter::new();
+    filter.set_ip_type(req.i as i32);
+    if let Some(q) = req.q {
+        filter.set_quality_type_list(get_str_to_quality(q));
     }
+    if let Some(l) = req.l {
+        filter.set_live_status(get_live_status_by_mask(l));
+    }
+    if let Some(t) = req.t {
+        filter.set_min_throughput_ratio(t);
+    }
+    filter.set_exclude_flaky(req.f == Some(1));
+    if let Some(b) = req.b {
+        filter.set_exclude_content(get_content_issue_by_mask(b));
+    }
+    filter.set_exclude_placeholder(req.p == Some(1));
+    filter.set_exclude_key_unreachable(req.k == Some(1));
+    filter.set_pin_variant(match req.v {
+        Some(1) => Some(VariantPin::Highest),
+        Some(2) => Some(VariantPin::Lowest),
+        _ => None,
+    });
+    let expiry_action = match req.e {
+        Some(1) => Some(ExpiryAction::Warn),
+        Some(2) => Some(ExpiryAction::Drop),
+        _ => None,
+    };
+    // 没有计划运行的任务时，只处理已经过期的源
+    let expire_before = get_next_run_time(&req.c).unwrap_or_else(|| chrono::Utc::now().timestamp());
+    filter.set_expiry_action(expiry_action, expire_before);
     return match json_file {
         Ok(mut file) => {
             let mut json_content = String::default();
@@ -1340,14 +1509,11 @@ async fn q_m3u(req: web::Query<QRequest>) -> impl Responder {
                     m3u_header.set_x_tv_url(vec![format!("{}/epg/info/{}", host, req.c)]);
                     m3u_obj.set_header(m3u_header);
                     let all_content_m3u = &m3u_obj.clone().export(
-                        req.i as i32,
                         host.clone(),
                         logos_map.clone(),
-                        vec![],
-                        vec![],
                         true,
                         req.r,
-                        qualities,
+                        filter,
                     );
                     HttpResponse::Ok()
                         .append_header((
@@ -1365,6 +1531,139 @@ async fn q_m3u(req: web::Query<QRequest>) -> impl Responder {
     };
 }
 
+/// 获取任务最近一次运行与上一次运行的对比结果（失效、恢复、新增、删除、清晰度和延迟变化）
+#[get("/tasks/diff")]
+async fn get_task_diff(
+    task_manager: web::Data<Arc<TaskManager>>,
+    req: web::Query<GetTaskContentRequest>,
+) -> impl Responder {
+    let task_info = match task_manager.get_task(req.task_id.clone()) {
+        Some(info) => info,
+        None => {
+            return HttpResponse::NotFound().json(serde_json::json!({"msg": "Task not found"}));
+        }
+    };
+    match load_diff(&task_info.original.get_result_name()) {
+        Ok(diff) => HttpResponse::Ok().json(diff),
+        Err(e) => HttpResponse::NotFound()
+            .json(serde_json::json!({"msg": format!("Failed to read diff: {}", e)})),
+    }
+}
+
+/// 进度事件的最小推送间隔，避免检查很快时刷屏
+const PROGRESS_EVENT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 以Server-Sent Events推送任务检查进度，任务结束后关闭连接
+#[get("/tasks/progress/{id}")]
+async fn get_task_progress_events(path: web::Path<String>) -> impl Responder {
+    let task_id = path.into_inner();
+    let rx = match subscribe_progress(&task_id) {
+        Some(rx) => rx,
+        None => {
+            return HttpResponse::NotFound().json(serde_json::json!({"msg": "No progress for task"}));
+        }
+    };
+    let events = futures::stream::unfold((rx, true, false), |(mut rx, first, done)| async move {
+        if done {
+            return None;
+        }
+        if !first {
+            tokio::time::sleep(PROGRESS_EVENT_INTERVAL).await;
+            // 任务重新开始时旧的发送端被替换，结束本次推送
+            if rx.changed().await.is_err() {
+                return None;
+            }
+        }
+        let progress = rx.borrow_and_update().clone();
+        let data = serde_json::to_string(&progress).unwrap_or_default();
+        let event = web::Bytes::from(format!("event: progress\ndata: {}\n\n", data));
+        Some((Ok::<_, actix_web::Error>(event), (rx, false, progress.finished)))
+    });
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "no-cache"))
+        .content_type("text/event-stream")
+        .streaming(events)
+}
+
+/// 获取任务检查进度快照，供轮询使用
+#[get("/tasks/progress/{id}/snapshot")]
+async fn get_task_progress(path: web::Path<String>) -> impl Responder {
+    match get_progress(&path.into_inner()) {
+        Some(progress) => HttpResponse::Ok().json(progress),
+        None => HttpResponse::NotFound().json(serde_json::json!({"msg": "No progress for task"})),
+    }
+}
+
+/// 获取任务检查结果中内容相同的源分组，可以将占位画面的指纹加入黑名单
+#[get("/tasks/placeholders")]
+async fn get_task_placeholders(
+    task_manager: web::Data<Arc<TaskManager>>,
+    req: web::Query<GetTaskContentRequest>,
+) -> impl Responder {
+    let task_info = match task_manager.get_task(req.task_id.clone()) {
+        Some(info) => info,
+        None => {
+            return HttpResponse::NotFound().json(serde_json::json!({"msg": "Task not found"}));
+        }
+    };
+    match load_result(&task_info.original.get_result_name()) {
+        Some(m3u_obj) => {
+            let config = crate::config::placeholder::get_placeholder_config();
+            HttpResponse::Ok().json(cluster_fingerprints(&m3u_obj.get_list(), config.min_channels))
+        }
+        None => HttpResponse::BadRequest().json(serde_json::json!({"msg": "Failed to read result"})),
+    }
+}
+
+// ============== History API ==============
+
+#[derive(Deserialize)]
+struct HistoryUrlQuery {
+    url: String,
+}
+
+/// 获取单个URL的历史统计（24小时/7天/30天在线率、平均延迟、最后在线时间）
+#[get("/history/url")]
+async fn get_url_history(query: web::Query<HistoryUrlQuery>) -> impl Responder {
+    let stats = load_stats_async(HISTORY_FILE, Local::now().timestamp()).await;
+    match stats.get(&query.url) {
+        Some(s) => HttpResponse::Ok().json(s),
+        None => HttpResponse::NotFound().json(serde_json::json!({"msg": "no history"})),
+    }
+}
+
+/// 获取任务检查结果中所有URL的历史统计
+#[get("/history/task")]
+async fn get_task_history(
+    task_manager: web::Data<Arc<TaskManager>>,
+    req: web::Query<GetTaskContentRequest>,
+) -> impl Responder {
+    let task_info = match task_manager.get_task(req.task_id.clone()) {
+        Some(info) => info,
+        None => {
+            return HttpResponse::NotFound().json(serde_json::json!({"msg": "Task not found"}));
+        }
+    };
+    let file_name = format!("{}{}.json", OUTPUT_FOLDER, task_info.original.get_result_name());
+    let m3u_obj = match fs::read_to_string(&file_name)
+        .map_err(|e| e.to_string())
+        .and_then(|c| serde_json::from_str::<M3uObjectList>(&c).map_err(|e| e.to_string()))
+    {
+        Ok(obj) => obj,
+        Err(e) => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"msg": format!("Failed to read result: {}", e)}));
+        }
+    };
+    let stats = load_stats_async(HISTORY_FILE, Local::now().timestamp()).await;
+    let list = m3u_obj
+        .get_list()
+        .iter()
+        .filter_map(|item| stats.get(&item.get_url()).cloned())
+        .collect::<Vec<UrlStats>>();
+    HttpResponse::Ok().json(list)
+}
+
 // ============== EPG API ==============
 
 #[derive(Deserialize)]
@@ -1495,6 +1794,11 @@ async fn delete_epg_cache_api() -> impl Responder {
 
 /// 启动Web服务器
 pub async fn start_web(port: u16) {
+    // 上次进程异常退出时遗留的运行状态
+    for id in reconcile_running_state() {
+        info!("reset stale running state of task {}", id);
+    }
+
     // 初始化任务管理器
     let task_manager = Arc::new(TaskManager {});
 
@@ -1609,9 +1913,17 @@ pub async fn start_web(port: u16) {
             .service(update_base_config)
             .service(get_epg_config)
             .service(update_epg_config)
+            .service(get_placeholder_config)
+            .service(update_placeholder_config)
             .service(q_m3u)
             .service(get_task_detail)
             .service(get_task_content)
+            .service(get_task_diff)
+            .service(get_task_progress_events)
+            .service(get_task_progress)
+            .service(get_task_placeholders)
+            .service(get_url_history)
+            .service(get_task_history)
             .service(actix_fs::Files::new("/static", STATIC_FOLDER.to_owned()).show_files_listing())
             .app_data(web::Data::new(scheduler.clone()))
             .app_data(web::Data::new(Arc::clone(&task_manager)))
@@ -1620,6 +1932,7 @@ pub async fn start_web(port: u16) {
             .route("/tasks/update", web::post().to(update_task))
             .route("/tasks/add", web::post().to(add_task))
             .route("/tasks/delete/{id}", web::delete().to(delete_task))
+            .route("/tasks/cancel/{id}", web::post().to(cancel_task))
             .service(actix_fs::Files::new("/", "./web/"))
             .wrap(Logger::default())
     })